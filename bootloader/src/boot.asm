@@ -11,9 +11,6 @@ _start_16:
     ; Clear interrupts
     cli
 
-    mov ax, 0xe820
-    call do_e820
-    
     ; Zero out segment registers
     xor ax, ax
     mov ds, ax
@@ -22,9 +19,15 @@ _start_16:
     mov fs, ax
     mov gs, ax
 
+    ; Stack grows down from the bootloader
+    mov sp, 0x7C00
+
     ; Clear direction bits
     cld
 
+    ; Find out which regions of memory can be used by the Kernel
+    call do_e820
+
 enable_a20:
     in al, 0x64
     test al, 0x2
@@ -75,7 +78,8 @@ prepare_load_kernel:
     ; symbolic table.
     call load_kernel
 
-    ; Jump to Kernel entry point in memory
+    ; Jump to Kernel entry point in memory, handing it the boot information
+    mov eax, BOOT_INFO
     call ebx
     
     ; This part is unreachable. In case it is reached, something went very wrong.
@@ -1,6 +1,7 @@
 ; Memory Locations
 %define KERNEL_BUFFER        0x500
 %define KERNEL_ENTRY         0x100000
+%define BOOT_INFO            0xE820
 
 ; Misc
 %define SECTOR_SIZE          512
@@ -30,4 +31,5 @@
 %define ELF_PH_SIZE          32
 %define ELF_MAGIC            0x464C457F
 
-%define E820_MAGIC_NUMBER    0x0534d4150
\ No newline at end of file
+%define E820_MAGIC_NUMBER    0x0534d4150
+%define E820_MAX_ENTRIES     32
\ No newline at end of file
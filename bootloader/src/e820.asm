@@ -1,12 +1,10 @@
-dest_addr:
-  dw 0x0
-
+; Query the memory map of the BIOS (E820). Entries are stored in the boot information, right after
+; the number of entries, so that the Kernel knows which regions of memory it can use. Expects es to
+; be zero.
 global do_e820
 do_e820:
-  ; Save destination address and setup destination register (di)
-  mov [dest_addr], ax
-  add ax, 4
-  mov di, ax
+  ; Setup destination register (di) to the first entry
+  mov di, BOOT_INFO + 4
 
   ; Clear registers
   xor ebx, ebx
@@ -19,24 +17,22 @@ do_e820:
   mov [es:di + 20], dword 1 ; Force a valid ACPI entry
   mov ecx, 24
   int 0x15
-  jc .e820f
+  jc .e820f ; Carry is set once the list is over (or if E820 is not supported)
   mov edx, E820_MAGIC_NUMBER ; BIOS compatibility procedure
 
   cmp eax, edx
   jne .e820f
-  test ebx, ebx
-  je .e820f
-  jcxz .skipent
-  cmp cl, 0x20
+  jcxz .skipent ; Skip empty entries
+  cmp bp, E820_MAX_ENTRIES
+  jae .e820f
   inc bp
   add di, 24
 
 .skipent:
-  test ebx, ebx
+  test ebx, ebx ; Zero once the last entry was returned
   jne .e820_loop
 
 .e820f:
-  xor eax, eax
-  mov ax, [dest_addr]
-  mov [eax], bp
-  ret
\ No newline at end of file
+  movzx ebp, bp
+  mov [BOOT_INFO], ebp
+  ret
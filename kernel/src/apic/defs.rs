@@ -5,10 +5,19 @@ pub const IRQ_KEYBOARD: usize = 1;
 pub const IRQ_COM1: usize = 4;
 pub const IRQ_IDE: usize = 14;
 pub const IRQ_ERROR: usize = 19;
+pub const IRQ_TLB_FLUSH: usize = 20; // Sent between CPUs (see vm::flush_page_dir)
 pub const IRQ_SPURIOUS: usize = 31;
 
 pub const INITIAL_TIMER_COUNT: usize = 100000000; // Around 100ms per tick. Change this to change clock speed
 
+// Startup of the application processors
+pub const CMOS_PORT: u16 = 0x70;
+pub const CMOS_RETURN: u16 = 0x71;
+pub const CMOS_SHUTDOWN_STATUS: u8 = 0xF;
+pub const CMOS_WARM_RESET: u8 = 0xA; // Jump to the warm reset vector after the next INIT
+pub const WARM_RESET_VECTOR: usize = 0x467; // Real mode address (segment:offset) to jump to
+pub const STARTUP_DELAY: usize = 100000; // Iterations to wait between startup interrupts
+
 /// Local APIC Registers
 
 pub mod local_apic_registers {
@@ -18,6 +27,7 @@ pub mod local_apic_registers {
     pub const EOI: usize = 0xB0 / 4;
     pub const SPURIOUS_INTERRUPT: usize = 0xF0 / 4;
     pub const UNIT_ENABLE: usize = 0x100;
+    pub const INTERRUPT_REQUEST: usize = 0x200 / 4; // 8 registers of 32 vectors, 0x10 bytes apart
     pub const ERROR_STATUS: usize = 0x280 / 4;
     pub const INTERRUPT_COMMAND_LOW: usize = 0x300 / 4;
     pub const INTERRUPT_COMMAND_HIGH: usize = 0x310 / 4;
@@ -1,10 +1,14 @@
 use core::sync::atomic::Ordering;
 
-use crate::{apic::defs::local_apic_registers as regs, apic::mp::LOCAL_APIC};
+use crate::{
+    apic::defs::local_apic_registers as regs, apic::mp::LOCAL_APIC, memory::defs::KERNEL_BASE,
+    x86::helpers::outb, P2V,
+};
 
 use super::defs::{
     local_apic_registers::{MASKED_INTERRUPT, PERFORMANCE_COUNTER, VERSION},
-    BASE_IRQ, INITIAL_TIMER_COUNT, IRQ_ERROR, IRQ_SPURIOUS, IRQ_TIMER,
+    BASE_IRQ, CMOS_PORT, CMOS_RETURN, CMOS_SHUTDOWN_STATUS, CMOS_WARM_RESET, INITIAL_TIMER_COUNT,
+    IRQ_ERROR, IRQ_SPURIOUS, IRQ_TIMER, STARTUP_DELAY, WARM_RESET_VECTOR,
 };
 
 pub fn setup_local_apic() {
@@ -61,6 +65,66 @@ pub fn local_apic_acknowledge() {
     local_apic_write(regs::EOI, 0);
 }
 
+/// Checks whether an interrupt of the given IRQ has been raised, but not yet delivered to the CPU,
+/// such as a timer tick that went by while interrupts were disabled.
+pub fn local_apic_is_pending(irq: usize) -> bool {
+    let vector = BASE_IRQ + irq;
+    let register = regs::INTERRUPT_REQUEST + (vector / 32) * 4;
+    (local_apic_read(register) & (1 << (vector % 32))) > 0
+}
+
+/// Starts the application processor with the given APIC id, following the INIT-SIPI-SIPI sequence
+/// of the MP specification. The processor starts in real mode at address, which must be page
+/// aligned and below 1MiB.
+pub fn local_apic_start_cpu(apic_id: u8, address: usize) {
+    // Older processors go through the BIOS after INIT, which then jumps to the warm reset vector
+    outb(CMOS_PORT, CMOS_SHUTDOWN_STATUS);
+    outb(CMOS_RETURN, CMOS_WARM_RESET);
+
+    let warm_reset_vector = P2V!(WARM_RESET_VECTOR) as *mut u16;
+    unsafe {
+        *warm_reset_vector = 0;
+        *warm_reset_vector.add(1) = (address >> 4) as u16;
+    }
+
+    // INIT resets the processor, which then waits for STARTUP
+    let destination = (apic_id as usize) << 24;
+    send_interrupt(
+        destination,
+        regs::INIT | regs::LEVEL | regs::ASSERT_INTERRUPT,
+    );
+    send_interrupt(
+        destination,
+        regs::INIT | regs::LEVEL | regs::DEASSERT_INTERRUPT,
+    );
+
+    // STARTUP holds the page of the entry point. It is sent twice, as the specification asks.
+    for _ in 0..2 {
+        send_interrupt(destination, regs::STARTUP | (address >> 12));
+    }
+}
+
+/// Sends the interrupt of the given IRQ to the processor with the given APIC id. Returns once the
+/// interrupt has been delivered, which does not mean that the processor has handled it yet.
+pub fn local_apic_send_irq(apic_id: u8, irq: usize) {
+    local_apic_write(regs::INTERRUPT_COMMAND_HIGH, (apic_id as usize) << 24);
+    local_apic_write(regs::INTERRUPT_COMMAND_LOW, regs::FIXED | (BASE_IRQ + irq));
+
+    while (local_apic_read(regs::INTERRUPT_COMMAND_LOW) & regs::DELIVERY_STATUS) > 0 {}
+}
+
+/// Sends an interrupt to another processor, and waits for a while once it has been delivered
+fn send_interrupt(destination: usize, command: usize) {
+    local_apic_write(regs::INTERRUPT_COMMAND_HIGH, destination);
+    local_apic_write(regs::INTERRUPT_COMMAND_LOW, command);
+
+    while (local_apic_read(regs::INTERRUPT_COMMAND_LOW) & regs::DELIVERY_STATUS) > 0 {}
+
+    for _ in 0..STARTUP_DELAY {
+        core::hint::spin_loop();
+    }
+}
+
 fn local_apic_read(register: usize) -> usize {
     let register_handle = LOCAL_APIC.load(Ordering::Relaxed) as *mut usize;
     unsafe { *register_handle.add(register) }
@@ -5,7 +5,7 @@ use crate::x86::helpers::{outb, sti};
 use self::{
     io_apic::{check_apic, setup_io_apic},
     local_apic::setup_local_apic,
-    mp::{setup_mp, IS_CPU_MAPPED},
+    mp::{get_my_cpu, setup_mp, IS_CPU_MAPPED},
 };
 
 pub mod defs;
@@ -26,6 +26,12 @@ pub fn conclude() {
     sti();
 }
 
+/// Application processors conclude once they are set up, so that the next one can be started.
+/// Interrupts are enabled later on, by the scheduler.
+pub fn conclude_cpu() {
+    get_my_cpu().started.store(true, Ordering::Release);
+}
+
 pub fn disable_pic() {
     outb(0x21, 0xFF);
     outb(0xA1, 0xFF);
@@ -6,22 +6,31 @@ use core::{
 use lazy_static::lazy_static;
 
 use crate::{
-    memory::defs::{GlobalDescriptorTable, TaskStateSegment, KERNEL_BASE, MEM_BDA},
+    memory::{
+        defs::{GlobalDescriptorTable, TaskStateSegment, KERNEL_BASE, MEM_BDA, PAGE_SIZE},
+        vm::{allocate_pages, KERNEL_PAGE_DIR},
+    },
     println,
-    scheduler::defs::process::Context,
+    scheduler::defs::{process::Context, scheduler::Scheduler},
     sync::spin_mutex::SpinMutex,
     x86::helpers::{inb, outb},
-    P2V,
+    P2V, V2P,
 };
 
-use super::{io_apic::IOApic, local_apic::get_local_apic_id};
+use super::{
+    io_apic::IOApic,
+    local_apic::{get_local_apic_id, local_apic_start_cpu},
+};
 
 pub const MAX_NUM_CPUS: usize = 8;
+pub const AP_ENTRY: usize = 0x7000; // Physical address where application processors start
+pub const AP_STACK_PAGES: usize = 4; // Size of the stack of an application processor
 pub const MP_PROCESS: u8 = 0x0;
 pub const MP_BUS: u8 = 0x01;
 pub const MP_IO_APIC: u8 = 0x2;
 pub const MP_IO_INTERRUPT: u8 = 0x3;
 pub const MP_LOCAL_INTERRUPT: u8 = 0x4;
+pub const MP_BOOTSTRAP_PROCESSOR: u8 = 0x2; // Flag of the processor that boots the Kernel
 
 pub static LOCAL_APIC: AtomicUsize = AtomicUsize::new(0);
 
@@ -40,6 +49,13 @@ pub struct CPU {
     pub gdt: SpinMutex<GlobalDescriptorTable>,
     pub number_cli: AtomicU32, // Number of CLI (Clear Interrupt) issued
     pub enable_interrupt: AtomicBool, // State of interrupts before pushcli
+    pub scheduler: SpinMutex<Scheduler>,
+    pub started: AtomicBool, // CPU is set up and about to run its scheduler
+    pub bootstrap: bool,     // CPU that booted the Kernel, and keeps the uptime
+    pub kernel_tick: AtomicBool, // A timer tick went by while in the Kernel (see irqs::timer)
+    pub page_dir: AtomicUsize, // Physical address of the loaded page directory (see load_page_dir)
+    pub tlb_flush: AtomicBool, // Another CPU asked for the TLB to be flushed (see flush_page_dir)
+    pub tlb_generation: AtomicUsize, // Number of times the TLB was flushed for other CPUs
 }
 
 unsafe impl Sync for CPU {}
@@ -106,6 +122,13 @@ impl CPU {
             taskstate: SpinMutex::new(None),
             enable_interrupt: AtomicBool::new(false),
             number_cli: AtomicU32::new(0),
+            scheduler: SpinMutex::new(Scheduler::new()),
+            started: AtomicBool::new(false),
+            bootstrap: false,
+            kernel_tick: AtomicBool::new(false),
+            page_dir: AtomicUsize::new(0),
+            tlb_flush: AtomicBool::new(false),
+            tlb_generation: AtomicUsize::new(0),
         }
     }
 
@@ -150,6 +173,58 @@ pub fn setup_cpus() -> *const Option<CPU> {
     CPUS.as_ptr()
 }
 
+extern "C" {
+    // Real mode trampoline of the application processors, linked as pure assembly
+    static _binary_ap_entry_start: usize;
+    static _binary_ap_entry_size: usize;
+
+    fn enable_paging();
+}
+
+/// Starts the application processors one at a time. Each one runs the trampoline copied to
+/// AP_ENTRY, which reads its arguments right below AP_ENTRY: the top of its stack, the Kernel entry
+/// point, the physical address of the code enabling paging, and the Kernel page directory.
+pub fn start_cpus() {
+    let my_cpu = get_my_cpu();
+    my_cpu.started.store(true, Ordering::Release);
+
+    let trampoline = P2V!(AP_ENTRY) as *mut usize;
+    unsafe {
+        let start = &_binary_ap_entry_start as *const usize as *const u8;
+        let size = &_binary_ap_entry_size as *const usize as usize;
+        core::ptr::copy_nonoverlapping(start, trampoline as *mut u8, size);
+    }
+
+    let kernel_page_dir = V2P!(KERNEL_PAGE_DIR.lock().unwrap());
+    let mut number_cpus = 1;
+
+    for cpu in CPUS.iter().flatten() {
+        if cpu.apic_id == my_cpu.apic_id {
+            continue;
+        }
+
+        let stack = allocate_pages(AP_STACK_PAGES).expect("[FATAL] No memory for the CPU stack");
+
+        unsafe {
+            *trampoline.sub(1) = stack.as_ptr() as usize + AP_STACK_PAGES * PAGE_SIZE;
+            *trampoline.sub(2) = crate::_start_ap as *const () as usize;
+            *trampoline.sub(3) = V2P!(enable_paging as *const () as usize);
+            *trampoline.sub(4) = kernel_page_dir;
+        }
+
+        local_apic_start_cpu(cpu.apic_id, AP_ENTRY);
+
+        // The arguments are shared by every processor, so the next one must wait
+        while !cpu.started.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+
+        number_cpus += 1;
+    }
+
+    println!("[KERNEL] {} CPUs Started", number_cpus);
+}
+
 pub fn setup_mp() -> [Option<CPU>; MAX_NUM_CPUS] {
     let mp_table = unsafe { find_mp_table().as_ref().unwrap() };
     let mp_conf = unsafe { find_mp_config(mp_table).unwrap() };
@@ -186,6 +261,8 @@ unsafe fn parse_config_table(config_table: *const MPConfigTable) -> [Option<CPU>
                     let process = start as *const MPProcess;
                     cpus[number_cpus] = Some(CPU::new());
                     cpus[number_cpus].as_mut().unwrap().apic_id = (*process).apic_id;
+                    cpus[number_cpus].as_mut().unwrap().bootstrap =
+                        (*process).flags & MP_BOOTSTRAP_PROCESSOR > 0;
                     number_cpus += 1;
                 }
 
@@ -0,0 +1,70 @@
+; Application processors start in real mode, at the page sent along with the STARTUP interrupt.
+; The boot processor copies this trampoline to AP_ENTRY, and leaves the arguments of the new
+; processor right below it (see start_cpus). Once in protected mode, the processor enables paging
+; like the boot processor did, moves to the higher half and enters the Kernel on its own stack.
+
+%define AP_ENTRY 0x7000
+%define KERNEL_BASE 0x80000000
+
+%define AP_STACK AP_ENTRY - 4           ; Top of the stack of the processor
+%define AP_KERNEL_ENTRY AP_ENTRY - 8    ; Kernel entry point of application processors
+%define AP_ENABLE_PAGING AP_ENTRY - 12  ; Physical address of enable_paging (see entry.asm)
+%define AP_PAGE_DIR AP_ENTRY - 16       ; Physical address of the Kernel page directory
+
+bits 16
+org AP_ENTRY
+
+ap_entry:
+    cli
+
+    ; Segments start at 0, so that addresses match the ones of the 32 bits code
+    xor ax, ax
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+
+    ; Switch to protected mode
+    lgdt [gdt_pointer]
+    mov eax, cr0
+    or eax, 1
+    mov cr0, eax
+
+    jmp dword 0x8:protected_mode
+
+bits 32
+protected_mode:
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    xor ax, ax
+    mov fs, ax
+    mov gs, ax
+
+    ; The boot page directory maps this code both here and in the higher half
+    mov esp, AP_PAGE_DIR
+    call [AP_ENABLE_PAGING]
+
+    ; The Kernel page directory only maps the higher half, so the jump must come first
+    mov eax, higher_half + KERNEL_BASE
+    jmp eax
+
+higher_half:
+    mov eax, [AP_PAGE_DIR + KERNEL_BASE]
+    mov cr3, eax
+
+    mov esp, [AP_STACK + KERNEL_BASE]
+    call [AP_KERNEL_ENTRY + KERNEL_BASE]
+
+    ; If the above instruction fails, we halt the processor.
+    hlt
+
+; Flat segments, replaced by the GDT of the processor once in the Kernel
+align 8
+gdt:
+    dq 0                  ; Null Segment
+    dq 0x00CF9A000000FFFF ; Kernel Code Segment
+    dq 0x00CF92000000FFFF ; Kernel Data Segment
+gdt_pointer:
+    dw gdt_pointer - gdt - 1
+    dd gdt
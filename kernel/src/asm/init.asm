@@ -11,9 +11,12 @@ init_start:
     mov eax, 5
     mov edi, INIT_STRING
     mov edx, 5
+    mov ecx, INIT_ARGV
+    mov ebx, 1
     int 64
 
     jmp $
 
 align 4
+INIT_ARGV: dd INIT_STRING, 5
 INIT_STRING: db "/init", 0
\ No newline at end of file
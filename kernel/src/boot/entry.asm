@@ -14,15 +14,19 @@ bits 32
 entry:
     mov esp, stack_top
 
+    ; The bootloader provides the physical address of the boot information
+    mov edi, eax
+
     lidt [zero_idt]
 
     call enable_paging
     call has_cpuid
 
-    ; Finally, time to get Rusty
+    ; Finally, time to get Rusty. The boot information is the argument of _start.
+    push edi
     extern _start
     mov eax, _start
-    jmp eax
+    call eax
 
     ; If the above instruction fails, we halt the processor.
     hlt
@@ -53,8 +57,20 @@ has_cpuid:
 
 ; Time to enable paging. Here, page size extensions are enabled to allow for bigger pages
 ; and the initial page directory is loaded into CR3. Notice this page directory will be replace
-; as soon as we can, so we can build something more flexible.
+; as soon as we can, so we can build something more flexible. Application processors run this
+; code too, from its physical address (see ap_entry.asm).
+global enable_paging
 enable_paging:
+%ifdef PAE
+    ; Enable Physical Address Extension (2MB per page, with 64 bits entries)
+    mov eax, cr4
+    or eax, 0x00000020
+    mov cr4, eax
+
+    ; With PAE, CR3 points to the Page Directory Pointer Table instead
+    mov eax, pdpt_table - 0x80000000
+    mov cr3, eax
+%else
     ; Enable Size Extension (4MB per page)
     mov eax, cr4
     or eax, 0x00000010
@@ -64,6 +80,7 @@ enable_paging:
     ; and the CPU must translate every address accessed)
     mov eax, pd_table - 0x80000000
     mov cr3, eax
+%endif
 
     mov eax, cr0    ; Read current value of CR0
     or eax, 1 << 31 ; Update 32nd bit (Paging Enable)
@@ -74,6 +91,23 @@ enable_paging:
 error:
     hlt
 
+%ifdef PAE
+align 4096 ; Ensures page alignment
+pd_table:
+    dd 0x83, 0     ; Allows access to [0, 2MiB) section of memory
+    dd 0x200083, 0 ; Allows access to [2MiB, 4MiB) section of memory
+    resq 510
+pt_end:
+
+; Both [0, 1GiB) and [KERNBASE, KERNBASE + 1GiB) are translated by the same page directory, so
+; [KERNBASE, KERNBASE + 4MiB) is mapped to physical addresses as well
+align 32
+pdpt_table:
+    dd pd_table - 0x80000000 + 1, 0
+    dd 0, 0
+    dd pd_table - 0x80000000 + 1, 0
+    dd 0, 0
+%else
 align 4096 ; Ensures page alignment
 pd_table:
     dd 0x83 ; Allows access to [0, 4MiB) section of memory
@@ -81,6 +115,7 @@ pd_table:
     dd 0x83 ; Maps [KERNBASE, KERNBASE + 4MiB) section of memory to physical addresses
     resd 511
 pt_end:
+%endif
 
 ; After the bootloading process, we need to setup a more reliable, reserved space for our Stack.
 ; This is done in this step. It will, however, later be replaced with a dynamic page allocation for
@@ -2,7 +2,13 @@ use alloc::string::{String, ToString};
 
 use crate::{
     filesystem::fs::{find_inode_by_path, read_inode_data},
-    memory::defs::{Page, KERNEL_BASE, PAGE_SIZE, PTE_P},
+    memory::{
+        defs::{
+            Page, PageTableEntry, KERNEL_BASE, NUMBER_PAGE_ENTRIES, PAGE_DIR_SHIFT, PAGE_SIZE,
+            PTE_P,
+        },
+        vm::get_page_dir_entries,
+    },
     println,
     scheduler::exec::{
         ELFHeader, ProgramHeader, ProgramHeaderType, ELF_HEADER_SIZE, ELF_MAGIC,
@@ -10,7 +16,7 @@ use crate::{
     },
     sync::cpu_cli::{pop_cli, push_cli},
     x86::helpers::{cli, load_cr3, read_cr3, sti},
-    P2V, PTE_ADDRESS, PTE_FLAGS, V2P,
+    P2V, PAGE_DIR_INDEX, PTE_ADDRESS, PTE_FLAGS, V2P,
 };
 
 use super::vm::vm_corruption_checker;
@@ -24,8 +30,8 @@ pub fn check_process_memory(page_dir_address: usize) {
     );
 
     let mut page_dir = Page::new(page_dir_address as *mut u8);
-    let page_dir_entries = page_dir.cast_to::<usize>();
-    let number_pages = KERNEL_BASE / (PAGE_SIZE * 1024);
+    let page_dir_entries = get_page_dir_entries(&mut page_dir);
+    let number_pages = PAGE_DIR_INDEX!(KERNEL_BASE);
 
     // Check all pages from 0 to KERNEL_BASE
     for i in 0..number_pages {
@@ -37,14 +43,14 @@ pub fn check_process_memory(page_dir_address: usize) {
 
             let page_table_address = P2V!(PTE_ADDRESS!(page_dir_entry));
             let mut page_table = Page::new(page_table_address as *mut u8);
-            let page_table_entries = page_table.cast_to::<usize>();
+            let page_table_entries = page_table.cast_to::<PageTableEntry>();
 
-            for j in 0..1024 {
+            for j in 0..NUMBER_PAGE_ENTRIES {
                 let page_table_entry = page_table_entries[j];
                 let is_pt_present = PTE_FLAGS!(page_table_entry) & PTE_P > 0;
 
                 if is_pt_present {
-                    let virtual_address = i * 1024 * 4096 + j * 4096;
+                    let virtual_address = (i << PAGE_DIR_SHIFT) + j * PAGE_SIZE;
                     let physical_address = PTE_ADDRESS!(page_table_entry);
 
                     println!(
@@ -68,7 +74,8 @@ pub fn debug_elf(path: &str) {
     println!("[DEBUG] Checking ELF Binary");
 
     let inode = find_inode_by_path(path).unwrap();
-    let mut data = read_inode_data(&inode, 0, ELF_HEADER_SIZE as u32);
+    let mut data = read_inode_data(&inode, 0, ELF_HEADER_SIZE as u32)
+        .expect("[FATAL, DEBUG] Could not read the ELF Header");
     let header =
         unsafe { core::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut ELFHeader, 1)[0] };
 
@@ -85,7 +92,8 @@ pub fn debug_elf(path: &str) {
     // Load program headers into memory
     let mut offset = header.program_header_offset as usize;
     for i in 0..header.number_entries {
-        let data = read_inode_data(&inode, offset as u32, ELF_PROG_HEADER_SIZE as u32);
+        let data = read_inode_data(&inode, offset as u32, ELF_PROG_HEADER_SIZE as u32)
+            .expect("[FATAL, DEBUG] Could not read a Program Header");
         let prog_header = unsafe { *(data.as_slice().as_ptr() as *const ProgramHeader) };
         offset += ELF_PROG_HEADER_SIZE;
         println!("----- Found Program Header [{}] -----", i);
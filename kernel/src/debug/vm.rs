@@ -1,24 +1,29 @@
 use core::slice::from_raw_parts_mut;
 
 use crate::{
-    memory::defs::{Page, KERNEL_BASE, PAGE_SIZE, PTE_P},
+    memory::{
+        defs::{
+            Page, PageTableEntry, E820_USABLE, KERNEL_BASE, NUMBER_PAGE_DIR_ENTRIES,
+            NUMBER_PAGE_ENTRIES, PAGE_DIR_SHIFT, PAGE_SIZE, PTE_P,
+        },
+        frame::get_frame_stats,
+        heap::get_heap_stats,
+        mem::MEMORY_MAP,
+        vm::get_page_dir_entries,
+    },
     println,
     x86::helpers::{cli, hlt, read_cr3},
-    P2V, PTE_ADDRESS, PTE_FLAGS,
+    P2V, PAGE_DIR_INDEX, PTE_ADDRESS, PTE_FLAGS,
 };
 
 // Compares the memory of two page directories. While specific physical page addresses may not be equal
 // the corresponding virtual memory location and data must.
 pub unsafe fn compare_virtual_memory(first_pg_dir: &mut Page, second_pg_dir: &mut Page) {
-    let page_entries = PAGE_SIZE / core::mem::size_of::<usize>();
-    let first_page_dir_data = first_pg_dir.cast_to::<usize>();
-    let second_page_dir_data = second_pg_dir.cast_to::<usize>();
-
-    for i in 0..page_entries {
-        if i * PAGE_SIZE.pow(2) > KERNEL_BASE {
-            break;
-        }
+    let page_entries = NUMBER_PAGE_ENTRIES;
+    let first_page_dir_data = get_page_dir_entries(first_pg_dir);
+    let second_page_dir_data = get_page_dir_entries(second_pg_dir);
 
+    for i in 0..PAGE_DIR_INDEX!(KERNEL_BASE) {
         let first_page_dir_entry = first_page_dir_data[i];
         let second_page_dir_entry = second_page_dir_data[i];
 
@@ -37,12 +42,14 @@ pub unsafe fn compare_virtual_memory(first_pg_dir: &mut Page, second_pg_dir: &mu
 
         // Avoid calling walk_page_dir here due to lookup slowdown
         let second_page_dir_entry = second_page_dir_data[i];
-        let second_page_dir_address = P2V!(PTE_ADDRESS!(second_page_dir_entry)) as *mut usize;
-        let second_page_table_data = from_raw_parts_mut(second_page_dir_address, PAGE_SIZE / 4);
+        let second_page_dir_address =
+            P2V!(PTE_ADDRESS!(second_page_dir_entry)) as *mut PageTableEntry;
+        let second_page_table_data = from_raw_parts_mut(second_page_dir_address, page_entries);
 
         let first_page_dir_entry = first_page_dir_data[i];
-        let first_page_dir_address = P2V!(PTE_ADDRESS!(first_page_dir_entry)) as *mut usize;
-        let first_page_table_data = from_raw_parts_mut(first_page_dir_address, PAGE_SIZE / 4);
+        let first_page_dir_address =
+            P2V!(PTE_ADDRESS!(first_page_dir_entry)) as *mut PageTableEntry;
+        let first_page_table_data = from_raw_parts_mut(first_page_dir_address, page_entries);
 
         for j in 0..page_entries {
             let first_page_table_entry_flags = PTE_FLAGS!(first_page_table_data[j]);
@@ -65,7 +72,7 @@ pub unsafe fn compare_virtual_memory(first_pg_dir: &mut Page, second_pg_dir: &mu
             let second_page_address = P2V!(PTE_ADDRESS!(second_page_entry)) as *mut usize;
             let second_page_data = from_raw_parts_mut(second_page_address, PAGE_SIZE / 4);
 
-            for k in 0..page_entries {
+            for k in 0..PAGE_SIZE / 4 {
                 if first_page_data[k] != second_page_data[k] {
                     panic!("[ERROR] VMs are different - Data Mismatch");
                 }
@@ -79,23 +86,25 @@ pub unsafe fn compare_virtual_memory(first_pg_dir: &mut Page, second_pg_dir: &mu
 #[no_mangle]
 pub fn vm_corruption_checker() {
     cli();
-    let cr3 = P2V!(read_cr3()) as *const u32;
-    println!("[DEBUG] Memory Check on Page Directory 0x{:X}", cr3 as u32);
+    let cr3 = P2V!(read_cr3());
+    println!("[DEBUG] Memory Check on Page Directory 0x{:X}", cr3);
 
-    for pg_dir_index in 511..1024 {
-        let page_dir_entry = unsafe { *cr3.add(pg_dir_index) };
-        let page_table_address = page_dir_entry & 0xFFFFF000;
-        let page_present = page_dir_entry & 0b1;
-        let mut last_entry = 0;
+    let mut page_dir = Page::new(cr3 as *mut u8);
+    let page_dir_entries = get_page_dir_entries(&mut page_dir);
 
-        if page_present != 1 {
+    for pg_dir_index in (KERNEL_BASE >> PAGE_DIR_SHIFT) - 1..NUMBER_PAGE_DIR_ENTRIES {
+        let page_dir_entry = page_dir_entries[pg_dir_index];
+        let page_table_address = PTE_ADDRESS!(page_dir_entry);
+        let mut last_entry: usize = 0;
+
+        if page_dir_entry & PTE_P == 0 {
             continue;
         }
 
-        for pg_table_index in 0..1024 {
-            let entry_address = P2V!(page_table_address as usize) as *const u32;
+        for pg_table_index in 0..NUMBER_PAGE_ENTRIES {
+            let entry_address = P2V!(page_table_address) as *const PageTableEntry;
             let entry_data = unsafe { *entry_address.add(pg_table_index) };
-            let page_address = entry_data & 0xFFFFF000;
+            let page_address = PTE_ADDRESS!(entry_data);
 
             if last_entry != 0 && page_address.overflowing_sub(last_entry).0 != 0x1000 {
                 println!("[ERROR] Virtual Memory Corruption Detected");
@@ -113,3 +122,53 @@ pub fn vm_corruption_checker() {
 
     println!("[DEBUG] Memory Check Completed. No Corruption Found");
 }
+
+// Prints how the physical frames are being used
+pub fn debug_frames() {
+    let stats = get_frame_stats();
+
+    println!("\n--- Frames ---");
+    println!("Total: {}", stats.total);
+    println!("Free: {}", stats.free);
+    println!("Kernel: {}", stats.kernel);
+    println!("User: {}", stats.user);
+    println!("--- Frames ---\n");
+}
+
+// Prints how the objects of each slab cache of the heap are being used
+pub fn debug_heap() {
+    println!("\n--- Slab Caches ---");
+    for stats in get_heap_stats() {
+        println!(
+            "{} bytes: {} slabs, {} used, {} free",
+            stats.size, stats.slabs, stats.used, stats.free
+        );
+    }
+    println!("--- Slab Caches ---\n");
+}
+
+// Prints the memory map provided by the BIOS, followed by the memory used by the Kernel
+pub fn debug_memory_map() {
+    let memory_map = MEMORY_MAP.lock();
+
+    println!("\n--- Memory Map ---");
+    for entry in memory_map.get_entries() {
+        let (base, length, _type) = (entry.base, entry.length, entry._type);
+        let name = match _type {
+            E820_USABLE => "Usable",
+            2 => "Reserved",
+            3 => "ACPI Reclaimable",
+            4 => "ACPI NVS",
+            5 => "Bad Memory",
+            _ => "Unknown",
+        };
+
+        println!("0x{:X} - 0x{:X} ({})", base, base + length, name);
+    }
+
+    println!("--- Usable Memory ---");
+    for range in memory_map.get_usable() {
+        println!("0x{:X} - 0x{:X}", range.start, range.end);
+    }
+    println!("--- Memory Map ---\n");
+}
@@ -1,21 +1,34 @@
-// TODO: Implement reference counter to cache blocks (perform MRU, perhaps Priority Queue instead of LL?)
+/// Buffer cache. Disk blocks are kept in memory, so that repeated accesses to the same block do
+/// not go to the disk. Every block returned by read_disk_block is referenced until it is given
+/// back with release_disk_block. The list is kept in Most Recently Used order: released blocks
+/// move to its head, and once the cache is full, the least recently used block that is clean and
+/// unreferenced is recycled for the new request.
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use alloc::sync::Arc;
 
-use crate::{
-    scheduler::sleep::sleep, structures::heap_linked_list::HeapLinkedList,
-    sync::spin_mutex::SpinMutex,
-};
+use crate::{structures::heap_linked_list::HeapLinkedList, sync::spin_mutex::SpinMutex};
 
 use super::ide::{request_ide, DiskBlock, DiskRequestStatus, BLOCK_SIZE};
 
-const MAX_CACHE_BLOCKS: usize = 50; // Number of inodes to be kept in memory.
+const MAX_CACHE_BLOCKS: usize = 50; // Number of disk blocks to be kept in memory.
 
 pub type CacheBlock = Arc<SpinMutex<DiskBlock>>;
 
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStatistics {
+    pub hits: usize,      // Blocks found in cache
+    pub misses: usize,    // Blocks that had to be loaded from disk
+    pub evictions: usize, // Cached blocks recycled to hold another block
+}
+
 static CACHE_BLOCK_LIST: SpinMutex<HeapLinkedList<CacheBlock>> =
     SpinMutex::new(HeapLinkedList::new());
 
+static CACHE_HITS: AtomicUsize = AtomicUsize::new(0);
+static CACHE_MISSES: AtomicUsize = AtomicUsize::new(0);
+static CACHE_EVICTIONS: AtomicUsize = AtomicUsize::new(0);
+
 impl HeapLinkedList<CacheBlock> {
     pub fn search(&self, device: u32, block_number: u32) -> Option<CacheBlock> {
         let mut current = &self.head;
@@ -33,15 +46,37 @@ impl HeapLinkedList<CacheBlock> {
 
         None
     }
-}
 
-pub fn store_new_block(device: u32, block_number: u32) -> CacheBlock {
-    let mut cache = CACHE_BLOCK_LIST.lock();
+    /// Find the least recently used block that is neither referenced nor waiting to be written.
+    /// Since released blocks are moved to the head, this is the last such block of the list.
+    pub fn search_unused(&self) -> Option<CacheBlock> {
+        let mut current = &self.head;
+        let mut unused = None;
+
+        while let Some(node) = current {
+            let data = node.value.lock();
+
+            if data.reference_count == 0 && !data.dirty {
+                unused = Some(Arc::clone(&node.value));
+            }
 
+            current = &node.next;
+        }
+
+        unused
+    }
+}
+
+fn store_new_block(
+    cache: &mut HeapLinkedList<CacheBlock>,
+    device: u32,
+    block_number: u32,
+) -> CacheBlock {
     let block = Arc::new(SpinMutex::new({
         DiskBlock {
             device,
             block_number,
+            reference_count: 1,
             dirty: false,
             status: DiskRequestStatus::AWAITING,
             data: [0; BLOCK_SIZE],
@@ -52,17 +87,17 @@ pub fn store_new_block(device: u32, block_number: u32) -> CacheBlock {
 }
 
 pub fn write_disk_block(block: CacheBlock) -> CacheBlock {
-    let address = block.lock().get_address();
     block.lock().dirty = true;
 
     request_ide(Arc::clone(&block));
-    sleep(address);
 
     return block;
 }
 
+/// Get the block from cache, loading it from disk if needed. The block must be given back with
+/// release_disk_block once it is no longer used.
 pub fn read_disk_block(device: u32, block_number: u32) -> CacheBlock {
-    let block = get_cache_block(device, block_number).unwrap();
+    let block = get_cache_block(device, block_number).expect("[FATAL] No free cache blocks");
 
     // If block is already available in cache, return it
     if block.lock().status == DiskRequestStatus::READY {
@@ -70,23 +105,69 @@ pub fn read_disk_block(device: u32, block_number: u32) -> CacheBlock {
     }
 
     // Start IDE request
-    let address = block.lock().get_address();
     request_ide(Arc::clone(&block));
-    sleep(address);
 
     block
 }
 
+/// Find the cache block holding the given disk block and take a reference to it. On a miss, a
+/// new block is added to the cache or, when it is full, an unused block is recycled. Returns
+/// None if every block of a full cache is in use.
 pub fn get_cache_block(device: u32, block_number: u32) -> Option<CacheBlock> {
-    match CACHE_BLOCK_LIST.lock().search(device, block_number) {
-        None => Some(store_new_block(device, block_number)),
-        Some(block) => Some(block),
+    let mut cache = CACHE_BLOCK_LIST.lock();
+
+    if let Some(block) = cache.search(device, block_number) {
+        block.lock().reference_count += 1;
+        CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+        return Some(block);
+    }
+
+    CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+
+    if cache.size < MAX_CACHE_BLOCKS {
+        return Some(store_new_block(&mut cache, device, block_number));
+    }
+
+    let block = cache.search_unused()?;
+    {
+        let mut data = block.lock();
+        data.device = device;
+        data.block_number = block_number;
+        data.reference_count = 1;
+        data.status = DiskRequestStatus::AWAITING;
+    }
+
+    CACHE_EVICTIONS.fetch_add(1, Ordering::Relaxed);
+    Some(block)
+}
+
+/// Give back a block returned by read_disk_block. Once the block is no longer referenced, it is
+/// moved to the head of the list (Most Recently Used), so it is the last one to be recycled.
+pub fn release_disk_block(block: CacheBlock) {
+    let mut cache = CACHE_BLOCK_LIST.lock();
+
+    let reference_count = {
+        let mut data = block.lock();
+
+        if data.reference_count == 0 {
+            panic!("[FATAL] Released cache block is not referenced");
+        }
+
+        data.reference_count -= 1;
+        data.reference_count
+    };
+
+    if reference_count == 0 {
+        if let Some(block) = cache.remove(|cached| Arc::ptr_eq(cached, &block)) {
+            cache.push(block);
+        }
     }
 }
 
-/// Move the cache block to the head of the list (Most Recently Used) and reduce the reference
-/// count to it. If the list is full, blocks with a reference count of 0 and not dirty are reused.
-pub fn remove_cache_block(block: CacheBlock) {
-    let cache = block.lock();
-    let list = CACHE_BLOCK_LIST.lock();
+pub fn get_cache_statistics() -> CacheStatistics {
+    CacheStatistics {
+        hits: CACHE_HITS.load(Ordering::Relaxed),
+        misses: CACHE_MISSES.load(Ordering::Relaxed),
+        evictions: CACHE_EVICTIONS.load(Ordering::Relaxed),
+    }
 }
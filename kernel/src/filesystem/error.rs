@@ -0,0 +1,41 @@
+#[derive(Copy, Clone, Debug)]
+pub enum FileSystemError {
+    // No entry exists at the given path
+    NotFound,
+
+    // An entry already exists at the given path
+    AlreadyExists,
+
+    // Path goes through something that is not a directory
+    NotADirectory,
+
+    // Directory used where only files are accepted, such as when opened for writing
+    IsADirectory,
+
+    // Directory still has entries besides "." and ".."
+    DirectoryNotEmpty,
+
+    // Name is empty, "." or "..", or a directory would be moved inside itself
+    InvalidPath,
+
+    // Name does not fit in a directory entry
+    NameTooLong,
+
+    // No free data blocks or inodes left on disk
+    NoSpace,
+
+    // Inode already has the maximum number of links
+    TooManyLinks,
+
+    // Operation is not allowed on this type of inode, such as linking a directory
+    NotPermitted,
+
+    // File was not opened for reading or writing
+    BadFileMode,
+
+    // Data written to the console is not valid UTF-8
+    InvalidData,
+
+    // Pipe was written after its read end was closed
+    BrokenPipe,
+}
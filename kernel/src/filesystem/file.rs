@@ -0,0 +1,214 @@
+/// Open files are the bridge between processes and the file system. Each process keeps a table of
+/// file descriptors, small integers that index into its list of open files. Multiple descriptors
+/// (and multiple processes, after a fork) may point to the same open file, sharing its offset.
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{devices::console::CONSOLE, sync::spin_mutex::SpinMutex};
+
+use super::{
+    error::FileSystemError,
+    fs::{
+        create_inode, find_inode_number_by_path, get_inode, get_inode_reference, lock_inode,
+        read_inode_data, truncate_inode, write_inode_data, INodeReference, INodeType,
+    },
+    ide::BLOCK_SIZE,
+    log::{begin_operation, end_operation, MAX_OPERATION_BLOCKS},
+    pipe::{read_pipe, write_pipe, PipeEnd},
+};
+
+pub const MAX_OPEN_FILES: usize = 16; // Number of files a single process can have open
+
+// Largest write performed in a single log operation. Besides the data blocks (plus one, for
+// unaligned writes), an operation writes the inode, the bitmap blocks, and up to three blocks of
+// addresses (two indirect blocks and the double-indirect block, when crossing between them).
+const MAX_WRITE_CHUNK_SIZE: usize =
+    (MAX_OPERATION_BLOCKS as usize - 1 - 1 - 2 - 3) / 2 * BLOCK_SIZE;
+
+// Open Flags (arg2 of the OPEN system call)
+pub const O_RDONLY: usize = 0x000;
+pub const O_WRONLY: usize = 0x001;
+pub const O_RDWR: usize = 0x002;
+pub const O_CREATE: usize = 0x200;
+pub const O_TRUNC: usize = 0x400;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType {
+    INODE,
+    CONSOLE,
+    PIPE,
+}
+
+#[derive(Debug, Clone)]
+pub struct File {
+    pub _type: FileType,
+    pub inode: Option<Arc<INodeReference>>, // Only used by inodes
+    pub offset: u32,
+    pub readable: bool,
+    pub writable: bool,
+    pub pipe: Option<Arc<PipeEnd>>, // Only used by pipes
+}
+
+pub type FileDescriptor = Arc<SpinMutex<File>>;
+
+impl File {
+    pub const fn new(_type: FileType, readable: bool, writable: bool) -> Self {
+        File {
+            _type,
+            inode: None,
+            offset: 0,
+            readable,
+            writable,
+            pipe: None,
+        }
+    }
+}
+
+/// Opens the file at path. Directories can only be opened for reading. With O_CREATE, a missing
+/// file is created, and with O_TRUNC, the content of a file opened for writing is discarded.
+pub fn open_file(path: &str, flags: usize) -> Result<FileDescriptor, FileSystemError> {
+    let inode_number = match find_inode_number_by_path(path) {
+        Some(inode_number) => inode_number,
+        None if flags & O_CREATE > 0 => {
+            begin_operation();
+            let inode_number = create_inode(path, INodeType::FILE);
+            end_operation();
+
+            // Another process may have created the file since it was looked up
+            match inode_number {
+                Err(FileSystemError::AlreadyExists) => {
+                    find_inode_number_by_path(path).ok_or(FileSystemError::NotFound)?
+                }
+                inode_number => inode_number?,
+            }
+        }
+        None => return Err(FileSystemError::NotFound),
+    };
+
+    // Taken right away, so that the inode is not freed while it is being opened
+    let reference = get_inode_reference(inode_number);
+
+    let readable = flags & O_WRONLY == 0;
+    let writable = flags & (O_WRONLY | O_RDWR) > 0;
+
+    // The log operation must begin before the lock is taken, as in every other writer
+    let truncate = flags & O_TRUNC > 0 && writable;
+    if truncate {
+        begin_operation();
+    }
+
+    let inode_lock = lock_inode(inode_number);
+    let inode = get_inode(inode_number);
+
+    // The file was removed since its lookup
+    let output = match inode._type {
+        INodeType::FREE => Err(FileSystemError::NotFound),
+        _ if inode.number_links == 0 => Err(FileSystemError::NotFound),
+        INodeType::DIRECTORY if writable => Err(FileSystemError::IsADirectory),
+        _ => Ok(()),
+    };
+
+    if output.is_ok() && truncate {
+        truncate_inode(inode_number);
+    }
+
+    // Dropping the reference may free the inode, which takes its lock again
+    drop(inode_lock);
+    if truncate {
+        end_operation();
+    }
+
+    output?;
+
+    let mut file = File::new(FileType::INODE, readable, writable);
+    file.inode = Some(reference);
+
+    Ok(Arc::new(SpinMutex::new(file)))
+}
+
+/// Opens the console. Used to setup the standard input, output and error of the init process.
+pub fn open_console() -> FileDescriptor {
+    Arc::new(SpinMutex::new(File::new(FileType::CONSOLE, true, true)))
+}
+
+/// Reads up to length bytes from the file, starting at the file's current offset. The offset is
+/// moved forward by the number of bytes read. An empty buffer indicates the end of the file.
+pub fn read_file(descriptor: &FileDescriptor, length: usize) -> Result<Vec<u8>, FileSystemError> {
+    let file = descriptor.lock().clone();
+
+    if !file.readable {
+        return Err(FileSystemError::BadFileMode);
+    }
+
+    match file._type {
+        // Console input is not buffered yet, reads always hit the end of the file
+        FileType::CONSOLE => Ok(Vec::new()),
+        // The descriptor lock is not held, since reading may sleep until data is written
+        FileType::PIPE => Ok(read_pipe(file.pipe.as_ref().unwrap(), length)),
+        FileType::INODE => {
+            // The descriptor lock must not be held while the disk is accessed, since reading may
+            // sleep. The inode lock keeps writers from changing the data while it is read.
+            let inode_number = file.inode.as_ref().unwrap().inode_number;
+            let inode_lock = lock_inode(inode_number);
+            let inode = get_inode(inode_number);
+            if file.offset >= inode.size {
+                return Ok(Vec::new());
+            }
+
+            let data = read_inode_data(&inode, file.offset, length as u32)?;
+            drop(inode_lock);
+
+            descriptor.lock().offset += data.len() as u32;
+            Ok(data)
+        }
+    }
+}
+
+/// Writes the buffer into the file. Returns the number of bytes written.
+pub fn write_file(descriptor: &FileDescriptor, buffer: &[u8]) -> Result<usize, FileSystemError> {
+    let file = descriptor.lock().clone();
+
+    if !file.writable {
+        return Err(FileSystemError::BadFileMode);
+    }
+
+    match file._type {
+        FileType::CONSOLE => {
+            let message = core::str::from_utf8(buffer).map_err(|_| FileSystemError::InvalidData)?;
+            CONSOLE.lock().write_string(message);
+            Ok(buffer.len())
+        }
+
+        FileType::PIPE => write_pipe(file.pipe.as_ref().unwrap(), buffer),
+
+        FileType::INODE => {
+            let inode_number = file.inode.as_ref().unwrap().inode_number;
+            let mut count = 0;
+
+            // Writes are split so that each chunk fits in a single log operation
+            while count < buffer.len() {
+                let chunk_size = core::cmp::min(buffer.len() - count, MAX_WRITE_CHUNK_SIZE);
+                let chunk = &buffer[count..(count + chunk_size)];
+                let offset = descriptor.lock().offset;
+
+                begin_operation();
+                let inode_lock = lock_inode(inode_number);
+                let written = write_inode_data(inode_number, offset, chunk);
+                drop(inode_lock);
+                end_operation();
+
+                let written = written.unwrap_or(0);
+                descriptor.lock().offset += written as u32;
+                count += written;
+
+                if written != chunk_size {
+                    break;
+                }
+            }
+
+            match count {
+                0 if !buffer.is_empty() => Err(FileSystemError::NoSpace),
+                _ => Ok(count),
+            }
+        }
+    }
+}
@@ -1,13 +1,24 @@
+use core::convert::TryInto;
+
 use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
 use alloc::string::ToString;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloc::{string::String, vec};
 
-use crate::filesystem::log::setup_log;
-use crate::{println, sync::spin_mutex::SpinMutex};
+use crate::filesystem::log::{
+    begin_operation, end_operation, recover_log, setup_log, write_block_log,
+};
+use crate::println;
+use crate::sync::{
+    sleep_lock::{SleepLock, SleepLockGuard},
+    spin_mutex::SpinMutex,
+};
 
 use super::{
-    cache::{read_disk_block, CacheBlock},
+    cache::{read_disk_block, release_disk_block, CacheBlock},
+    error::FileSystemError,
     ide::BLOCK_SIZE,
 };
 
@@ -24,9 +35,19 @@ pub struct SuperBlock {
 
 const INODE_SIZE: usize = core::mem::size_of::<INode>();
 const INODE_PER_BLOCK: usize = BLOCK_SIZE / INODE_SIZE;
-const INODE_DATA_ADDRESS_SIZE: usize = 12;
+const INODE_DATA_ADDRESS_SIZE: usize = 12; // Direct blocks
+const INDIRECT_ADDRESS_SIZE: usize = BLOCK_SIZE / core::mem::size_of::<u32>();
+const INDIRECT_INDEX: usize = INODE_DATA_ADDRESS_SIZE; // Block of direct addresses
+const DOUBLE_INDIRECT_INDEX: usize = INODE_DATA_ADDRESS_SIZE + 1; // Block of indirect blocks
+
+/// Number of data blocks a single inode can address
+pub const MAX_FILE_BLOCKS: usize =
+    INODE_DATA_ADDRESS_SIZE + INDIRECT_ADDRESS_SIZE + INDIRECT_ADDRESS_SIZE * INDIRECT_ADDRESS_SIZE;
 
 const DIRECTORY_NAME_SIZE: usize = 20;
+const DIRECTORY_ENTRY_SIZE: usize = core::mem::size_of::<DirectoryEntry>();
+
+const BITS_PER_BITMAP_BLOCK: u32 = BLOCK_SIZE as u32 * 8;
 
 #[repr(u8)]
 #[derive(Default, Debug, PartialEq, Clone, Copy)]
@@ -40,12 +61,12 @@ pub enum INodeType {
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct INode {
-    _type: INodeType,
-    major: u8,
-    minor: u8,
-    number_links: u8,
-    size: u32,
-    data: [u32; INODE_DATA_ADDRESS_SIZE + 1],
+    pub _type: INodeType,
+    pub major: u8,
+    pub minor: u8,
+    pub number_links: u8,
+    pub size: u32,
+    pub data: [u32; INODE_DATA_ADDRESS_SIZE + 2], // Direct, indirect and double-indirect blocks
 }
 
 #[repr(C)]
@@ -55,6 +76,32 @@ pub struct DirectoryEntry {
     pub name: [u8; DIRECTORY_NAME_SIZE as usize],
 }
 
+/// Reference to an inode held by an open file or a file mapping. An inode whose last link is
+/// removed is kept allocated until its last reference is dropped (see unlink).
+#[derive(Debug)]
+pub struct INodeReference {
+    pub inode_number: u32,
+}
+
+/// References to an inode that is in use, and whether it was unlinked while in use
+#[derive(Debug, Default)]
+struct OpenINode {
+    references: usize,
+    unlinked: bool,
+}
+
+impl DirectoryEntry {
+    pub fn get_name(&self) -> &str {
+        let length = self
+            .name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(DIRECTORY_NAME_SIZE);
+
+        core::str::from_utf8(&self.name[..length]).unwrap_or("")
+    }
+}
+
 impl SuperBlock {
     pub const fn new() -> Self {
         SuperBlock {
@@ -79,45 +126,409 @@ pub const ROOT_INODE_NUMBER: u32 = 1;
 
 pub static SUPER_BLOCK_CACHE: SpinMutex<SuperBlock> = SpinMutex::new(SuperBlock::new());
 
+// Inodes referenced by open files or file mappings, by inode number
+static OPEN_INODES: SpinMutex<BTreeMap<u32, OpenINode>> = SpinMutex::new(BTreeMap::new());
+
+// Held from the search for a free block or inode until it is marked used, since the search may
+// sleep on the disk while another process finds the same one free
+static ALLOCATION_LOCK: SleepLock<()> = SleepLock::new();
+
+// One lock per inode, by inode number (see lock_inode)
+static INODE_LOCKS: SleepLock<u32> = SleepLock::new();
+
+// Held by rename, the only operation that moves directories around the tree (see rename)
+static RENAME_LOCK: SleepLock<()> = SleepLock::new();
+
 pub fn load_super_block() {
-    let cache_data = read_disk_block(SECONDARY_BLOCK_ID, 1).lock().data.as_ptr();
-    let super_block = unsafe { *(cache_data as *const SuperBlock) };
+    let block = read_disk_block(SECONDARY_BLOCK_ID, 1);
+    let super_block = unsafe { *(block.lock().data.as_ptr() as *const SuperBlock) };
+    release_disk_block(block);
+
     *SUPER_BLOCK_CACHE.lock() = super_block;
 }
 
-fn clear_block(block: CacheBlock) {
+fn clear_block(block: &CacheBlock) {
     block.lock().data = [0; BLOCK_SIZE];
 }
 
-pub fn get_inode(inode_number: u32) -> INode {
-    let inode_offset = INODE_SIZE / BLOCK_SIZE;
+/// Disk block holding the given inode. Inodes are packed INODE_PER_BLOCK to a block.
+fn get_inode_block_number(inode_number: u32) -> u32 {
     let inode_start = SUPER_BLOCK_CACHE.lock().inode_start_address;
-    let inode_block = inode_start + inode_number * inode_offset as u32;
+    inode_start + inode_number / INODE_PER_BLOCK as u32
+}
 
-    let block_data = read_disk_block(SECONDARY_BLOCK_ID, inode_block);
-    let mut block_data = block_data.lock();
-    let inode_list = block_data.cast_to::<INode>();
+pub fn get_inode(inode_number: u32) -> INode {
+    let inode_block = get_inode_block_number(inode_number);
+
+    let block = read_disk_block(SECONDARY_BLOCK_ID, inode_block);
     let inode_index = inode_number as usize % INODE_PER_BLOCK;
+    let inode = block.lock().cast_to::<INode>()[inode_index];
 
-    inode_list[inode_index]
+    release_disk_block(block);
+    inode
 }
 
-pub fn read_inode_data(inode: &INode, mut offset: u32, mut length: u32) -> Vec<u8> {
-    assert!(offset <= inode.size);
+/// Write the inode back into its block. Like every other function that modifies the disk, it
+/// must be called inside a log operation (begin_operation/end_operation).
+pub fn write_inode(inode_number: u32, inode: &INode) {
+    let inode_block = get_inode_block_number(inode_number);
+    let block = read_disk_block(SECONDARY_BLOCK_ID, inode_block);
 
-    // Truncate if length and offset are outside the side of the inode
-    if offset + length > inode.size {
-        length = inode.size - offset;
+    let inode_index = inode_number as usize % INODE_PER_BLOCK;
+    block.lock().cast_to::<INode>()[inode_index] = *inode;
+
+    write_block_log(&block);
+    release_disk_block(block);
+}
+
+/// Free-space bitmap. Every block of the file system is represented by a single bit, which is
+/// set when the block is in use. The bitmap is written by mkfs, marking all blocks up to the
+/// last data block it wrote as used.
+fn set_bitmap_bit(block_number: u32, value: bool) {
+    let bitmap_start = SUPER_BLOCK_CACHE.lock().bitmap_start_address;
+    let bitmap_block = bitmap_start + block_number / BITS_PER_BITMAP_BLOCK;
+    let bit_index = (block_number % BITS_PER_BITMAP_BLOCK) as usize;
+
+    let block = read_disk_block(SECONDARY_BLOCK_ID, bitmap_block);
+
+    {
+        let mut bitmap = block.lock();
+        if value {
+            bitmap.data[bit_index / 8] |= 1 << (bit_index % 8);
+        } else {
+            bitmap.data[bit_index / 8] &= !(1 << (bit_index % 8));
+        }
+    }
+
+    write_block_log(&block);
+    release_disk_block(block);
+}
+
+/// Allocate a zeroed data block. Scans the free-space bitmap for the first clear bit.
+pub fn allocate_block() -> Option<u32> {
+    let _allocation = ALLOCATION_LOCK.lock(());
+    let super_block = *SUPER_BLOCK_CACHE.lock();
+
+    let mut bitmap_block_number = 0;
+    while bitmap_block_number * BITS_PER_BITMAP_BLOCK < super_block.size {
+        let bitmap_address = super_block.bitmap_start_address + bitmap_block_number;
+        let block = read_disk_block(SECONDARY_BLOCK_ID, bitmap_address);
+        let bitmap = block.lock().data;
+        release_disk_block(block);
+
+        for bit_index in 0..BITS_PER_BITMAP_BLOCK {
+            let block_number = bitmap_block_number * BITS_PER_BITMAP_BLOCK + bit_index;
+            if block_number >= super_block.size {
+                return None;
+            }
+
+            let is_used = bitmap[bit_index as usize / 8] & (1 << (bit_index % 8)) > 0;
+            if is_used {
+                continue;
+            }
+
+            set_bitmap_bit(block_number, true);
+
+            let block = read_disk_block(SECONDARY_BLOCK_ID, block_number);
+            clear_block(&block);
+            write_block_log(&block);
+            release_disk_block(block);
+
+            return Some(block_number);
+        }
+
+        bitmap_block_number += 1;
+    }
+
+    None
+}
+
+/// Return a data block to the free-space bitmap.
+pub fn free_block(block_number: u32) {
+    set_bitmap_bit(block_number, false);
+}
+
+/// Allocate an inode of the given type. Free inodes are marked with INodeType::FREE.
+pub fn allocate_inode(_type: INodeType) -> Option<u32> {
+    let _allocation = ALLOCATION_LOCK.lock(());
+    let number_inodes = SUPER_BLOCK_CACHE.lock().number_inodes;
+
+    for inode_number in ROOT_INODE_NUMBER..number_inodes {
+        let inode = get_inode(inode_number);
+        if inode._type != INodeType::FREE {
+            continue;
+        }
+
+        let new_inode = INode {
+            _type,
+            major: 0,
+            minor: 0,
+            number_links: 1,
+            size: 0,
+            data: [0; INODE_DATA_ADDRESS_SIZE + 2],
+        };
+
+        write_inode(inode_number, &new_inode);
+        return Some(inode_number);
+    }
+
+    None
+}
+
+/// Locks the inode until the guard is dropped. Anything that reads the inode's data and relies on
+/// it staying the same, or that changes its data, links or size, must hold its lock, since the
+/// disk accesses in between may sleep. A process takes the locks of directories before the locks of
+/// their entries, and never holds two locks of directories that are not parent and child (except
+/// in rename).
+pub fn lock_inode(inode_number: u32) -> SleepLockGuard<'static, u32> {
+    INODE_LOCKS.lock(inode_number)
+}
+
+/// Lock the directory, and check that it is still part of the tree, since it may have been removed
+/// between its lookup and the lock.
+fn lock_directory(
+    directory_number: u32,
+) -> Result<(SleepLockGuard<'static, u32>, INode), FileSystemError> {
+    let guard = lock_inode(directory_number);
+    let directory = get_inode(directory_number);
+
+    match directory._type {
+        INodeType::DIRECTORY if directory.number_links > 0 => Ok((guard, directory)),
+        INodeType::DIRECTORY | INodeType::FREE => Err(FileSystemError::NotFound),
+        INodeType::FILE => Err(FileSystemError::NotADirectory),
+    }
+}
+
+/// Release all data held by the inode and mark it as free. The caller holds the inode's lock.
+pub fn free_inode(inode_number: u32) {
+    let mut inode = truncate_inode(inode_number);
+    inode._type = INodeType::FREE;
+    inode.number_links = 0;
+    write_inode(inode_number, &inode);
+}
+
+/// Takes a reference to the inode, which keeps it allocated for as long as it is held
+pub fn get_inode_reference(inode_number: u32) -> Arc<INodeReference> {
+    OPEN_INODES
+        .lock()
+        .entry(inode_number)
+        .or_default()
+        .references += 1;
+
+    Arc::new(INodeReference { inode_number })
+}
+
+impl Drop for INodeReference {
+    fn drop(&mut self) {
+        let unlinked = {
+            let mut open_inodes = OPEN_INODES.lock();
+            let open_inode = open_inodes.get_mut(&self.inode_number).unwrap();
+            open_inode.references -= 1;
+
+            match open_inode.references {
+                0 => open_inodes.remove(&self.inode_number).unwrap().unlinked,
+                _ => false,
+            }
+        };
+
+        // The inode lost its last link while in use, so it is freed now that it is no longer used
+        if unlinked {
+            begin_operation();
+            let inode_lock = lock_inode(self.inode_number);
+            free_inode(self.inode_number);
+            drop(inode_lock);
+            end_operation();
+        }
+    }
+}
+
+/// Free the inodes that lost their last link while in use, but were never freed since the system
+/// went down before their last reference was dropped.
+fn free_orphan_inodes() {
+    let number_inodes = SUPER_BLOCK_CACHE.lock().number_inodes;
+
+    for inode_number in ROOT_INODE_NUMBER..number_inodes {
+        let inode = get_inode(inode_number);
+        if inode._type != INodeType::FREE && inode.number_links == 0 {
+            begin_operation();
+            let inode_lock = lock_inode(inode_number);
+            free_inode(inode_number);
+            drop(inode_lock);
+            end_operation();
+        }
+    }
+}
+
+/// Return the block at address, allocating a new one (and storing it in address) if it is zero
+/// and allocate is true.
+fn get_or_allocate_block(address: &mut u32, allocate: bool) -> Option<u32> {
+    if *address == 0 {
+        if !allocate {
+            return None;
+        }
+
+        *address = allocate_block()?;
+    }
+
+    Some(*address)
+}
+
+/// Find the block stored at index of an indirect block. If the entry is empty and allocate is
+/// true, a new block is assigned to it.
+fn get_indirect_block(indirect_block: u32, index: usize, allocate: bool) -> Option<u32> {
+    let block = read_disk_block(SECONDARY_BLOCK_ID, indirect_block);
+    let offset = index * core::mem::size_of::<u32>();
+    let address_data = block.lock().data[offset..(offset + 4)].try_into().unwrap();
+    let mut address = u32::from_le_bytes(address_data);
+
+    // A newly allocated block must be recorded in the indirect block
+    let is_empty = address == 0;
+    let output = get_or_allocate_block(&mut address, allocate);
+    if is_empty && output.is_some() {
+        block.lock().data[offset..(offset + 4)].copy_from_slice(&address.to_le_bytes());
+        write_block_log(&block);
+    }
+
+    release_disk_block(block);
+    output
+}
+
+/// Find the disk block that stores the given block of the inode's data. The first blocks are
+/// addressed directly by the inode, the next ones through its indirect block, and the remaining
+/// ones through its double-indirect block, which holds the addresses of other indirect blocks.
+/// If the block does not exist yet and allocate is true, a new block is assigned to the inode
+/// (along with any indirect block needed to reach it).
+fn get_inode_data_block(inode: &mut INode, block_index: usize, allocate: bool) -> Option<u32> {
+    if block_index < INODE_DATA_ADDRESS_SIZE {
+        return get_or_allocate_block(&mut inode.data[block_index], allocate);
+    }
+
+    let block_index = block_index - INODE_DATA_ADDRESS_SIZE;
+    if block_index < INDIRECT_ADDRESS_SIZE {
+        let indirect_block = get_or_allocate_block(&mut inode.data[INDIRECT_INDEX], allocate)?;
+        return get_indirect_block(indirect_block, block_index, allocate);
+    }
+
+    let block_index = block_index - INDIRECT_ADDRESS_SIZE;
+    if block_index < INDIRECT_ADDRESS_SIZE * INDIRECT_ADDRESS_SIZE {
+        let (outer_index, inner_index) = (
+            block_index / INDIRECT_ADDRESS_SIZE,
+            block_index % INDIRECT_ADDRESS_SIZE,
+        );
+
+        let double_block = get_or_allocate_block(&mut inode.data[DOUBLE_INDIRECT_INDEX], allocate)?;
+        let indirect_block = get_indirect_block(double_block, outer_index, allocate)?;
+        return get_indirect_block(indirect_block, inner_index, allocate);
+    }
+
+    None
+}
+
+/// Write data into the inode starting at offset, allocating blocks as needed and growing the
+/// inode. Returns the number of bytes written, which is smaller than the data when the disk
+/// or the inode runs out of blocks. The caller holds the inode's lock.
+pub fn write_inode_data(inode_number: u32, mut offset: u32, data: &[u8]) -> Option<usize> {
+    let mut inode = get_inode(inode_number);
+
+    // Writes cannot leave holes in the file
+    if offset > inode.size {
+        return None;
+    }
+
+    let mut count: usize = 0;
+    while count < data.len() {
+        let block_index = offset as usize / BLOCK_SIZE;
+        let block_offset = offset as usize % BLOCK_SIZE;
+        let Some(block_number) = get_inode_data_block(&mut inode, block_index, true) else {
+            break;
+        };
+
+        let byte_count = core::cmp::min(data.len() - count, BLOCK_SIZE - block_offset);
+        let block = read_disk_block(SECONDARY_BLOCK_ID, block_number);
+
+        // Copy from buffer to block
+        block.lock().data[block_offset..(block_offset + byte_count)]
+            .copy_from_slice(&data[count..(count + byte_count)]);
+        write_block_log(&block);
+        release_disk_block(block);
+
+        count += byte_count;
+        offset += byte_count as u32;
+    }
+
+    if offset > inode.size {
+        inode.size = offset;
     }
 
+    write_inode(inode_number, &inode);
+    Some(count)
+}
+
+/// Free the block and, for an indirect block, every block it points to. Levels is the number of
+/// indirect blocks between this block and the data (0 for a data block).
+fn free_block_tree(block_number: u32, levels: usize) {
+    if levels > 0 {
+        let block = read_disk_block(SECONDARY_BLOCK_ID, block_number);
+        let data = block.lock().data;
+        release_disk_block(block);
+
+        for address in data.chunks_exact(core::mem::size_of::<u32>()) {
+            let address = u32::from_le_bytes(address.try_into().unwrap());
+            if address != 0 {
+                free_block_tree(address, levels - 1);
+            }
+        }
+    }
+
+    free_block(block_number);
+}
+
+/// Discard the content of the inode, freeing all of its data blocks. The caller holds the inode's
+/// lock.
+pub fn truncate_inode(inode_number: u32) -> INode {
+    let mut inode = get_inode(inode_number);
+
+    for (block_index, address) in inode.data.iter_mut().enumerate() {
+        let levels = match block_index {
+            INDIRECT_INDEX => 1,
+            DOUBLE_INDIRECT_INDEX => 2,
+            _ => 0,
+        };
+
+        if *address != 0 {
+            free_block_tree(*address, levels);
+            *address = 0;
+        }
+    }
+
+    inode.size = 0;
+    write_inode(inode_number, &inode);
+    inode
+}
+
+/// Read up to length bytes of the inode's data, starting at offset. Nothing is read past the end
+/// of the inode. Fails if one of the blocks of the data is missing, as when the inode was changed
+/// without holding its lock since it was read.
+pub fn read_inode_data(
+    inode: &INode,
+    mut offset: u32,
+    length: u32,
+) -> Result<Vec<u8>, FileSystemError> {
+    let mut inode = *inode; // Blocks are only looked up, so this copy is never changed
+
+    // Truncate if length and offset are outside the side of the inode
+    let length = core::cmp::min(length, inode.size.saturating_sub(offset));
+
     let mut buffer = vec![0; length as usize];
     let mut count: usize = 0;
     while count < length as usize {
         let block_offset = offset as usize % BLOCK_SIZE;
-        let block_number = inode.data[offset as usize / BLOCK_SIZE];
-        let block_data = read_disk_block(SECONDARY_BLOCK_ID, block_number)
-            .lock()
-            .data;
+        let block_index = offset as usize / BLOCK_SIZE;
+        let block_number = get_inode_data_block(&mut inode, block_index, false)
+            .ok_or(FileSystemError::InvalidData)?;
+        let block = read_disk_block(SECONDARY_BLOCK_ID, block_number);
+        let block_data = block.lock().data;
+        release_disk_block(block);
 
         let byte_count = core::cmp::min(
             length as usize - count,
@@ -132,7 +543,7 @@ pub fn read_inode_data(inode: &INode, mut offset: u32, mut length: u32) -> Vec<u
         offset += byte_count as u32;
     }
 
-    buffer
+    Ok(buffer)
 }
 
 pub fn get_root_inode() -> INode {
@@ -147,7 +558,10 @@ pub fn setup_file_system() {
         SUPER_BLOCK_CACHE.lock().number_inodes
     );
 
+    // Replay any transaction interrupted by a crash before the disk is used
     setup_log();
+    recover_log();
+    free_orphan_inodes();
 }
 
 pub fn read_dir(inode: &INode) -> Option<Vec<DirectoryEntry>> {
@@ -158,7 +572,7 @@ pub fn read_dir(inode: &INode) -> Option<Vec<DirectoryEntry>> {
         return None;
     }
 
-    let dir_data = read_inode_data(inode, 0, inode.size);
+    let dir_data = read_inode_data(inode, 0, inode.size).ok()?;
     let dir_count = inode.size / core::mem::size_of::<DirectoryEntry>() as u32;
 
     let dir_children = unsafe {
@@ -176,35 +590,321 @@ pub fn get_path_filename(path: &str) -> String {
 }
 
 pub fn find_inode_by_path(path: &str) -> Option<INode> {
-    let dirs;
+    let inode_number = find_inode_number_by_path(path)?;
+    Some(get_inode(inode_number))
+}
 
-    if path.chars().nth(0).unwrap() == '/' {
-        dirs = path.split('/').skip(1);
-    } else {
-        dirs = path.split('/').skip(0);
-    }
+/// Resolve a path into the number of the inode it points to. Knowing the number (and not only
+/// the inode contents) is required by anything that must later reload or update the inode. Each
+/// directory is locked while it is searched, so the caller must not hold any inode lock.
+pub fn find_inode_number_by_path(path: &str) -> Option<u32> {
+    // Empty components come from the leading, trailing or repeated slashes
+    let dirs = path.split('/').filter(|dir| !dir.is_empty());
 
-    let mut current_inode = get_root_inode();
+    let mut current_inode_number = ROOT_INODE_NUMBER;
     for dir in dirs {
-        let Some(entries) = read_dir(&current_inode) else {
+        let _directory_lock = lock_inode(current_inode_number);
+        let current_inode = get_inode(current_inode_number);
+        if current_inode._type != INodeType::DIRECTORY {
             return None;
-        };
+        }
 
         // Search for directory/file in current directory
-        let mut found = false;
-        for entry in entries {
-            let name = core::str::from_utf8(&entry.name).unwrap();
-            if name.trim_matches(char::from(0)) == dir {
-                current_inode = get_inode(entry.inode_number);
-                found = true;
-                break;
+        let (_, inode_number) = find_directory_entry(&current_inode, dir)?;
+        current_inode_number = inode_number;
+    }
+
+    Some(current_inode_number)
+}
+
+/// Turn the path into an absolute path without "." or ".." components. Relative paths are
+/// resolved against directory, which must already be absolute.
+pub fn normalize_path(directory: &str, path: &str) -> String {
+    let base = if path.starts_with('/') { "" } else { directory };
+    let mut components: Vec<&str> = Vec::new();
+
+    for component in base.split('/').chain(path.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
             }
+            name => components.push(name),
         }
+    }
 
-        if found == false {
-            return None;
+    if components.is_empty() {
+        return String::from("/");
+    }
+
+    let mut normalized = String::new();
+    for component in components {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+
+    normalized
+}
+
+/// Find the entry with the given name in the directory. Returns the index of the entry and the
+/// number of the inode it points to.
+fn find_directory_entry(directory: &INode, name: &str) -> Option<(usize, u32)> {
+    let entries = read_dir(directory)?;
+
+    entries
+        .iter()
+        .enumerate()
+        .find(|(_, entry)| entry.inode_number != 0 && entry.get_name() == name)
+        .map(|(index, entry)| (index, entry.inode_number))
+}
+
+/// A directory is empty when it holds nothing besides "." and "..".
+fn is_directory_empty(directory: &INode) -> bool {
+    read_dir(directory)
+        .unwrap_or_default()
+        .iter()
+        .all(|entry| entry.inode_number == 0 || matches!(entry.get_name(), "." | ".."))
+}
+
+/// Split a path into the path of its parent directory and the name of the last component.
+pub fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+
+    match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(index) => (&path[..index], &path[(index + 1)..]),
+        None => ("/", path),
+    }
+}
+
+/// Add an entry to the directory. Entries whose inode number is zero are free and are reused
+/// before the directory is grown. The caller holds the directory's lock.
+pub fn add_directory_entry(
+    directory_number: u32,
+    name: &str,
+    inode_number: u32,
+) -> Result<(), FileSystemError> {
+    if name.is_empty() {
+        return Err(FileSystemError::InvalidPath);
+    }
+
+    if name.len() > DIRECTORY_NAME_SIZE {
+        return Err(FileSystemError::NameTooLong);
+    }
+
+    let directory = get_inode(directory_number);
+    let entries = read_dir(&directory).unwrap_or_default();
+    let index = entries
+        .iter()
+        .position(|entry| entry.inode_number == 0)
+        .unwrap_or(entries.len());
+
+    write_directory_entry(directory_number, index, name, inode_number)
+}
+
+/// Free the entry at index, so that it can be reused by add_directory_entry.
+fn remove_directory_entry(directory_number: u32, index: usize) -> Result<(), FileSystemError> {
+    write_directory_entry(directory_number, index, "", 0)
+}
+
+fn write_directory_entry(
+    directory_number: u32,
+    index: usize,
+    name: &str,
+    inode_number: u32,
+) -> Result<(), FileSystemError> {
+    let mut entry = DirectoryEntry::default();
+    entry.inode_number = inode_number;
+    entry.name[..name.len()].copy_from_slice(name.as_bytes());
+
+    let entry_data = unsafe {
+        core::slice::from_raw_parts(
+            &entry as *const DirectoryEntry as *const u8,
+            DIRECTORY_ENTRY_SIZE,
+        )
+    };
+
+    let offset = (index * DIRECTORY_ENTRY_SIZE) as u32;
+    match write_inode_data(directory_number, offset, entry_data) {
+        Some(DIRECTORY_ENTRY_SIZE) => Ok(()),
+        _ => Err(FileSystemError::NoSpace),
+    }
+}
+
+/// Find the directory at path. Fails if it does not exist or is not a directory.
+fn find_directory_by_path(path: &str) -> Result<u32, FileSystemError> {
+    let inode_number = find_inode_number_by_path(path).ok_or(FileSystemError::NotFound)?;
+
+    match get_inode(inode_number)._type {
+        INodeType::DIRECTORY => Ok(inode_number),
+        _ => Err(FileSystemError::NotADirectory),
+    }
+}
+
+/// Create an empty file or directory at path. Directories start with the "." and ".." entries.
+/// Fails if the parent directory does not exist or the name is already taken.
+pub fn create_inode(path: &str, _type: INodeType) -> Result<u32, FileSystemError> {
+    let (parent_path, name) = split_path(path);
+    let parent_number = find_directory_by_path(parent_path)?;
+
+    // Held until the entry is added, so that no other entry takes the same name meanwhile
+    let (_parent_lock, parent) = lock_directory(parent_number)?;
+    if find_directory_entry(&parent, name).is_some() {
+        return Err(FileSystemError::AlreadyExists);
+    }
+
+    let inode_number = allocate_inode(_type).ok_or(FileSystemError::NoSpace)?;
+    let _inode_lock = lock_inode(inode_number);
+
+    let mut output = Ok(());
+    if _type == INodeType::DIRECTORY {
+        output = add_directory_entry(inode_number, ".", inode_number)
+            .and_then(|_| add_directory_entry(inode_number, "..", parent_number));
+    }
+
+    if let Err(error) = output.and_then(|_| add_directory_entry(parent_number, name, inode_number))
+    {
+        free_inode(inode_number);
+        return Err(error);
+    }
+
+    Ok(inode_number)
+}
+
+/// Remove the entry at path from its directory. The inode is freed once no entry points to it, or,
+/// if it is still open or mapped, once its last reference is dropped. Directories can only be
+/// removed when they are empty.
+pub fn unlink(path: &str) -> Result<(), FileSystemError> {
+    let (parent_path, name) = split_path(path);
+    if matches!(name, "" | "." | "..") {
+        return Err(FileSystemError::InvalidPath);
+    }
+
+    let parent_number = find_directory_by_path(parent_path)?;
+    let (_parent_lock, parent) = lock_directory(parent_number)?;
+
+    let (index, inode_number) =
+        find_directory_entry(&parent, name).ok_or(FileSystemError::NotFound)?;
+    let _inode_lock = lock_inode(inode_number);
+    let mut inode = get_inode(inode_number);
+    if inode._type == INodeType::DIRECTORY && !is_directory_empty(&inode) {
+        return Err(FileSystemError::DirectoryNotEmpty);
+    }
+
+    remove_directory_entry(parent_number, index)?;
+
+    inode.number_links = inode.number_links.saturating_sub(1);
+    let deferred = match OPEN_INODES.lock().get_mut(&inode_number) {
+        Some(open_inode) if inode.number_links == 0 => {
+            open_inode.unlinked = true;
+            true
         }
+        _ => false,
+    };
+
+    // An unlinked inode that is still in use keeps its data, and is freed by its last reference
+    if inode.number_links == 0 && !deferred {
+        free_inode(inode_number);
+    } else {
+        write_inode(inode_number, &inode);
+    }
+
+    Ok(())
+}
+
+/// Create a new entry at new_path for the file at old_path. Directories cannot be linked, as
+/// that would allow cycles in the directory tree.
+pub fn link(old_path: &str, new_path: &str) -> Result<(), FileSystemError> {
+    let inode_number = find_inode_number_by_path(old_path).ok_or(FileSystemError::NotFound)?;
+    let (parent_path, name) = split_path(new_path);
+    let parent_number = find_directory_by_path(parent_path)?;
+
+    // Files have no entries, so their lock can be taken while holding the lock of any directory
+    let (_parent_lock, parent) = lock_directory(parent_number)?;
+    let _inode_lock = lock_inode(inode_number);
+
+    let mut inode = get_inode(inode_number);
+    if inode._type != INodeType::FILE {
+        return Err(FileSystemError::NotPermitted);
+    }
+
+    // The file was removed since its lookup
+    if inode.number_links == 0 {
+        return Err(FileSystemError::NotFound);
+    }
+
+    if inode.number_links == u8::MAX {
+        return Err(FileSystemError::TooManyLinks);
+    }
+
+    if find_directory_entry(&parent, name).is_some() {
+        return Err(FileSystemError::AlreadyExists);
+    }
+
+    add_directory_entry(parent_number, name, inode_number)?;
+
+    inode.number_links += 1;
+    write_inode(inode_number, &inode);
+    Ok(())
+}
+
+/// Move the entry at old_path to new_path. Both paths must be normalized. The destination must
+/// not exist, and a directory cannot be moved inside itself. Renames are serialized, since they are
+/// the only operations that change which directories are ancestors of others: no rename can then
+/// invalidate the check of the paths, nor the order in which both parents are locked.
+pub fn rename(old_path: &str, new_path: &str) -> Result<(), FileSystemError> {
+    let (old_parent_path, old_name) = split_path(old_path);
+    let (new_parent_path, new_name) = split_path(new_path);
+    if matches!(old_name, "" | "." | "..") || matches!(new_name, "" | "." | "..") {
+        return Err(FileSystemError::InvalidPath);
+    }
+
+    if new_path.starts_with(old_path) && new_path[old_path.len()..].starts_with('/') {
+        return Err(FileSystemError::InvalidPath);
+    }
+
+    let _rename_lock = RENAME_LOCK.lock(());
+    let old_parent_number = find_directory_by_path(old_parent_path)?;
+    let new_parent_number = find_directory_by_path(new_parent_path)?;
+
+    // Directories are locked before their descendants. Unrelated ones can be locked in any order,
+    // since no other operation holds the lock of one directory while waiting for another one.
+    let is_new_parent_inside = new_parent_path.starts_with(old_parent_path);
+    let (first_number, second_number) = match is_new_parent_inside {
+        true => (old_parent_number, new_parent_number),
+        false => (new_parent_number, old_parent_number),
+    };
+
+    let (_first_lock, first_parent) = lock_directory(first_number)?;
+    let (_second_lock, second_parent) = match second_number == first_number {
+        true => (None, first_parent),
+        false => {
+            let (guard, directory) = lock_directory(second_number)?;
+            (Some(guard), directory)
+        }
+    };
+
+    let (old_parent, new_parent) = match is_new_parent_inside {
+        true => (first_parent, second_parent),
+        false => (second_parent, first_parent),
+    };
+
+    if find_directory_entry(&new_parent, new_name).is_some() {
+        return Err(FileSystemError::AlreadyExists);
+    }
+
+    let (index, inode_number) =
+        find_directory_entry(&old_parent, old_name).ok_or(FileSystemError::NotFound)?;
+    add_directory_entry(new_parent_number, new_name, inode_number)?;
+    remove_directory_entry(old_parent_number, index)?;
+
+    // A directory that changes parent must have its ".." entry updated
+    let _inode_lock = lock_inode(inode_number);
+    let inode = get_inode(inode_number);
+    if inode._type == INodeType::DIRECTORY && old_parent_number != new_parent_number {
+        let (index, _) = find_directory_entry(&inode, "..").ok_or(FileSystemError::NotFound)?;
+        write_directory_entry(inode_number, index, "..", new_parent_number)?;
     }
 
-    Some(current_inode)
+    Ok(())
 }
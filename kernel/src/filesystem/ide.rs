@@ -7,7 +7,7 @@ use alloc::sync::Arc;
 use crate::{
     apic::{defs::IRQ_IDE, io_apic::enable_irq, local_apic::local_apic_acknowledge},
     devices::pci::{PCIDevice, IS_PCI_MAPPED, PCI_DEVICES},
-    scheduler::sleep::wakeup,
+    scheduler::sleep::{sleep, wakeup},
     structures::heap_linked_list::HeapLinkedList,
     sync::spin_mutex::SpinMutex,
     x86::helpers::{inb, insd, outb, outsd},
@@ -49,6 +49,7 @@ pub struct DiskBlock {
     pub status: DiskRequestStatus,
     pub device: u32,
     pub block_number: u32,
+    pub reference_count: u32, // Number of users of the block in the buffer cache
     pub data: [u8; BLOCK_SIZE],
 }
 
@@ -194,13 +195,29 @@ pub fn interrupt_ide() {
 
 /// Request IDE operation, either read or write, as defined by the DiskBlock request.
 /// Every request is added to a queue, for which each block is later sent to the IDE to processed.
+/// Sleeps until the request has been fulfilled.
 pub fn request_ide(block: Arc<SpinMutex<DiskBlock>>) {
     let mut ide_queue = IDE_QUEUE.lock();
     ide_queue.push(Arc::clone(&block));
 
     if ide_queue.size == 1 {
         let next = ide_queue.peek().unwrap().value.as_ref();
-        unsafe { IDE_QUEUE.force_unlock() };
         start_ide_request(next);
     }
+
+    // The interrupt fulfills requests while holding the queue, which is only released once asleep
+    loop {
+        let (address, is_fulfilled) = {
+            let block = block.lock();
+            let is_fulfilled = block.status == DiskRequestStatus::READY && !block.dirty;
+            (block.get_address(), is_fulfilled)
+        };
+
+        if is_fulfilled {
+            return;
+        }
+
+        sleep(address, ide_queue);
+        ide_queue = IDE_QUEUE.lock();
+    }
 }
@@ -1,14 +1,27 @@
-use crate::{memory::mem::mem_move, sync::spin_mutex::SpinMutex};
+/// Write-ahead log. File system calls group their block writes into transactions, delimited by
+/// begin_operation and end_operation. Blocks modified by a transaction are only recorded in the
+/// log header (write_block_log) and kept in cache. Once no operation is outstanding, all of them
+/// are committed at once: first copied into the log area, then the header is written (commit
+/// point), and only then blocks are installed at their home location. If the system crashes
+/// before the header is written, the transaction is lost as a whole. If it crashes after, the
+/// log is replayed on the next mount (recover_log). Either way, the disk stays consistent.
+use crate::{
+    scheduler::sleep::{sleep, wakeup},
+    sync::spin_mutex::SpinMutex,
+};
 
 use super::{
-    cache::{read_disk_block, write_disk_block, CacheBlock},
+    cache::{read_disk_block, release_disk_block, write_disk_block, CacheBlock},
     fs::{SECONDARY_BLOCK_ID, SUPER_BLOCK_CACHE},
-    ide::BLOCK_SIZE,
 };
 
 const LOG_BLOCK_SIZE: usize = 30;
 
+/// Maximum number of distinct blocks a single operation may write
+pub const MAX_OPERATION_BLOCKS: u32 = 13;
+
 #[repr(C)]
+#[derive(Clone, Copy)]
 struct DiskLogHeader {
     count: u32,                    // Number of blocks to commit
     blocks: [u32; LOG_BLOCK_SIZE], // Blocks to commit
@@ -38,10 +51,20 @@ impl DiskLog {
             },
         }
     }
+
+    /// Number of blocks the log can hold, which is bound by both the header and the log area
+    /// (minus the header block itself).
+    fn capacity(&self) -> u32 {
+        core::cmp::min(LOG_BLOCK_SIZE as u32, self.size - 1)
+    }
 }
 
 static DISK_LOG: SpinMutex<DiskLog> = SpinMutex::new(DiskLog::new());
 
+fn get_log_address() -> usize {
+    &DISK_LOG as *const SpinMutex<DiskLog> as usize
+}
+
 pub fn setup_log() {
     let mut disk_log = DISK_LOG.lock();
     let super_block = SUPER_BLOCK_CACHE.lock();
@@ -51,103 +74,154 @@ pub fn setup_log() {
     disk_log.start = super_block.log_start_address;
 }
 
-pub fn read_log_header() {
-    let mut disk_log = DISK_LOG.lock();
+/// Load the header from disk into memory
+fn read_log_header() {
+    let (dev, start) = {
+        let disk_log = DISK_LOG.lock();
+        (disk_log.dev, disk_log.start)
+    };
 
-    let in_disk_log_header = read_disk_block(disk_log.dev, disk_log.start);
-    let mut header_data_lock = in_disk_log_header.lock();
-    let header_data = header_data_lock.cast_to::<DiskLogHeader>();
-    disk_log.header.count = header_data[0].count;
+    let block = read_disk_block(dev, start);
+    let header = block.lock().cast_to::<DiskLogHeader>()[0];
+    release_disk_block(block);
 
-    for i in 0..disk_log.header.count {
-        disk_log.header.blocks[i as usize] = header_data[0].blocks[i as usize];
-    }
+    DISK_LOG.lock().header = header;
 }
 
-pub fn write_log_header() {
-    let disk_log = DISK_LOG.lock();
-    let in_disk_log_header = read_disk_block(disk_log.dev, disk_log.start);
-    let mut header_data_lock = in_disk_log_header.lock();
-    let header_data = header_data_lock.cast_to::<DiskLogHeader>();
-    header_data[0].count = disk_log.header.count;
+/// Write the in-memory header to disk. This is the point at which a transaction is committed.
+fn write_log_header() {
+    let (dev, start, header) = {
+        let disk_log = DISK_LOG.lock();
+        (disk_log.dev, disk_log.start, disk_log.header)
+    };
 
-    for i in 0..disk_log.header.count {
-        header_data[0].blocks[i as usize] = disk_log.header.blocks[i as usize];
-    }
+    let block = read_disk_block(dev, start);
+    block.lock().cast_to::<DiskLogHeader>()[0] = header;
+    release_disk_block(write_disk_block(block));
+}
 
-    unsafe { in_disk_log_header.force_unlock() };
+/// Copy the blocks of the current transaction from the log area to their home location
+fn install_log_blocks() {
+    let (dev, start, header) = {
+        let disk_log = DISK_LOG.lock();
+        (disk_log.dev, disk_log.start, disk_log.header)
+    };
+
+    for i in 0..header.count {
+        let log_block = read_disk_block(dev, start + i + 1);
+        let disk_block = read_disk_block(dev, header.blocks[i as usize]);
+
+        let data = log_block.lock().data;
+        disk_block.lock().data = data;
+        release_disk_block(write_disk_block(disk_block));
+        release_disk_block(log_block);
+    }
+}
 
-    // Block already in cache, skips Rust borrow-checker
-    let new_block = read_disk_block(disk_log.dev, disk_log.start);
-    write_disk_block(new_block);
+/// Copy the modified blocks from cache into the log area
+fn write_log_blocks() {
+    let (dev, start, header) = {
+        let disk_log = DISK_LOG.lock();
+        (disk_log.dev, disk_log.start, disk_log.header)
+    };
+
+    for i in 0..header.count {
+        let log_block = read_disk_block(dev, start + i + 1);
+        let cache_block = read_disk_block(dev, header.blocks[i as usize]);
+
+        let data = cache_block.lock().data;
+        log_block.lock().data = data;
+        release_disk_block(write_disk_block(log_block));
+        release_disk_block(cache_block);
+    }
 }
 
+/// Replay a transaction that was committed but not fully installed before the system stopped.
+/// Must run when the file system is mounted, before any other operation.
 pub fn recover_log() {
     read_log_header();
-    write_log_blocks();
+    install_log_blocks();
     DISK_LOG.lock().header.count = 0;
+    write_log_header();
 }
 
-/// Write log blocks back to disk
-pub fn write_log_blocks() {
-    let disk_log = DISK_LOG.lock();
-
-    for (i, block_number) in disk_log.header.blocks.iter().enumerate() {
-        let log_block = read_disk_block(disk_log.dev, disk_log.start + i as u32 + 1);
-        let disk_block = read_disk_block(disk_log.dev, *block_number);
-        let log_block_ptr = log_block.lock().data.as_mut_ptr();
-        let disk_block_ptr = disk_block.lock().data.as_mut_ptr();
-        unsafe { mem_move(log_block_ptr, disk_block_ptr, BLOCK_SIZE) };
-        write_disk_block(disk_block);
+fn commit_log() {
+    let count = DISK_LOG.lock().header.count;
+
+    if count > 0 {
+        write_log_blocks();
+        write_log_header();
+        install_log_blocks();
+        DISK_LOG.lock().header.count = 0;
+        write_log_header();
     }
 }
 
-/// Moves blocks from cache to log
-pub fn move_cache_to_log() {
-    let disk_log = DISK_LOG.lock();
-
-    for (i, block_number) in disk_log.header.blocks.iter().enumerate() {
-        let to_block = read_disk_block(disk_log.dev, disk_log.start + i as u32 + 1);
-        let from_block = read_disk_block(disk_log.dev, *block_number);
-        let to_block_ptr = to_block.lock().data.as_mut_ptr();
-        let from_block_ptr = from_block.lock().data.as_mut_ptr();
-        unsafe { mem_move(to_block_ptr, from_block_ptr, BLOCK_SIZE) };
-        write_disk_block(from_block);
+/// Start a file system operation. Waits while the log is being committed or while there is not
+/// enough space left in the log for this operation to write MAX_OPERATION_BLOCKS blocks.
+pub fn begin_operation() {
+    loop {
+        let mut disk_log = DISK_LOG.lock();
+
+        let reserved = disk_log.header.count + (disk_log.outstanding + 1) * MAX_OPERATION_BLOCKS;
+        if disk_log.commiting || reserved > disk_log.capacity() {
+            sleep(get_log_address(), disk_log);
+            continue;
+        }
+
+        disk_log.outstanding += 1;
+        return;
     }
 }
 
-pub fn commit_log() {
-    let mut disk_log = DISK_LOG.lock();
+/// Finish a file system operation. The last outstanding operation commits the transaction.
+pub fn end_operation() {
+    let should_commit = {
+        let mut disk_log = DISK_LOG.lock();
 
-    if disk_log.header.count > 0 {
-        move_cache_to_log();
-        write_log_header();
-        write_log_blocks();
-        disk_log.header.count = 0;
-        write_log_header();
+        if disk_log.commiting {
+            panic!("[FATAL] FS operation ended during commit");
+        }
+
+        disk_log.outstanding -= 1;
+        disk_log.commiting = disk_log.outstanding == 0;
+        disk_log.commiting
+    };
+
+    if should_commit {
+        commit_log();
+        DISK_LOG.lock().commiting = false;
     }
+
+    // Operations waiting on begin_operation may now fit in the log
+    wakeup(get_log_address());
 }
 
-pub fn write_block_log(block: CacheBlock) {
+/// Record the block as part of the current transaction, in place of writing it to disk. The
+/// block data stays in cache until the transaction commits. Writing the same block more than
+/// once in a transaction only takes a single log slot (absorption). The caller still owns its
+/// reference to the block, and must release it.
+pub fn write_block_log(block: &CacheBlock) {
+    let block_number = block.lock().block_number;
     let mut disk_log = DISK_LOG.lock();
 
-    if disk_log.header.count >= LOG_BLOCK_SIZE as u32 || disk_log.header.count >= disk_log.size - 1
-    {
-        panic!("[ERROR] FS Log is too big");
+    if disk_log.outstanding == 0 {
+        panic!("[FATAL] FS write outside of an operation");
     }
 
-    let mut index = 0;
-    for block_number in disk_log.header.blocks {
-        if block_number == block.lock().block_number {
-            break;
-        }
-
-        index += 1;
+    let count = disk_log.header.count as usize;
+    if disk_log.header.blocks[..count].contains(&block_number) {
+        return;
     }
 
-    if index == disk_log.header.count {
-        disk_log.header.count += 1;
+    if disk_log.header.count >= disk_log.capacity() {
+        panic!("[ERROR] FS Log is too big");
     }
 
+    disk_log.header.blocks[count] = block_number;
+    disk_log.header.count += 1;
+
+    // Data in cache is now ahead of the disk. Dirty blocks are never recycled by the cache, which
+    // keeps them in memory until the commit installs them.
     block.lock().dirty = true;
 }
@@ -1,4 +1,7 @@
 pub mod cache;
+pub mod error;
+pub mod file;
 pub mod fs;
 pub mod ide;
 pub mod log;
+pub mod pipe;
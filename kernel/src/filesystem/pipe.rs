@@ -0,0 +1,164 @@
+/// Pipes connect two open files through a kernel ring buffer: bytes written into the write end
+/// are read, in order, from the read end. Readers sleep while the buffer is empty and writers
+/// sleep while it is full. Once every write end is closed, readers get the end of the file, and
+/// once every read end is closed, writes fail.
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    scheduler::sleep::{sleep_killable, wakeup},
+    sync::spin_mutex::SpinMutex,
+};
+
+use super::{
+    error::FileSystemError,
+    file::{File, FileDescriptor, FileType},
+};
+
+pub const PIPE_SIZE: usize = 512; // Bytes held by a pipe before writers have to wait
+
+#[derive(Debug)]
+pub struct Pipe {
+    data: [u8; PIPE_SIZE],
+    read_count: usize,  // Total number of bytes read
+    write_count: usize, // Total number of bytes written
+    read_open: bool,    // Read end is still open
+    write_open: bool,   // Write end is still open
+}
+
+/// One end of a pipe. It is shared by every file descriptor of that end (including the ones
+/// inherited through fork), so that the end is only closed once the last of them is dropped.
+#[derive(Debug)]
+pub struct PipeEnd {
+    pipe: Arc<SpinMutex<Pipe>>,
+    writable: bool,
+}
+
+impl Pipe {
+    pub const fn new() -> Self {
+        Pipe {
+            data: [0; PIPE_SIZE],
+            read_count: 0,
+            write_count: 0,
+            read_open: true,
+            write_open: true,
+        }
+    }
+
+    // Readers wait for writes, and writers wait for reads
+    fn read_address(&self) -> usize {
+        &self.read_count as *const usize as usize
+    }
+
+    fn write_address(&self) -> usize {
+        &self.write_count as *const usize as usize
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        let mut pipe = self.pipe.lock();
+
+        let address = match self.writable {
+            true => {
+                pipe.write_open = false;
+                pipe.read_address()
+            }
+            false => {
+                pipe.read_open = false;
+                pipe.write_address()
+            }
+        };
+
+        drop(pipe);
+
+        // Processes waiting on the other end must learn that this one is gone
+        wakeup(address);
+    }
+}
+
+/// Create a pipe. Returns its read and write ends, in that order.
+pub fn create_pipe() -> (FileDescriptor, FileDescriptor) {
+    let pipe = Arc::new(SpinMutex::new(Pipe::new()));
+
+    let mut read_end = File::new(FileType::PIPE, true, false);
+    read_end.pipe = Some(Arc::new(PipeEnd {
+        pipe: Arc::clone(&pipe),
+        writable: false,
+    }));
+
+    let mut write_end = File::new(FileType::PIPE, false, true);
+    write_end.pipe = Some(Arc::new(PipeEnd {
+        pipe,
+        writable: true,
+    }));
+
+    (
+        Arc::new(SpinMutex::new(read_end)),
+        Arc::new(SpinMutex::new(write_end)),
+    )
+}
+
+/// Reads up to length bytes from the pipe, sleeping until some data is available. An empty
+/// buffer indicates that the pipe is empty and its write end is closed.
+pub fn read_pipe(end: &PipeEnd, length: usize) -> Vec<u8> {
+    let mut pipe = end.pipe.lock();
+
+    while pipe.read_count == pipe.write_count && pipe.write_open {
+        // A killed reader returns nothing, and exits on its way back to user space
+        let address = pipe.write_address();
+        if sleep_killable(address, pipe) {
+            return Vec::new();
+        }
+        pipe = end.pipe.lock();
+    }
+
+    let available = pipe.write_count - pipe.read_count;
+    let mut data = Vec::with_capacity(core::cmp::min(length, available));
+
+    while data.len() < length && pipe.read_count < pipe.write_count {
+        data.push(pipe.data[pipe.read_count % PIPE_SIZE]);
+        pipe.read_count += 1;
+    }
+
+    let address = pipe.read_address();
+    drop(pipe);
+
+    // Writers waiting for free space can continue
+    wakeup(address);
+    data
+}
+
+/// Writes the whole buffer into the pipe, sleeping whenever it is full. Fails if the read end is
+/// closed, since nobody could ever read the data.
+pub fn write_pipe(end: &PipeEnd, buffer: &[u8]) -> Result<usize, FileSystemError> {
+    let mut pipe = end.pipe.lock();
+    let mut count = 0;
+
+    while count < buffer.len() {
+        if !pipe.read_open {
+            return Err(FileSystemError::BrokenPipe);
+        }
+
+        if pipe.write_count - pipe.read_count == PIPE_SIZE {
+            // Let readers empty the buffer before writing the rest
+            let (read_address, write_address) = (pipe.read_address(), pipe.write_address());
+            wakeup(write_address);
+            if sleep_killable(read_address, pipe) {
+                return Ok(count);
+            }
+            pipe = end.pipe.lock();
+            continue;
+        }
+
+        let index = pipe.write_count % PIPE_SIZE;
+        pipe.data[index] = buffer[count];
+        pipe.write_count += 1;
+        count += 1;
+    }
+
+    let address = pipe.write_address();
+    drop(pipe);
+
+    wakeup(address);
+    Ok(count)
+}
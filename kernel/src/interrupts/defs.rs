@@ -14,6 +14,29 @@ pub mod system_call {
     pub const WAIT: usize = 7;
     pub const PRINT: usize = 8;
     pub const SBRK: usize = 9;
+    pub const OPEN: usize = 10;
+    pub const READ: usize = 11;
+    pub const WRITE: usize = 12;
+    pub const CLOSE: usize = 13;
+    pub const DUP: usize = 14;
+    pub const MKDIR: usize = 15;
+    pub const UNLINK: usize = 16;
+    pub const LINK: usize = 17;
+    pub const RENAME: usize = 18;
+    pub const CHDIR: usize = 19;
+    pub const PIPE: usize = 20;
+    pub const FRAME_STATS: usize = 21;
+    pub const MMAP: usize = 22;
+    pub const MUNMAP: usize = 23;
+    pub const SHM_CREATE: usize = 24;
+    pub const SHM_ATTACH: usize = 25;
+    pub const SHM_DETACH: usize = 26;
+    pub const HEAP_STATS: usize = 27;
+    pub const SET_PRIORITY: usize = 28;
+    pub const PROCESS_STATS: usize = 29;
+    pub const UPTIME: usize = 30;
+    pub const CLONE: usize = 31;
+    pub const JOIN: usize = 32;
 }
 
 /// Structure of a pointer to a IDT. Must be passed in this format
@@ -0,0 +1,116 @@
+use crate::{
+    filesystem::error::FileSystemError,
+    memory::error::MemoryError,
+    scheduler::error::{ELFError, ProcessError, SharedMemoryError},
+};
+
+/// Errors returned to the user by System Calls. A failed System Call returns the negated code in
+/// eax, so any value from -1 to -4095 is an error. The codes follow the usual Unix errno numbers
+/// and must not change, since user programs depend on them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SystemCallError {
+    NotPermitted = 1,        // EPERM
+    NotFound = 2,            // ENOENT
+    NoSuchProcess = 3,       // ESRCH
+    ArgumentListTooLong = 7, // E2BIG
+    InvalidExecutable = 8,   // ENOEXEC
+    BadFileDescriptor = 9,   // EBADF
+    NoChildProcess = 10,     // ECHILD
+    TryAgain = 11,           // EAGAIN
+    OutOfMemory = 12,        // ENOMEM
+    BadAddress = 14,         // EFAULT
+    AlreadyExists = 17,      // EEXIST
+    NotADirectory = 20,      // ENOTDIR
+    IsADirectory = 21,       // EISDIR
+    InvalidArgument = 22,    // EINVAL
+    TooManyOpenFiles = 24,   // EMFILE
+    NoSpace = 28,            // ENOSPC
+    TooManyLinks = 31,       // EMLINK
+    BrokenPipe = 32,         // EPIPE
+    NameTooLong = 36,        // ENAMETOOLONG
+    InvalidSystemCall = 38,  // ENOSYS
+    DirectoryNotEmpty = 39,  // ENOTEMPTY
+}
+
+impl SystemCallError {
+    /// Value placed in eax when the System Call fails
+    pub fn code(self) -> isize {
+        -(self as isize)
+    }
+}
+
+impl From<MemoryError> for SystemCallError {
+    fn from(error: MemoryError) -> Self {
+        match error {
+            MemoryError::OutOfMemory
+            | MemoryError::HeapUnavailable
+            | MemoryError::MemorySpaceViolation => SystemCallError::OutOfMemory,
+            MemoryError::InvalidUserAddress(_) | MemoryError::PageNotFound(_) => {
+                SystemCallError::BadAddress
+            }
+            MemoryError::InvalidPhysicalTop(_)
+            | MemoryError::PageRemapped(_)
+            | MemoryError::ReadFailure => SystemCallError::InvalidArgument,
+        }
+    }
+}
+
+impl From<ProcessError> for SystemCallError {
+    fn from(error: ProcessError) -> Self {
+        match error {
+            ProcessError::SlotAllocationFailure => SystemCallError::TryAgain,
+            ProcessError::MemoryAllocationFailure => SystemCallError::OutOfMemory,
+            ProcessError::NotFound => SystemCallError::NoSuchProcess,
+            ProcessError::NotPermitted => SystemCallError::NotPermitted,
+            ProcessError::InvalidPriority => SystemCallError::InvalidArgument,
+        }
+    }
+}
+
+impl From<ELFError> for SystemCallError {
+    fn from(error: ELFError) -> Self {
+        match error {
+            ELFError::ELFOverflow(_, _)
+            | ELFError::InvalidMemorySize(_, _)
+            | ELFError::InvalidELFMagic(_)
+            | ELFError::ReadFailure => SystemCallError::InvalidExecutable,
+            ELFError::KernelMappingFailure | ELFError::MemoryAllocationFailure => {
+                SystemCallError::OutOfMemory
+            }
+            ELFError::ArgumentsOverflow => SystemCallError::ArgumentListTooLong,
+            ELFError::Killed => SystemCallError::NotPermitted,
+        }
+    }
+}
+
+impl From<SharedMemoryError> for SystemCallError {
+    fn from(error: SharedMemoryError) -> Self {
+        match error {
+            SharedMemoryError::AlreadyExists => SystemCallError::AlreadyExists,
+            SharedMemoryError::NotFound => SystemCallError::NotFound,
+            SharedMemoryError::InvalidSize => SystemCallError::InvalidArgument,
+            SharedMemoryError::MemoryAllocationFailure => SystemCallError::OutOfMemory,
+        }
+    }
+}
+
+impl From<FileSystemError> for SystemCallError {
+    fn from(error: FileSystemError) -> Self {
+        match error {
+            FileSystemError::NotFound => SystemCallError::NotFound,
+            FileSystemError::AlreadyExists => SystemCallError::AlreadyExists,
+            FileSystemError::NotADirectory => SystemCallError::NotADirectory,
+            FileSystemError::IsADirectory => SystemCallError::IsADirectory,
+            FileSystemError::DirectoryNotEmpty => SystemCallError::DirectoryNotEmpty,
+            FileSystemError::InvalidPath | FileSystemError::InvalidData => {
+                SystemCallError::InvalidArgument
+            }
+            FileSystemError::NameTooLong => SystemCallError::NameTooLong,
+            FileSystemError::NoSpace => SystemCallError::NoSpace,
+            FileSystemError::TooManyLinks => SystemCallError::TooManyLinks,
+            FileSystemError::NotPermitted => SystemCallError::NotPermitted,
+            FileSystemError::BadFileMode => SystemCallError::BadFileDescriptor,
+            FileSystemError::BrokenPipe => SystemCallError::BrokenPipe,
+        }
+    }
+}
@@ -168,7 +168,12 @@ lazy_static! {
     };
 }
 
-pub fn setup_idt() {
+/// Every CPU shares the same IDT, which must be loaded by each one of them
+pub fn setup_cpu_idt() {
     GLOBAL_IDT.load();
+}
+
+pub fn setup_idt() {
+    setup_cpu_idt();
     println!("[KERNEL] Interrupt Table Initialized");
 }
@@ -1,18 +1,23 @@
 use crate::{
     apic::local_apic::local_apic_acknowledge,
-    interrupts::system_calls::exit,
+    interrupts::system_calls::{exit, KILLED_EXIT_CODE},
     memory::{
-        defs::{Page, PTE_U},
-        vm::walk_page_dir,
+        defs::{Page, PTE_NX, PTE_P, PTE_U, PTE_W},
+        vm::{copy_on_write, walk_page_dir},
     },
     println,
-    scheduler::{defs::process::TrapFrame, scheduler::SCHEDULER},
-    x86::helpers::read_cr2,
+    scheduler::{
+        defs::process::TrapFrame,
+        mmap::fault_in_page,
+        process::{is_current_killed, lock_process_memory},
+        scheduler::SCHEDULER,
+    },
+    x86::{defs::PrivilegeLevel, helpers::read_cr2},
 };
 
 use super::{
     defs::{InterruptStackFrame, PageFaultErr},
-    irqs::handle_irq,
+    irqs::{check_kernel_tick, handle_irq},
     system_calls::handle_system_call,
 };
 
@@ -24,42 +29,81 @@ pub extern "x86-interrupt" fn breakpoint_handler(frame: InterruptStackFrame) {
     println!("EXCEPTION: BREAKPOINT\n{:#?}", frame);
 }
 
-pub extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, _error_code: PageFaultErr) {
+pub extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, error_code: PageFaultErr) {
     let address = read_cr2();
 
-    let scheduler = unsafe { SCHEDULER.lock() };
-
-    if scheduler.current_process.is_none() {
+    // The scheduler is not held, since the process may be killed and never return here. Faults
+    // of the scheduler or of kernel threads, which have no user memory, are Kernel bugs.
+    let process = unsafe { SCHEDULER.lock().get_current_process() };
+    let page_dir_ptr = process.as_ref().and_then(|process| process.lock().pgdir);
+    let (Some(process), Some(page_dir_ptr)) = (process, page_dir_ptr) else {
         panic!(
             "[FATAL] Kernel produced a page fault\nEIP: 0x{:X}\nCR2: 0x{:X}\n",
             frame.instruction_pointer, address
         );
-    }
+    };
 
-    let process = scheduler.current_process.as_ref().unwrap();
-    let page_dir_ptr = process.lock().pgdir.unwrap();
     let mut page_dir = Page::new(page_dir_ptr as *mut u8);
     let page_entry = walk_page_dir(&mut page_dir, address, false);
 
     // Process hits the return trap. It has finished execution and should be killed.
     if address == 0xFFFFFFFF {
         println!("[WARNING] Return Trap - {}", process.lock().name);
-        exit();
+        exit(KILLED_EXIT_CODE);
     }
 
+    // Threads resolve the faults on the memory they share one at a time, so the fault may have been
+    // resolved by another thread while this one waited for the lock
+    let memory_lock = lock_process_memory();
+    if is_fault_resolved(&mut page_dir, address, error_code) {
+        return;
+    }
+
+    // Writes to pages shared by fork get their own copy of the page and can be retried
+    let copy_on_write_fault =
+        PageFaultErr::CPL_USER | PageFaultErr::WRITE_FAILURE | PageFaultErr::FAILURE_TYPE;
+    if error_code.contains(copy_on_write_fault) && copy_on_write(&mut page_dir, address).is_ok() {
+        return;
+    }
+
+    // Pages reserved by sbrk or mmap are backed on their first access, which can then be retried
+    if !error_code.contains(PageFaultErr::FAILURE_TYPE)
+        && fault_in_page(&mut page_dir, address).is_ok()
+    {
+        return;
+    }
+
+    // A killed process never returns here to release the lock
+    drop(memory_lock);
+
     // Stack overflow happens when a write is performend on the guard page
     if page_entry.is_ok() && unsafe { *page_entry.unwrap() & PTE_U == 0 } {
         println!("[WARNING] Stack Overflow - {}", process.lock().name);
-        unsafe { SCHEDULER.force_unlock() };
-        exit();
+        exit(KILLED_EXIT_CODE);
     }
 
-    // If a page fault occurs while running the Kernel, we need to panic
-    if scheduler.current_process.is_none() {
-        panic!(
-            "[ERROR] Kernel Page Fault\nEIP: 0x{:X}\nCR2: 0x{:X}",
-            frame.instruction_pointer, address
-        )
+    // Writes to read-only pages, such as the program code, kill the process
+    let write_protection_fault = PageFaultErr::WRITE_FAILURE | PageFaultErr::FAILURE_TYPE;
+    if error_code.contains(write_protection_fault) {
+        println!(
+            "\n[WARNING] Write to Read-Only Memory\nProcess Name: {}\nEIP: 0x{:X}\nCR2: 0x{:X}\n",
+            process.lock().name,
+            frame.instruction_pointer,
+            address
+        );
+        exit(KILLED_EXIT_CODE);
+    }
+
+    // So do instructions fetched from non-executable pages, such as the stack
+    let execute_protection_fault = PageFaultErr::INST_FETCH | PageFaultErr::FAILURE_TYPE;
+    if error_code.contains(execute_protection_fault) {
+        println!(
+            "\n[WARNING] Execution of Non-Executable Memory\nProcess Name: {}\nEIP: 0x{:X}\nCR2: 0x{:X}\n",
+            process.lock().name,
+            frame.instruction_pointer,
+            address
+        );
+        exit(KILLED_EXIT_CODE);
     }
 
     println!(
@@ -69,7 +113,7 @@ pub extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, _error_code
         address
     );
 
-    exit();
+    exit(KILLED_EXIT_CODE);
 }
 
 pub extern "x86-interrupt" fn non_maskable(frame: InterruptStackFrame) {
@@ -104,14 +148,41 @@ pub extern "x86-interrupt" fn general_irq_handler(_frame: InterruptStackFrame) {
     local_apic_acknowledge();
 }
 
+/// Whether the access that faulted would now succeed, as when another thread backed the page
+fn is_fault_resolved(page_dir: &mut Page, address: usize, error_code: PageFaultErr) -> bool {
+    let Ok(page_entry) = walk_page_dir(page_dir, address, false) else {
+        return false;
+    };
+
+    let entry = unsafe { *page_entry };
+    let mut required_flags = PTE_P | PTE_U;
+    if error_code.contains(PageFaultErr::WRITE_FAILURE) {
+        required_flags |= PTE_W;
+    }
+
+    let can_execute = !error_code.contains(PageFaultErr::INST_FETCH) || entry & PTE_NX == 0;
+    entry & required_flags == required_flags && can_execute
+}
+
 #[no_mangle]
 extern "C" fn interrupt_manager(trapframe: &mut TrapFrame) -> isize {
     // If Trap Number is 64, then this is a System Call, and not an IRQ
-    if trapframe.trap_number == 64 {
-        let output = handle_system_call(trapframe);
-        return output.unwrap_or(0x0) as isize;
+    let output = if trapframe.trap_number == 64 {
+        match handle_system_call(trapframe) {
+            Ok(output) => output as isize,
+            Err(error) => error.code(),
+        }
+    } else {
+        handle_irq(trapframe);
+        0x0
+    };
+
+    check_kernel_tick();
+
+    // Threads killed by another thread calling exec exit instead of returning to user space
+    if (trapframe.cs & 0x3) == PrivilegeLevel::Ring3 as u16 && is_current_killed() {
+        exit(KILLED_EXIT_CODE);
     }
 
-    handle_irq(trapframe);
-    0x0
+    output
 }
@@ -1,15 +1,23 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use crate::{
     apic::{
-        defs::{IRQ_COM1, IRQ_IDE, IRQ_KEYBOARD, IRQ_TIMER},
-        local_apic::local_apic_acknowledge,
+        defs::{IRQ_COM1, IRQ_IDE, IRQ_KEYBOARD, IRQ_TIMER, IRQ_TLB_FLUSH},
+        local_apic::{local_apic_acknowledge, local_apic_is_pending},
+        mp::get_my_cpu,
     },
     devices::console::CONSOLE,
     filesystem::ide::interrupt_ide,
-    scheduler::{defs::process::TrapFrame, scheduler::SCHEDULER},
+    memory::vm::handle_tlb_flush,
+    scheduler::{defs::process::TrapFrame, policy, scheduler::SCHEDULER, sleep::wakeup},
+    x86::defs::PrivilegeLevel,
 };
 
 use super::system_calls::_yield;
 
+/// Timer ticks since the Kernel started, counted by the bootstrap processor only
+pub static TICKS: AtomicUsize = AtomicUsize::new(0);
+
 pub fn handle_irq(trapframe: &mut TrapFrame) {
     let irq_number = trapframe.trap_number - 32;
 
@@ -18,18 +26,49 @@ pub fn handle_irq(trapframe: &mut TrapFrame) {
         IRQ_COM1 => keyboard(trapframe),
         IRQ_KEYBOARD => keyboard(trapframe),
         IRQ_IDE => interrupt_ide(),
+        IRQ_TLB_FLUSH => {
+            local_apic_acknowledge();
+            handle_tlb_flush();
+        }
         _ => local_apic_acknowledge(),
     }
 }
 
-fn timer(_trapframe: &mut TrapFrame) {
-    // Do Something Here
+fn timer(trapframe: &mut TrapFrame) {
+    let cpu = get_my_cpu();
+    if cpu.bootstrap {
+        TICKS.fetch_add(1, Ordering::Relaxed);
+        wakeup(&TICKS as *const AtomicUsize as usize);
+    }
+
     local_apic_acknowledge();
 
-    // Clock Tick: Yield
-    let is_running_process = unsafe { SCHEDULER.lock().current_process.is_some() };
-    if is_running_process {
-        _yield();
+    let in_kernel = cpu.kernel_tick.swap(false, Ordering::Relaxed)
+        || (trapframe.cs & 0x3) == PrivilegeLevel::Ring0 as u16;
+
+    // Clock Tick: Yield, once the scheduling policy says the process has run for long enough
+    let current_process = unsafe { SCHEDULER.lock().get_current_process() };
+    if let Some(process) = current_process {
+        let mut process_lock = process.lock();
+        if in_kernel {
+            process_lock.kernel_ticks += 1;
+        } else {
+            process_lock.user_ticks += 1;
+        }
+        drop(process_lock);
+
+        if policy::tick(&process) {
+            _yield();
+        }
+    }
+}
+
+/// The Kernel runs with interrupts disabled, so a timer tick that goes by while handling a trap is
+/// only delivered once back in the process, and would count as user time. Must be called right
+/// before returning from the trap, so that the timer counts that tick as Kernel time instead.
+pub fn check_kernel_tick() {
+    if local_apic_is_pending(IRQ_TIMER) {
+        get_my_cpu().kernel_tick.store(true, Ordering::Relaxed);
     }
 }
 
@@ -1,4 +1,5 @@
 pub mod defs;
+pub mod error;
 pub mod idt;
 pub mod interrupt_handlers;
 pub mod irqs;
@@ -1,30 +1,54 @@
-use core::slice::from_raw_parts;
+use core::{convert::TryInto, sync::atomic::Ordering};
 
-use alloc::string::ToString;
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 
 use crate::{
-    filesystem::fs::{find_inode_by_path, get_path_filename, setup_file_system},
-    interrupts::defs::system_call as SystemCall,
+    filesystem::{
+        file::{open_file, read_file, write_file, FileDescriptor, FileType},
+        fs::{
+            create_inode, find_inode_by_path, find_inode_number_by_path, get_inode,
+            get_path_filename, link, rename, setup_file_system, unlink, INodeType,
+        },
+        log::{begin_operation, end_operation},
+        pipe::create_pipe,
+    },
+    interrupts::{defs::system_call as SystemCall, error::SystemCallError, irqs::TICKS},
+    memory::{
+        defs::{Page, KERNEL_BASE, PAGE_SIZE},
+        frame::get_frame_stats,
+        heap::get_heap_stats,
+        vm::{check_user_range, copy_from_user, copy_to_user},
+    },
     println,
     scheduler::{
-        defs::process::{Process, ProcessState, TrapFrame},
-        exec::exec,
-        process::{fork, resize_current_process_memory, wait},
-        scheduler::SCHEDULER,
-        sleep::wakeup,
+        defs::process::{
+            Process, ProcessState, TrapFrame, INIT_PROCESS_ID, MAP_ANONYMOUS, NO_PARENT,
+            PROCESS_NAME_LENGTH, PROT_WRITE,
+        },
+        exec::{exec, MAX_ARGUMENTS, MAX_ARGUMENTS_SIZE},
+        mmap::{map_memory, unmap_memory},
+        process::{
+            clone, fork, free_process_memory, get_process_stats, join, reap_process,
+            resize_current_process_memory, set_priority, wait,
+        },
+        scheduler::{PROCESS_LIST, SCHEDULER},
+        shm::{attach_segment, create_segment, detach_segment},
+        sleep::{wakeup, wakeup_locked},
     },
     sync::spin_mutex::SpinMutex,
 };
 
-/// If a call to an undefined System Call happens, panic and exit.
-fn panic_undefined_syscall() {
-    println!("[WARNING] Invalid system call");
-    exit();
-}
+/// Exit code of a process killed by the kernel, such as after a fault
+pub const KILLED_EXIT_CODE: i32 = -1;
 
 /// Every System Call passes through this handler. The trapframe is passed to facilitate loading
-/// the ABI registers and getting the system call number in eax.
-pub fn handle_system_call(trapframe: &mut TrapFrame) -> Option<usize> {
+/// the ABI registers and getting the system call number in eax. On failure, the error is returned
+/// to the user as a negative code (see SystemCallError).
+pub fn handle_system_call(trapframe: &mut TrapFrame) -> Result<usize, SystemCallError> {
     unsafe {
         // Update trapframe, which contains all registers of the process execution context
         SCHEDULER.lock().set_trapframe(trapframe);
@@ -34,53 +58,71 @@ pub fn handle_system_call(trapframe: &mut TrapFrame) -> Option<usize> {
     let arg0 = trapframe.edi;
     let arg1 = trapframe.edx;
     let arg2 = trapframe.ecx;
+    let arg3 = trapframe.ebx;
 
-    let output: Option<usize> = match system_call_number {
+    match system_call_number {
         SystemCall::PRINT_TRAP_FRAME => {
             print_trapframe();
-            None
+            Ok(0)
         }
         SystemCall::EXIT => {
-            exit();
-            None
+            exit(arg0 as i32);
+            Ok(0)
         }
         SystemCall::YIELD => {
             _yield();
-            None
+            Ok(0)
         }
         SystemCall::SETUP_FS => {
             setup_file_system();
-            None
+            Ok(0)
         }
         SystemCall::EXEC => {
-            let str_slice = unsafe { from_raw_parts(arg0 as *const u8, arg1) };
-            let path = core::str::from_utf8(str_slice).unwrap();
-            let inode = find_inode_by_path(path).unwrap();
-            exec(&inode, path);
-            None
-        }
-        SystemCall::FORK => {
-            fork();
-            None
-        }
-        SystemCall::WAIT => {
-            wait();
-            None
+            let path = get_user_path(arg0, arg1)?;
+            let arguments = get_user_arguments(arg2, arg3)?;
+            let inode_number = find_inode_number_by_path(&path).ok_or(SystemCallError::NotFound)?;
+            exec(inode_number, &path, &arguments)?;
+            Ok(0)
         }
+        SystemCall::FORK => Ok(fork()?),
+        SystemCall::WAIT => wait_child(arg0, arg1, arg2),
         SystemCall::PRINT => {
-            let str_slice = unsafe { from_raw_parts(arg0 as *const u8, arg1) };
-            let message = core::str::from_utf8(str_slice).unwrap();
+            let message = get_user_string(arg0, arg1)?;
             println!("{}", message);
-            None
+            Ok(0)
+        }
+        SystemCall::SBRK => Ok(resize_current_process_memory(arg0 as isize)?),
+        SystemCall::OPEN => open(&get_user_path(arg0, arg1)?, arg2),
+        SystemCall::READ => read(arg0, arg1, arg2),
+        SystemCall::WRITE => write(arg0, &copy_in(arg1, arg2)?),
+        SystemCall::CLOSE => close(arg0),
+        SystemCall::DUP => dup(arg0),
+        SystemCall::MKDIR => mkdir(&get_user_path(arg0, arg1)?),
+        SystemCall::UNLINK => remove(&get_user_path(arg0, arg1)?),
+        SystemCall::LINK => hard_link(&get_user_path(arg0, arg1)?, &get_user_path(arg2, arg3)?),
+        SystemCall::RENAME => move_path(&get_user_path(arg0, arg1)?, &get_user_path(arg2, arg3)?),
+        SystemCall::CHDIR => chdir(&get_user_path(arg0, arg1)?),
+        SystemCall::PIPE => pipe(arg0),
+        SystemCall::FRAME_STATS => frame_stats(arg0),
+        SystemCall::MMAP => mmap(arg0, arg1, arg2, arg3),
+        SystemCall::MUNMAP => munmap(arg0, arg1),
+        SystemCall::SHM_CREATE => Ok(create_segment(arg0, arg1)?),
+        SystemCall::SHM_ATTACH => Ok(attach_segment(arg0)?),
+        SystemCall::SHM_DETACH => {
+            detach_segment(arg0)?;
+            Ok(0)
         }
-        SystemCall::SBRK => Some(resize_current_process_memory(arg0).unwrap()),
+        SystemCall::HEAP_STATS => heap_stats(arg0),
+        SystemCall::SET_PRIORITY => Ok(set_priority(arg0, arg1)?),
+        SystemCall::PROCESS_STATS => process_stats(arg0, arg1),
+        SystemCall::CLONE => clone_thread(arg0, arg1, arg2),
+        SystemCall::JOIN => join_thread(arg0, arg1),
+        SystemCall::UPTIME => Ok(TICKS.load(Ordering::Relaxed)),
         _ => {
-            panic_undefined_syscall();
-            None
+            println!("[WARNING] Invalid system call {}", system_call_number);
+            Err(SystemCallError::InvalidSystemCall)
         }
-    };
-
-    output
+    }
 }
 
 pub fn print_trapframe() {
@@ -93,17 +135,510 @@ pub fn _yield() {
     unsafe { SCHEDULER.lock().resume() };
 }
 
-pub fn exit() {
-    let mut scheduler = unsafe { SCHEDULER.lock() };
-    let mut process = scheduler.current_process.as_mut().unwrap().lock();
+/// Terminates the current process. The process stays as a zombie, holding its exit code, until
+/// its parent waits for it. Its children are handed over to init, which reaps them instead.
+/// Exiting a thread only terminates that thread. The memory and open files it shares with the
+/// rest of its process are released once the last thread is gone.
+pub fn exit(code: i32) {
+    let scheduler = unsafe { SCHEDULER.lock() };
+    let process = scheduler.get_current_process().unwrap();
+    let leader = scheduler.get_current_leader().unwrap();
+    drop(scheduler);
 
-    if process.parent.is_some() {
-        let parent_process = process.parent.as_mut().unwrap().as_ref() as *const SpinMutex<Process>;
-        wakeup(parent_process as usize);
+    // Init can only be replaced by one of its threads calling exec (see end_other_threads)
+    if process.lock().pid == INIT_PROCESS_ID && !process.lock().killed {
+        panic!("[FATAL] Init process exited with code {}", code);
     }
 
-    unsafe {
-        process.state = ProcessState::KILLED;
-        SCHEDULER.lock().resume();
+    // The page directory of a thread belongs to its leader, and must not be freed with the thread
+    let is_thread = !Arc::ptr_eq(&process, &leader);
+    if is_thread {
+        process.lock().pgdir = None;
+    }
+
+    let remaining_threads = {
+        let mut leader_lock = leader.lock();
+        leader_lock.thread_count -= 1;
+        leader_lock.thread_count
+    };
+
+    if remaining_threads == 0 {
+        // Close all open files. They are dropped without holding the process lock, since closing
+        // the end of a pipe wakes up the processes waiting on the other end.
+        let open_files = core::mem::take(&mut leader.lock().open_files);
+        drop(open_files);
+
+        // The memory of the process is no longer needed. Only its kernel stack is kept, since it
+        // is still in use, and is freed once the process is reaped.
+        free_process_memory(&leader);
     }
+
+    let init_process = unsafe { PROCESS_LIST.lock().get_pid(INIT_PROCESS_ID).unwrap() };
+    let mut has_zombie_children = false;
+    let mut zombie_threads = Vec::new();
+
+    for child in unsafe { PROCESS_LIST.lock() }.list.iter() {
+        let mut child_lock = child.lock();
+
+        // Threads and children alike are children of the leader
+        let is_child = match child_lock.parent.as_ref() {
+            Some(parent) => Arc::ptr_eq(parent, &leader),
+            None => false,
+        };
+
+        if !is_child {
+            continue;
+        }
+
+        // Nobody is left to join the threads of the process. Those still running are reaped by
+        // the scheduler once they exit, as processes without parent.
+        if child_lock.leader.is_some() {
+            if !is_thread {
+                child_lock.parent = None;
+                if child_lock.state == ProcessState::ZOMBIE {
+                    zombie_threads.push(Arc::clone(child));
+                }
+            }
+        } else if remaining_threads == 0 {
+            // Children are only orphans once the last thread of the process has exited
+            child_lock.parent = Some(Arc::clone(&init_process));
+            child_lock.adopted = true;
+            has_zombie_children |= child_lock.state == ProcessState::ZOMBIE;
+        }
+    }
+
+    zombie_threads.iter().for_each(|thread| {
+        reap_process(thread);
+    });
+
+    // Init may be waiting already, and must learn about children that have exited before
+    if has_zombie_children {
+        wakeup(init_process.as_ref() as *const SpinMutex<Process> as usize);
+    }
+
+    // The parent of the process could not reap it while its threads were running (see wait)
+    if is_thread && remaining_threads == 0 {
+        let leader_parent = leader.lock().parent.clone();
+        if let Some(leader_parent) = leader_parent {
+            wakeup(leader_parent.as_ref() as *const SpinMutex<Process> as usize);
+        }
+    }
+
+    // The parent frees the kernel stack once it reaps the zombie, so PROCESS_LIST is held until
+    // the process is off its stack, which keeps the parent from seeing it as a zombie before
+    let mut process_list = unsafe { PROCESS_LIST.lock() };
+
+    let parent_process = {
+        let mut process_lock = process.lock();
+        process_lock.exit_code = code;
+        process_lock.state = ProcessState::ZOMBIE;
+        process_lock.parent.clone()
+    };
+
+    if let Some(parent_process) = parent_process {
+        let parent_address = parent_process.as_ref() as *const SpinMutex<Process> as usize;
+        wakeup_locked(&mut process_list, parent_address);
+    }
+
+    // A thread calling exec waits for the other threads to exit (see end_other_threads)
+    if is_thread || remaining_threads > 0 {
+        let leader_address = leader.as_ref() as *const SpinMutex<Process> as usize;
+        wakeup_locked(&mut process_list, leader_address);
+    }
+
+    core::mem::forget(process_list);
+    unsafe { SCHEDULER.lock().resume() };
+}
+
+/// Page directory of the current process, through which user memory is accessed
+fn get_current_page_dir<'a>() -> Page<'a> {
+    let process = unsafe { SCHEDULER.lock().get_current_process().unwrap() };
+    let page_dir = process.lock().pgdir.unwrap();
+    Page::new(page_dir as *mut u8)
+}
+
+/// Copies length bytes at address from the memory of the current process. Fails if any part of
+/// the range is not accessible by the process.
+fn copy_in(address: usize, length: usize) -> Result<Vec<u8>, SystemCallError> {
+    Ok(copy_from_user(
+        &mut get_current_page_dir(),
+        address,
+        length,
+    )?)
+}
+
+/// Copies data into the memory of the current process at address. Fails if any part of the range
+/// is not writable by the process.
+fn copy_out(address: usize, data: &[u8]) -> Result<(), SystemCallError> {
+    Ok(copy_to_user(&mut get_current_page_dir(), address, data)?)
+}
+
+/// Reads a UTF-8 string from the memory of the current process
+fn get_user_string(address: usize, length: usize) -> Result<String, SystemCallError> {
+    let data = copy_in(address, length)?;
+    String::from_utf8(data).map_err(|_| SystemCallError::InvalidArgument)
+}
+
+/// Reads a path from the memory of the current process. The path is resolved against the current
+/// working directory of the process, so the file system only ever sees absolute paths.
+fn get_user_path(address: usize, length: usize) -> Result<String, SystemCallError> {
+    let path = get_user_string(address, length)?;
+
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let path = process.lock().resolve_path(&path);
+    Ok(path)
+}
+
+/// Reads the argument vector of EXEC from the memory of the current process. Each argument is
+/// passed as a pair of string address and length. Arguments must be copied into the kernel, since
+/// the memory of the process is replaced by exec.
+fn get_user_arguments(address: usize, count: usize) -> Result<Vec<String>, SystemCallError> {
+    if count > MAX_ARGUMENTS {
+        return Err(SystemCallError::ArgumentListTooLong);
+    }
+
+    let word_size = core::mem::size_of::<usize>();
+    let pairs = copy_in(address, count * 2 * word_size)?;
+    let mut arguments = Vec::with_capacity(count);
+    let mut size = 0;
+
+    for pair in pairs.chunks_exact(2 * word_size) {
+        let address = usize::from_le_bytes(pair[..word_size].try_into().unwrap());
+        let length = usize::from_le_bytes(pair[word_size..].try_into().unwrap());
+
+        size += length + 1;
+        if size > MAX_ARGUMENTS_SIZE {
+            return Err(SystemCallError::ArgumentListTooLong);
+        }
+
+        arguments.push(get_user_string(address, length)?);
+    }
+
+    Ok(arguments)
+}
+
+/// Open file behind the descriptor of the current process
+fn get_file_descriptor(descriptor: usize) -> Result<FileDescriptor, SystemCallError> {
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let file = process.lock().get_file_descriptor(descriptor);
+    file.ok_or(SystemCallError::BadFileDescriptor)
+}
+
+/// Opens the file at path and returns the new file descriptor
+pub fn open(path: &str, flags: usize) -> Result<usize, SystemCallError> {
+    let file = open_file(path, flags)?;
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let mut process = process.lock();
+    process
+        .allocate_file_descriptor(file)
+        .ok_or(SystemCallError::TooManyOpenFiles)
+}
+
+/// Reads up to length bytes from the file descriptor into the user buffer at address. Returns the
+/// number of bytes read, where 0 indicates the end of the file.
+pub fn read(descriptor: usize, address: usize, length: usize) -> Result<usize, SystemCallError> {
+    let file = get_file_descriptor(descriptor)?;
+
+    // The buffer is checked first, so that the file offset does not move if it is invalid
+    check_user_range(&mut get_current_page_dir(), address, length, true)?;
+
+    let data = read_file(&file, length)?;
+    copy_out(address, &data)?;
+    Ok(data.len())
+}
+
+/// Writes the buffer into the file descriptor. Returns the number of bytes written.
+pub fn write(descriptor: usize, buffer: &[u8]) -> Result<usize, SystemCallError> {
+    let file = get_file_descriptor(descriptor)?;
+    Ok(write_file(&file, buffer)?)
+}
+
+/// Releases the file descriptor. The open file is dropped once no descriptor references it.
+pub fn close(descriptor: usize) -> Result<usize, SystemCallError> {
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let file = process
+        .lock()
+        .open_files
+        .get_mut(descriptor)
+        .and_then(|file| file.take())
+        .ok_or(SystemCallError::BadFileDescriptor)?;
+
+    // Released once the process lock is no longer held (see exit)
+    drop(file);
+    Ok(0)
+}
+
+/// Creates a pipe and stores its read and write descriptors, in that order, at address.
+pub fn pipe(address: usize) -> Result<usize, SystemCallError> {
+    let word_size = core::mem::size_of::<usize>();
+
+    // The array is checked first, so that no descriptor is allocated if it is invalid
+    check_user_range(&mut get_current_page_dir(), address, 2 * word_size, true)?;
+
+    let (read_end, write_end) = create_pipe();
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+
+    let descriptors = {
+        let mut process = process.lock();
+        let free_descriptors = process
+            .open_files
+            .iter()
+            .filter(|file| file.is_none())
+            .count();
+
+        if free_descriptors >= 2 {
+            let read_descriptor = process.allocate_file_descriptor(read_end).unwrap();
+            let write_descriptor = process.allocate_file_descriptor(write_end).unwrap();
+            Ok([read_descriptor, write_descriptor])
+        } else {
+            Err((read_end, write_end))
+        }
+    };
+
+    // Rejected ends are dropped without holding the process lock, since closing the end of a pipe
+    // wakes up the processes waiting on the other end.
+    let descriptors = descriptors.map_err(|_| SystemCallError::TooManyOpenFiles)?;
+    let mut data = Vec::with_capacity(2 * word_size);
+    data.extend_from_slice(&descriptors[0].to_le_bytes());
+    data.extend_from_slice(&descriptors[1].to_le_bytes());
+
+    copy_out(address, &data)?;
+    Ok(0)
+}
+
+/// Stores the number of total, free, kernel and user frames, in that order, at address.
+pub fn frame_stats(address: usize) -> Result<usize, SystemCallError> {
+    let stats = get_frame_stats();
+    let mut data = Vec::with_capacity(4 * core::mem::size_of::<usize>());
+
+    for count in [stats.total, stats.free, stats.kernel, stats.user] {
+        data.extend_from_slice(&count.to_le_bytes());
+    }
+
+    copy_out(address, &data)?;
+    Ok(0)
+}
+
+/// Stores the object size, number of slabs, used objects and free objects of each slab cache of the
+/// heap, in that order, at address. Returns the number of caches.
+pub fn heap_stats(address: usize) -> Result<usize, SystemCallError> {
+    let caches = get_heap_stats();
+    let mut data = Vec::with_capacity(4 * caches.len() * core::mem::size_of::<usize>());
+
+    for stats in caches.iter() {
+        for count in [stats.size, stats.slabs, stats.used, stats.free] {
+            data.extend_from_slice(&count.to_le_bytes());
+        }
+    }
+
+    copy_out(address, &data)?;
+    Ok(caches.len())
+}
+
+/// Stores a snapshot of up to count processes at address. Each one holds the pid, parent pid (or
+/// NO_PARENT), state, name (PROCESS_NAME_LENGTH bytes, NUL padded), memory size, user ticks, Kernel
+/// ticks, context switches and start tick, in that order. Returns the number of processes, which
+/// may be more than count.
+pub fn process_stats(address: usize, count: usize) -> Result<usize, SystemCallError> {
+    let processes = get_process_stats();
+    let mut data = Vec::new();
+
+    for stats in processes.iter().take(count) {
+        let mut name = [0u8; PROCESS_NAME_LENGTH];
+        let length = stats.name.len().min(PROCESS_NAME_LENGTH);
+        name[..length].copy_from_slice(&stats.name.as_bytes()[..length]);
+
+        data.extend_from_slice(&stats.pid.to_le_bytes());
+        data.extend_from_slice(&stats.parent_pid.unwrap_or(NO_PARENT).to_le_bytes());
+        data.extend_from_slice(&(stats.state as usize).to_le_bytes());
+        data.extend_from_slice(&name);
+
+        for value in [
+            stats.mem_size,
+            stats.user_ticks,
+            stats.kernel_ticks,
+            stats.context_switches,
+            stats.start_tick,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    copy_out(address, &data)?;
+    Ok(processes.len())
+}
+
+/// Maps length bytes into the memory of the process and returns their address. With MAP_ANONYMOUS,
+/// the memory starts zeroed (and can be written with PROT_WRITE). Otherwise, it holds the content
+/// of the file descriptor from offset, which must be page aligned, and cannot be written.
+pub fn mmap(
+    length: usize,
+    flags: usize,
+    descriptor: usize,
+    offset: usize,
+) -> Result<usize, SystemCallError> {
+    if length == 0 || length > KERNEL_BASE {
+        return Err(SystemCallError::InvalidArgument);
+    }
+
+    let writable = flags & PROT_WRITE > 0;
+    if flags & MAP_ANONYMOUS > 0 {
+        return Ok(map_memory(length, writable, None, 0)?);
+    }
+
+    // Offsets into the file are 32 bits wide, up to the end of the mapping
+    let end_offset = offset.checked_add(length);
+    if writable || offset % PAGE_SIZE != 0 || end_offset.map_or(true, |end| end > u32::MAX as usize)
+    {
+        return Err(SystemCallError::InvalidArgument);
+    }
+
+    let file = get_file_descriptor(descriptor)?.lock().clone();
+    if file._type != FileType::INODE || !file.readable {
+        return Err(SystemCallError::BadFileDescriptor);
+    }
+
+    let inode = file.inode.unwrap();
+    if get_inode(inode.inode_number)._type == INodeType::DIRECTORY {
+        return Err(SystemCallError::IsADirectory);
+    }
+
+    Ok(map_memory(length, false, Some(inode), offset as u32)?)
+}
+
+/// Unmaps the pages of the memory mappings from address to address + length
+pub fn munmap(address: usize, length: usize) -> Result<usize, SystemCallError> {
+    let end_address = address
+        .checked_add(length)
+        .filter(|&end_address| end_address <= KERNEL_BASE);
+
+    if address % PAGE_SIZE != 0 || length == 0 || end_address.is_none() {
+        return Err(SystemCallError::InvalidArgument);
+    }
+
+    unmap_memory(address, length);
+    Ok(0)
+}
+
+/// Duplicates the file descriptor into the lowest free slot. Both descriptors share the same
+/// open file, including its offset.
+pub fn dup(descriptor: usize) -> Result<usize, SystemCallError> {
+    let file = get_file_descriptor(descriptor)?;
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let mut process = process.lock();
+    process
+        .allocate_file_descriptor(file)
+        .ok_or(SystemCallError::TooManyOpenFiles)
+}
+
+/// Waits for a child to exit (see process::wait). The exit code is stored at status_address,
+/// unless it is null. Returns the pid of the child.
+pub fn wait_child(
+    pid: usize,
+    status_address: usize,
+    flags: usize,
+) -> Result<usize, SystemCallError> {
+    reap_child(status_address, || wait(pid, flags))
+}
+
+/// Creates a thread of the current process, which calls entry with argument on the stack that ends
+/// at stack (see process::clone). Returning from entry hits the return trap, which kills the
+/// thread, so entry should exit instead. Returns the pid of the thread.
+pub fn clone_thread(entry: usize, stack: usize, argument: usize) -> Result<usize, SystemCallError> {
+    // The argument is found above the return address, as if entry had been called
+    let word_size = core::mem::size_of::<usize>();
+    let stack = stack
+        .checked_sub(2 * word_size)
+        .ok_or(SystemCallError::BadAddress)?;
+
+    let mut data = Vec::with_capacity(2 * word_size);
+    data.extend_from_slice(&0xFFFFFFFFusize.to_le_bytes()); // Return trap
+    data.extend_from_slice(&argument.to_le_bytes());
+
+    copy_out(stack, &data)?;
+    Ok(clone(entry, stack)?)
+}
+
+/// Waits for a thread of the current process to exit (see process::join). The exit code is stored
+/// at status_address, unless it is null. Returns the pid of the thread.
+pub fn join_thread(tid: usize, status_address: usize) -> Result<usize, SystemCallError> {
+    reap_child(status_address, || join(tid))
+}
+
+/// Reaps a child with reap, which returns its pid (or 0 if there is none yet) and exit code. The
+/// exit code is stored at status_address, unless it is null. Returns the pid of the child.
+fn reap_child(
+    status_address: usize,
+    reap: impl FnOnce() -> Option<(usize, i32)>,
+) -> Result<usize, SystemCallError> {
+    let status_size = core::mem::size_of::<i32>();
+
+    // The status pointer is checked first, so that a child is never reaped without reporting it
+    if status_address != 0 {
+        check_user_range(
+            &mut get_current_page_dir(),
+            status_address,
+            status_size,
+            true,
+        )?;
+    }
+
+    let (pid, exit_code) = reap().ok_or(SystemCallError::NoChildProcess)?;
+    if status_address != 0 && pid != 0 {
+        copy_out(status_address, &exit_code.to_le_bytes())?;
+    }
+
+    Ok(pid)
+}
+
+/// Creates an empty directory at path
+pub fn mkdir(path: &str) -> Result<usize, SystemCallError> {
+    begin_operation();
+    let inode_number = create_inode(path, INodeType::DIRECTORY);
+    end_operation();
+
+    inode_number?;
+    Ok(0)
+}
+
+/// Removes the entry at path. Directories must be empty.
+pub fn remove(path: &str) -> Result<usize, SystemCallError> {
+    begin_operation();
+    let output = unlink(path);
+    end_operation();
+
+    output?;
+    Ok(0)
+}
+
+/// Creates a new entry at new_path for the file at old_path
+pub fn hard_link(old_path: &str, new_path: &str) -> Result<usize, SystemCallError> {
+    begin_operation();
+    let output = link(old_path, new_path);
+    end_operation();
+
+    output?;
+    Ok(0)
+}
+
+/// Moves the entry at old_path to new_path
+pub fn move_path(old_path: &str, new_path: &str) -> Result<usize, SystemCallError> {
+    begin_operation();
+    let output = rename(old_path, new_path);
+    end_operation();
+
+    output?;
+    Ok(0)
+}
+
+/// Changes the current working directory of the process
+pub fn chdir(path: &str) -> Result<usize, SystemCallError> {
+    let inode = find_inode_by_path(path).ok_or(SystemCallError::NotFound)?;
+    if inode._type != INodeType::DIRECTORY {
+        return Err(SystemCallError::NotADirectory);
+    }
+
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    process.lock().current_working_directory = path.to_string();
+    Ok(0)
 }
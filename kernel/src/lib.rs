@@ -29,19 +29,21 @@ extern crate alloc;
 // Interface definition of panic in Rust. Core represents the core library
 use core::panic::PanicInfo;
 
-use crate::sync::cpu_cli::push_cli;
+use crate::{memory::defs::BootInfo, sync::cpu_cli::push_cli};
 
 // Uses C calling convention instead of Rust. no_mangle removes name mangling when compiled.
 // _start is the default entry point for most systems. Function is diverging as the Kernel should
 // never return
 #[no_mangle]
-pub unsafe extern "C" fn _start() -> ! {
+pub unsafe extern "C" fn _start(boot_info: *const BootInfo) -> ! {
     // Initialize debugging method (VGA or Console)
     devices::debug::debug_init();
     misc::logo::print_logo();
 
     // Setup Virtual Memory
+    memory::mem::setup_memory_map(boot_info);
     memory::vm::setup_vm();
+    memory::frame::setup_frames();
     memory::heap::setup_heap();
 
     // Setup Hardware Interrupts and Multiprocessing
@@ -64,6 +66,26 @@ pub unsafe extern "C" fn _start() -> ! {
 
     // Scheduler
     scheduler::process::spawn_init_process();
+    scheduler::kthread::spawn_kernel_workers();
+    apic::mp::start_cpus();
+    scheduler::scheduler::setup_scheduler();
+
+    // Should never proceeed
+    panic!("[FATAL] Returned from Scheduler");
+}
+
+// Entry point of the application processors, called by their trampoline (see ap_entry.asm) once
+// paging and their own stack are in place. Everything shared is already set up by _start.
+#[no_mangle]
+pub unsafe extern "C" fn _start_ap() -> ! {
+    #[cfg(feature = "pae")]
+    memory::vm::enable_no_execute();
+
+    apic::local_apic::setup_local_apic();
+    memory::gdt::setup_cpu_gdt();
+    interrupts::idt::setup_cpu_idt();
+    apic::conclude_cpu();
+
     scheduler::scheduler::setup_scheduler();
 
     // Should never proceeed
@@ -41,28 +41,30 @@ macro_rules! P2V {
 #[macro_export]
 macro_rules! PAGE_TABLE_INDEX {
     ($n:expr) => {
-        ($n >> PAGE_TABLE_SHIFT) & 0x3FF
+        ($n >> PAGE_TABLE_SHIFT) & ($crate::memory::defs::NUMBER_PAGE_ENTRIES - 1)
     };
 }
 
+/// Index of the page directory entry of an address. With PAE, the page directories are indexed as
+/// if they were a single one (see get_page_dir_entries).
 #[macro_export]
 macro_rules! PAGE_DIR_INDEX {
     ($n:expr) => {
-        ($n >> PAGE_DIR_SHIFT) & 0x3FF
+        ($n >> PAGE_DIR_SHIFT) & ($crate::memory::defs::NUMBER_PAGE_DIR_ENTRIES - 1)
     };
 }
 
 #[macro_export]
 macro_rules! PTE_ADDRESS {
     ($n:expr) => {
-        $n & !0xFFF
+        ($n & $crate::memory::defs::PTE_ADDRESS_MASK) as usize
     };
 }
 
 #[macro_export]
 macro_rules! PTE_FLAGS {
     ($n:expr) => {
-        $n & 0xFFF
+        $n & !$crate::memory::defs::PTE_ADDRESS_MASK
     };
 }
 
@@ -80,8 +82,9 @@ pub const MEM_BDA: usize = 0x400; // Memory BIOS Data Area
 
 /// VM Definitions
 pub const PAGE_SIZE: usize = 4096;
-pub const PAGE_ENTRY_SIZE: usize = size_of::<u32>();
+pub const PAGE_ENTRY_SIZE: usize = size_of::<PageTableEntry>();
 pub const NUMBER_PAGE_ENTRIES: usize = PAGE_SIZE / PAGE_ENTRY_SIZE;
+pub const NUMBER_PAGE_DIR_ENTRIES: usize = NUMBER_PAGE_DIRS * NUMBER_PAGE_ENTRIES;
 
 pub const EXTENDED_MEMORY: usize = 0x100000;
 pub const DEVICE_SPACE: usize = 0xFE000000;
@@ -89,20 +92,81 @@ pub const PHYSICAL_DEVICE_SPACE: usize = V2P!(DEVICE_SPACE);
 pub const KERNEL_BASE: usize = 0x80000000;
 pub const KERNEL_LINK: usize = KERNEL_BASE + EXTENDED_MEMORY;
 
-pub const PAGE_DIR_SHIFT: usize = 22;
 pub const PAGE_TABLE_SHIFT: usize = 12;
 
-pub const PTE_P: usize = 0x001; // Present Bit
-pub const PTE_W: usize = 0x002; // Writable Bit
-pub const PTE_U: usize = 0x004; // User Bit
-pub const PTE_PS: usize = 0x080; // Page Size Bit
+/// Without PAE, a single page directory of 32 bit entries maps 4MiB per entry
+#[cfg(not(feature = "pae"))]
+pub type PageTableEntry = usize;
+#[cfg(not(feature = "pae"))]
+pub const PAGE_DIR_SHIFT: usize = 22;
+#[cfg(not(feature = "pae"))]
+pub const NUMBER_PAGE_DIRS: usize = 1;
+#[cfg(not(feature = "pae"))]
+pub const PTE_ADDRESS_MASK: PageTableEntry = !0xFFF;
+#[cfg(not(feature = "pae"))]
+pub const PTE_NX: PageTableEntry = 0; // Pages can only be made non-executable with PAE
+
+/// With PAE, entries are 64 bits long, so four page directories (pointed by the page directory
+/// pointer table) of 2MiB per entry are needed to map the whole address space
+#[cfg(feature = "pae")]
+pub type PageTableEntry = u64;
+#[cfg(feature = "pae")]
+pub const PAGE_DIR_SHIFT: usize = 21;
+#[cfg(feature = "pae")]
+pub const NUMBER_PAGE_DIRS: usize = 4;
+#[cfg(feature = "pae")]
+pub const PTE_ADDRESS_MASK: PageTableEntry = 0x000F_FFFF_FFFF_F000;
+#[cfg(feature = "pae")]
+pub const PTE_NX: PageTableEntry = 1 << 63; // No-Execute Bit
+
+pub const PTE_P: PageTableEntry = 0x001; // Present Bit
+pub const PTE_W: PageTableEntry = 0x002; // Writable Bit
+pub const PTE_U: PageTableEntry = 0x004; // User Bit
+pub const PTE_PS: PageTableEntry = 0x080; // Page Size Bit
+pub const PTE_COW: PageTableEntry = 0x200; // Copy-On-Write Bit (available to software)
+pub const PTE_LAZY: PageTableEntry = 0x400; // Lazily Allocated Bit (available to software)
+pub const PTE_SHARED: PageTableEntry = 0x800; // Shared Memory Bit (available to software)
 
 /// Heap Definitions
 pub const HEAP_PAGES: usize = 25;
 pub const STACK_PAGES: usize = 4;
 
 pub struct LinkedListAllocator {
-    pub head: StaticLinkedListNode,
+    pub head: StaticLinkedListNode, // Free regions, sorted by address
+}
+
+/// Slab Definitions
+pub const NUMBER_SLAB_CACHES: usize = 7; // Objects of up to 1024 bytes are allocated from slabs
+pub const SLAB_MIN_SIZE: usize = 16; // Object size of the first cache, doubled by each next one
+pub const MAX_EMPTY_SLABS: usize = 1; // Empty slabs kept by a cache, instead of freeing their pages
+
+/// Small allocations are served by slab caches, while bigger ones (or ones that cannot get a slab)
+/// fall through to the linked list allocator, which manages the region from start to end
+pub struct HeapAllocator {
+    pub caches: [SlabCache; NUMBER_SLAB_CACHES],
+    pub list: LinkedListAllocator,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct SlabCache {
+    pub slabs: Option<&'static mut Slab>, // Slabs with at least one free object
+    pub stats: SlabStats,
+}
+
+/// A slab is a page split into objects of the same size. This header sits at the start of it.
+pub struct Slab {
+    pub next: Option<&'static mut Slab>, // Next slab of the cache with free objects
+    pub free: Option<&'static mut StaticLinkedListNode>, // First free object
+    pub used: usize,                     // Number of allocated objects
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SlabStats {
+    pub size: usize,  // Size of the objects of the cache
+    pub slabs: usize, // Pages held by the cache
+    pub used: usize,  // Allocated objects
+    pub free: usize,  // Objects ready to be allocated
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -114,10 +178,10 @@ pub struct GlobalDescriptorTable(pub [GlobalDescriptorTableSegment; NUMBER_DESCR
 #[derive(Debug)]
 #[repr(C)]
 pub struct MemoryLayoutEntry {
-    pub virt: *const usize, // Start of the virtual address
-    pub phys_start: usize,  // Start of the physical address
-    pub phys_end: usize,    // End of the physical address
-    pub perm: usize,        // Permission flags
+    pub virt: *const usize,   // Start of the virtual address
+    pub phys_start: usize,    // Start of the physical address
+    pub phys_end: usize,      // End of the physical address
+    pub perm: PageTableEntry, // Permission flags
 }
 
 #[derive(Debug)]
@@ -130,6 +194,80 @@ pub struct MemoryRegion {
     pub end: usize,
 }
 
+/// Memory Map Definitions
+pub const MAX_MEMORY_MAP_ENTRIES: usize = 32; // Must match E820_MAX_ENTRIES of the bootloader
+pub const E820_USABLE: u32 = 1; // Memory available to the Kernel
+pub const E820_ATTRIBUTE_VALID: u32 = 1; // Entries without this attribute must be ignored
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct E820Entry {
+    pub base: u64,
+    pub length: u64,
+    pub _type: u32,
+    pub attributes: u32,
+}
+
+// Information gathered by the bootloader, handed over to _start
+#[repr(C)]
+pub struct BootInfo {
+    pub number_memory_entries: u32,
+    pub memory_map: [E820Entry; MAX_MEMORY_MAP_ENTRIES],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryRange {
+    pub start: usize, // Physical address of the first byte
+    pub end: usize,   // Physical address past the last byte
+}
+
+#[derive(Debug)]
+pub struct MemoryMap {
+    pub entries: [E820Entry; MAX_MEMORY_MAP_ENTRIES], // Memory map provided by the BIOS
+    pub number_entries: usize,
+    pub usable: [MemoryRange; MAX_MEMORY_MAP_ENTRIES], // Memory usable by the Kernel, sorted
+    pub number_usable: usize,
+}
+
+/// Frame Allocator Definitions
+pub const MAX_FRAME_ORDER: usize = 10; // Largest block holds 2^10 frames (4 MiB)
+pub const NO_FRAME: u32 = u32::MAX; // End of a free list
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameUsage {
+    Free,   // First frame of a free block
+    Kernel, // Page tables, Kernel stacks, Heap and other Kernel structures
+    User,   // Memory of user processes
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    pub next: u32,         // Next free block of the same order
+    pub previous: u32,     // Previous free block of the same order
+    pub references: u16,   // Number of owners of the block (such as page directories)
+    pub order: u8,         // Block holds 2^order frames
+    pub usage: FrameUsage, // Only meaningful for the first frame of a block
+}
+
+#[derive(Debug)]
+pub struct FrameAllocator {
+    pub frames: *mut Frame,                     // Metadata of every managed frame
+    pub first_frame: usize,                     // Physical frame number of the first managed frame
+    pub number_frames: usize,                   // Number of frames, including holes
+    pub free_lists: [u32; MAX_FRAME_ORDER + 1], // First free block of each order
+    pub free_frames: usize,
+    pub kernel_frames: usize,
+    pub user_frames: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+    pub kernel: usize,
+    pub user: usize,
+}
+
 bitflags! {
     pub struct DescriptorFlags: u64 {
         // Access
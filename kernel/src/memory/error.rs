@@ -17,4 +17,10 @@ pub enum MemoryError {
 
     // Page already present when a map is called
     PageRemapped(u32),
+
+    // User address is not mapped, not accessible by the user, or not writable when written to
+    InvalidUserAddress(u32),
+
+    // Data of a mapped file could not be read from the disk
+    ReadFailure,
 }
@@ -0,0 +1,300 @@
+/// Physical memory is managed in frames (physical pages) by a buddy allocator. Free frames are
+/// grouped in blocks of 2^order frames, aligned to their size. An allocation splits the smallest
+/// block that fits in halves until it has the requested order, and a freed block is merged with
+/// its buddy (the other half of the block it was split from) whenever the buddy is free as well.
+/// Each allocated block counts its references, so that blocks shared between page directories,
+/// such as copy-on-write pages, only return to the free lists once their last reference drops.
+use core::mem::size_of;
+
+use crate::{println, sync::spin_mutex::SpinMutex, P2V, ROUND_UP, V2P};
+
+use super::{
+    defs::{
+        Frame, FrameAllocator, FrameStats, FrameUsage, KERNEL_BASE, MAX_FRAME_ORDER, NO_FRAME,
+        PAGE_SIZE,
+    },
+    error::MemoryError,
+    mem::{MEMORY_MAP, MEMORY_REGION},
+};
+
+pub static FRAME_ALLOCATOR: SpinMutex<FrameAllocator> = SpinMutex::new(FrameAllocator::new());
+
+impl Frame {
+    pub const fn new() -> Self {
+        Frame {
+            next: NO_FRAME,
+            previous: NO_FRAME,
+            references: 0,
+            order: 0,
+            usage: FrameUsage::Kernel,
+        }
+    }
+}
+
+impl FrameAllocator {
+    pub const fn new() -> Self {
+        FrameAllocator {
+            frames: core::ptr::null_mut(),
+            first_frame: 0,
+            number_frames: 0,
+            free_lists: [NO_FRAME; MAX_FRAME_ORDER + 1],
+            free_frames: 0,
+            kernel_frames: 0,
+            user_frames: 0,
+        }
+    }
+
+    /// Until the allocator is initialized, pages can only be taken from the Memory Region
+    pub fn is_enabled(&self) -> bool {
+        !self.frames.is_null()
+    }
+
+    /// Manage the frames from start to end (Kernel virtual addresses). The metadata of the frames
+    /// is stored in the first pages of the range, which are never allocated. Frames are only
+    /// handed out once they are added through add_free_range.
+    pub unsafe fn init(&mut self, start: usize, end: usize) {
+        let total_frames = (end - start) / PAGE_SIZE;
+        let metadata_pages = ROUND_UP!(total_frames * size_of::<Frame>(), PAGE_SIZE) / PAGE_SIZE;
+        assert!(metadata_pages < total_frames);
+
+        self.frames = start as *mut Frame;
+        self.first_frame = V2P!(start) / PAGE_SIZE + metadata_pages;
+        self.number_frames = total_frames - metadata_pages;
+
+        for index in 0..self.number_frames {
+            self.frames.add(index).write(Frame::new());
+        }
+    }
+
+    /// Add the frames from start to end (Kernel virtual addresses) to the free lists, split into
+    /// the largest blocks that are aligned to their size
+    pub fn add_free_range(&mut self, start: usize, end: usize) {
+        let mut index = self.get_index(start);
+        let end_index = self.get_index(end - PAGE_SIZE) + 1;
+
+        while index < end_index {
+            let frame_number = self.first_frame + index;
+            let mut order = MAX_FRAME_ORDER;
+
+            while frame_number % (1 << order) != 0 || index + (1 << order) > end_index {
+                order -= 1;
+            }
+
+            self.push_block(index, order);
+            self.free_frames += 1 << order;
+            index += 1 << order;
+        }
+    }
+
+    fn frame(&mut self, index: usize) -> &mut Frame {
+        assert!(index < self.number_frames);
+        unsafe { &mut *self.frames.add(index) }
+    }
+
+    fn get_index(&self, address: usize) -> usize {
+        let frame_number = V2P!(address) / PAGE_SIZE;
+        if address % PAGE_SIZE != 0
+            || frame_number < self.first_frame
+            || frame_number >= self.first_frame + self.number_frames
+        {
+            panic!("[ERROR] Address 0x{:X} is not a managed frame", address);
+        }
+
+        frame_number - self.first_frame
+    }
+
+    fn get_address(&self, index: usize) -> usize {
+        P2V!((self.first_frame + index) * PAGE_SIZE)
+    }
+
+    /// The buddy of a block differs from it only in the bit of its order. The buddy may not exist
+    /// for blocks at the edges of the managed memory.
+    fn get_buddy(&self, index: usize, order: usize) -> Option<usize> {
+        let buddy_frame_number = (self.first_frame + index) ^ (1 << order);
+
+        if buddy_frame_number < self.first_frame
+            || buddy_frame_number + (1 << order) > self.first_frame + self.number_frames
+        {
+            return None;
+        }
+
+        Some(buddy_frame_number - self.first_frame)
+    }
+
+    fn get_usage_count(&mut self, usage: FrameUsage) -> &mut usize {
+        match usage {
+            FrameUsage::Free => &mut self.free_frames,
+            FrameUsage::Kernel => &mut self.kernel_frames,
+            FrameUsage::User => &mut self.user_frames,
+        }
+    }
+
+    fn push_block(&mut self, index: usize, order: usize) {
+        let next = self.free_lists[order];
+
+        let frame = self.frame(index);
+        frame.next = next;
+        frame.previous = NO_FRAME;
+        frame.order = order as u8;
+        frame.usage = FrameUsage::Free;
+
+        if next != NO_FRAME {
+            self.frame(next as usize).previous = index as u32;
+        }
+
+        self.free_lists[order] = index as u32;
+    }
+
+    fn remove_block(&mut self, index: usize) {
+        let frame = *self.frame(index);
+
+        match frame.previous {
+            NO_FRAME => self.free_lists[frame.order as usize] = frame.next,
+            previous => self.frame(previous as usize).next = frame.next,
+        }
+
+        if frame.next != NO_FRAME {
+            self.frame(frame.next as usize).previous = frame.previous;
+        }
+    }
+
+    /// Allocate a block of 2^order contiguous frames. Returns the Kernel address of the block.
+    pub fn allocate(&mut self, order: usize, usage: FrameUsage) -> Result<usize, MemoryError> {
+        assert!(usage != FrameUsage::Free);
+
+        // Find the smallest free block that fits
+        let mut current_order = order;
+        while current_order <= MAX_FRAME_ORDER && self.free_lists[current_order] == NO_FRAME {
+            current_order += 1;
+        }
+
+        if current_order > MAX_FRAME_ORDER {
+            return Err(MemoryError::OutOfMemory);
+        }
+
+        let index = self.free_lists[current_order] as usize;
+        self.remove_block(index);
+
+        // Return the upper halves to the free lists until the block has the requested order
+        while current_order > order {
+            current_order -= 1;
+            self.push_block(index + (1 << current_order), current_order);
+        }
+
+        let frame = self.frame(index);
+        frame.references = 1;
+        frame.order = order as u8;
+        frame.usage = usage;
+
+        self.free_frames -= 1 << order;
+        *self.get_usage_count(usage) += 1 << order;
+
+        Ok(self.get_address(index))
+    }
+
+    /// Drop a reference to the block starting at address. Once its last reference is dropped, the
+    /// block is merged with its free buddies and returned to the free lists.
+    pub fn deallocate(&mut self, address: usize) {
+        let mut index = self.get_index(address);
+        let frame = self.frame(index);
+
+        if frame.usage == FrameUsage::Free || frame.references == 0 {
+            panic!("[ERROR] Frame 0x{:X} is already free", address);
+        }
+
+        frame.references -= 1;
+        if frame.references > 0 {
+            return;
+        }
+
+        let (mut order, usage) = (frame.order as usize, frame.usage);
+        *self.get_usage_count(usage) -= 1 << order;
+        self.free_frames += 1 << order;
+
+        while order < MAX_FRAME_ORDER {
+            let Some(buddy) = self.get_buddy(index, order) else {
+                break;
+            };
+
+            let buddy_frame = *self.frame(buddy);
+            if buddy_frame.usage != FrameUsage::Free || buddy_frame.order as usize != order {
+                break;
+            }
+
+            self.remove_block(buddy);
+            index = core::cmp::min(index, buddy);
+            order += 1;
+        }
+
+        self.push_block(index, order);
+    }
+
+    /// Add a reference to the block starting at address
+    pub fn share(&mut self, address: usize) {
+        let index = self.get_index(address);
+        let frame = self.frame(index);
+
+        if frame.usage == FrameUsage::Free {
+            panic!("[ERROR] Cannot share free frame 0x{:X}", address);
+        }
+
+        frame.references += 1;
+    }
+
+    pub fn get_references(&mut self, address: usize) -> usize {
+        let index = self.get_index(address);
+        self.frame(index).references as usize
+    }
+
+    pub fn get_stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.free_frames + self.kernel_frames + self.user_frames,
+            free: self.free_frames,
+            kernel: self.kernel_frames,
+            user: self.user_frames,
+        }
+    }
+}
+
+unsafe impl Send for FrameAllocator {}
+
+/// Smallest order of a block holding the given number of pages
+pub fn get_block_order(number_pages: usize) -> usize {
+    number_pages.next_power_of_two().trailing_zeros() as usize
+}
+
+pub fn get_frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.lock().get_stats()
+}
+
+/// Hand the pages left in the Memory Region over to the frame allocator. Pages allocated before
+/// (such as the page tables of the Kernel) are not managed and can never be freed. Holes in the
+/// memory map are never handed out.
+pub fn setup_frames() {
+    let (start, end) = MEMORY_REGION.lock().take_remaining();
+
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    unsafe { frame_allocator.init(start, end) };
+
+    // Metadata is written right away, so it must not be placed on a hole
+    let first_frame_address = frame_allocator.first_frame * PAGE_SIZE;
+    let memory_map = MEMORY_MAP.lock();
+    assert!(memory_map
+        .get_usable()
+        .iter()
+        .any(|range| range.start <= V2P!(start) && first_frame_address <= range.end));
+
+    for range in memory_map.get_usable() {
+        let range_start = core::cmp::max(range.start, first_frame_address);
+        let range_end = core::cmp::min(range.end, V2P!(end));
+
+        if range_start < range_end {
+            frame_allocator.add_free_range(P2V!(range_start), P2V!(range_end));
+        }
+    }
+
+    println!(
+        "[KERNEL] Frame Allocator Initialized ({} Frames)",
+        frame_allocator.get_stats().total
+    );
+}
@@ -4,14 +4,17 @@ use core::sync::atomic::{AtomicBool, Ordering};
 use alloc::alloc::{GlobalAlloc, Layout};
 
 use crate::{
-    memory::vm::allocate_pages,
+    memory::vm::{allocate_page, allocate_pages, deallocate_page},
     println,
     structures::static_linked_list::StaticLinkedListNode,
     sync::spin_mutex::{SpinMutex, SpinMutexGuard},
-    ROUND_UP,
+    ROUND_DOWN, ROUND_UP,
 };
 
-use super::defs::{LinkedListAllocator, HEAP_PAGES, PAGE_SIZE};
+use super::defs::{
+    HeapAllocator, LinkedListAllocator, Slab, SlabCache, SlabStats, HEAP_PAGES, MAX_EMPTY_SLABS,
+    NUMBER_SLAB_CACHES, PAGE_SIZE, SLAB_MIN_SIZE,
+};
 
 pub struct Locked<A> {
     inner: SpinMutex<A>,
@@ -30,39 +33,212 @@ impl<A> Locked<A> {
 }
 
 #[global_allocator]
-pub static HEAP_ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
+pub static HEAP_ALLOCATOR: Locked<HeapAllocator> = Locked::new(HeapAllocator::new());
 pub static IS_HEAP_ENABLED: AtomicBool = AtomicBool::new(false);
 
 /// Heap Allocator is defined below. Rust no_std environment requires us
 /// to define our own allocator. As such, our goal is to first identify what
 /// memory region is available to be our Heap and then we instruct the allocator
-/// on how to allocate memory in that region. For this implementation, a Linked
-/// List allocator is used.
-unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
+/// on how to allocate memory in that region. Small objects, which make up most of
+/// the allocations (such as disk blocks, processes and strings), come from slab
+/// caches of fixed-size objects, while the rest is left to a Linked List allocator.
+unsafe impl GlobalAlloc for Locked<HeapAllocator> {
     unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
-        let (size, align) = LinkedListAllocator::size_align(_layout);
         let mut allocator = self.lock();
 
-        // Check if we can allocate a free node. If not, then we ran out of memory
-        if let Some((node, start)) = allocator.search_free_node(size, align) {
-            let end = start.checked_add(size).expect("overflow");
-            let excess_size = node.end_address() - end;
-
-            // If there is space left after we take the heap block, then this excess
-            // node should be returned to the linked list
-            if excess_size > 0 {
-                allocator.add_free_node(end, excess_size);
+        if let Some(index) = get_slab_index(_layout) {
+            if let Some(address) = allocator.caches[index].allocate(index) {
+                return address as *mut u8;
             }
+        }
 
-            start as *mut u8
+        allocator.list.allocate(_layout)
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        let mut allocator = self.lock();
+        let address = _ptr as usize;
+
+        // Objects outside of the linked list region can only come from a slab
+        if address >= allocator.start && address < allocator.end {
+            allocator.list.deallocate(_ptr, _layout);
         } else {
-            core::ptr::null_mut()
+            let index =
+                get_slab_index(_layout).expect("[ERROR] Object does not belong to the heap");
+            allocator.caches[index].deallocate(address, index);
         }
     }
+}
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        let (size, _) = LinkedListAllocator::size_align(_layout);
-        self.lock().add_free_node(_ptr as usize, size);
+impl HeapAllocator {
+    pub const fn new() -> Self {
+        const EMPTY_CACHE: SlabCache = SlabCache::new();
+
+        HeapAllocator {
+            caches: [EMPTY_CACHE; NUMBER_SLAB_CACHES],
+            list: LinkedListAllocator::new(),
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+/// Size of the objects of the slab cache at index
+fn get_slab_size(index: usize) -> usize {
+    SLAB_MIN_SIZE << index
+}
+
+/// Index of the smallest slab cache whose objects fit the layout, if any. Objects are aligned to
+/// their own size, so they also fit any alignment up to it.
+fn get_slab_index(layout: Layout) -> Option<usize> {
+    let size = core::cmp::max(layout.size(), layout.align());
+    (0..NUMBER_SLAB_CACHES).find(|&index| get_slab_size(index) >= size)
+}
+
+/// Offset of the first object of a slab, right after the slab header
+fn get_slab_offset(size: usize) -> usize {
+    ROUND_UP!(core::mem::size_of::<Slab>(), size)
+}
+
+/// Number of objects of size bytes that fit in a slab
+fn get_slab_capacity(size: usize) -> usize {
+    (PAGE_SIZE - get_slab_offset(size)) / size
+}
+
+impl Slab {
+    /// Address in memory where this slab (and its page) starts
+    pub fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl SlabCache {
+    pub const fn new() -> Self {
+        SlabCache {
+            slabs: None,
+            stats: SlabStats {
+                size: 0,
+                slabs: 0,
+                used: 0,
+                free: 0,
+            },
+        }
+    }
+
+    /// Allocate an object from the slabs of the cache at index. A new slab is created if every
+    /// slab is full. Returns None if there is no page left for it.
+    unsafe fn allocate(&mut self, index: usize) -> Option<usize> {
+        if self.slabs.is_none() {
+            self.slabs = Some(self.create_slab(get_slab_size(index))?);
+        }
+
+        let slab = self.slabs.take().unwrap();
+        let object = slab.free.take().unwrap();
+        slab.free = object.next.take();
+        slab.used += 1;
+
+        // A full slab leaves the list, until one of its objects is freed
+        self.slabs = match slab.free {
+            Some(_) => Some(slab),
+            None => slab.next.take(),
+        };
+
+        self.stats.used += 1;
+        self.stats.free -= 1;
+        Some(object.address())
+    }
+
+    /// Return an object to its slab. Slabs left empty free their page, unless the cache would be
+    /// left with fewer than MAX_EMPTY_SLABS empty slabs.
+    unsafe fn deallocate(&mut self, address: usize, index: usize) {
+        let slab = &mut *(ROUND_DOWN!(address, PAGE_SIZE) as *mut Slab);
+        let was_full = slab.free.is_none();
+
+        // Freed objects hold the free list of the slab, like the nodes of the linked list
+        let object_address = address as *mut StaticLinkedListNode;
+        let mut object = StaticLinkedListNode::new(0);
+        object.next = slab.free.take();
+        object_address.write(object);
+
+        slab.free = Some(&mut *object_address);
+        slab.used -= 1;
+        self.stats.used -= 1;
+        self.stats.free += 1;
+
+        let slab_address = slab.address();
+        let is_empty = slab.used == 0;
+
+        if was_full {
+            slab.next = self.slabs.take();
+            self.slabs = Some(slab);
+        }
+
+        if is_empty && self.count_empty_slabs() > MAX_EMPTY_SLABS {
+            self.remove_slab(slab_address, get_slab_size(index));
+        }
+    }
+
+    /// Split a new page into objects of size bytes
+    unsafe fn create_slab(&mut self, size: usize) -> Option<&'static mut Slab> {
+        let page = allocate_page().ok()?;
+        let slab_address = page.as_ptr() as usize;
+
+        let slab = slab_address as *mut Slab;
+        slab.write(Slab {
+            next: None,
+            free: None,
+            used: 0,
+        });
+
+        // Objects are pushed from the end, so that they are allocated from the start of the page
+        for offset in (get_slab_offset(size)..PAGE_SIZE).step_by(size).rev() {
+            let object_address = (slab_address + offset) as *mut StaticLinkedListNode;
+            let mut object = StaticLinkedListNode::new(0);
+            object.next = (*slab).free.take();
+            object_address.write(object);
+
+            (*slab).free = Some(&mut *object_address);
+        }
+
+        self.stats.slabs += 1;
+        self.stats.free += get_slab_capacity(size);
+        Some(&mut *slab)
+    }
+
+    /// Unlink the empty slab at slab_address, of objects of size bytes, from the cache and free its
+    /// page
+    unsafe fn remove_slab(&mut self, slab_address: usize, size: usize) {
+        let mut current = &mut self.slabs;
+        while current
+            .as_ref()
+            .map_or(false, |slab| slab.address() != slab_address)
+        {
+            current = &mut current.as_mut().unwrap().next;
+        }
+
+        let slab = current
+            .take()
+            .expect("[ERROR] Slab does not belong to the cache");
+        *current = slab.next.take();
+
+        self.stats.slabs -= 1;
+        self.stats.free -= get_slab_capacity(size);
+        deallocate_page(slab_address);
+    }
+
+    fn count_empty_slabs(&self) -> usize {
+        let mut count = 0;
+        let mut current = &self.slabs;
+
+        while let Some(slab) = current {
+            if slab.used == 0 {
+                count += 1;
+            }
+
+            current = &slab.next;
+        }
+
+        count
     }
 }
 
@@ -79,12 +255,46 @@ impl LinkedListAllocator {
         self.add_free_node(start_address, size);
     }
 
+    /// Allocate a block fitting the layout from the first node big enough for it. Whatever is left
+    /// of the node, before or after the block, goes back to the linked list.
+    pub unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = LinkedListAllocator::size_align(layout);
+
+        // Check if we can allocate a free node. If not, then we ran out of memory
+        if let Some((node, start)) = self.search_free_node(size, align) {
+            let end = start.checked_add(size).expect("overflow");
+            let (node_address, node_end_address) = (node.address(), node.end_address());
+
+            // Aligning the block may leave space before it, which is also returned to the list
+            if start > node_address {
+                self.add_free_node(node_address, start - node_address);
+            }
+
+            // If there is space left after we take the heap block, then this excess
+            // node should be returned to the linked list
+            if node_end_address > end {
+                self.add_free_node(end, node_end_address - end);
+            }
+
+            start as *mut u8
+        } else {
+            core::ptr::null_mut()
+        }
+    }
+
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = LinkedListAllocator::size_align(layout);
+        self.add_free_node(ptr as usize, size);
+    }
+
     /// This allocator uses a Linked List to store memory nodes. If a memory region is free, it is
     /// added back to this Linked List. But notice, our Linked List is not dynamic, since it is not
     /// implemented using Rust's Box, then how do we do it?
     /// The answer is that we don't need the Box just yet, instead, we can use each freed memory block
     /// to store the Linked List Node information. If the block is allocated, Node data can be erased.
-    pub unsafe fn add_free_node(&mut self, address: usize, size: usize) {
+    /// Nodes are kept sorted by address, so that a freed region is merged with the free regions
+    /// right before and after it, instead of fragmenting the heap over time.
+    pub unsafe fn add_free_node(&mut self, address: usize, mut size: usize) {
         // Ensures the provided address and size is aligned and capable of holding a list node
         assert_eq!(
             ROUND_UP!(address, core::mem::align_of::<StaticLinkedListNode>()),
@@ -93,15 +303,42 @@ impl LinkedListAllocator {
 
         assert!(size >= core::mem::size_of::<StaticLinkedListNode>());
 
+        // Find the last node before the region (or the head)
+        let mut previous = &mut self.head;
+        while previous
+            .next
+            .as_ref()
+            .map_or(false, |next| next.address() < address)
+        {
+            previous = previous.next.as_mut().unwrap();
+        }
+
+        // Merge with the next node, if the region ends right where it starts
+        if let Some(next) = previous.next.take() {
+            if next.address() == address + size {
+                size += next.size;
+                previous.next = next.next.take();
+            } else {
+                previous.next = Some(next);
+            }
+        }
+
+        // Merge with the previous node, if it ends right where the region starts. The head is
+        // zero-sized, so it is never merged.
+        if previous.size > 0 && previous.end_address() == address {
+            previous.size += size;
+            return;
+        }
+
         // Create a new node to hold the memory region
         let mut node = StaticLinkedListNode::new(size);
-        node.next = self.head.next.take(); // When we take, next becomes "None"
+        node.next = previous.next.take(); // When we take, next becomes "None"
 
         // Update the given address with the new node information
         let node_address = address as *mut StaticLinkedListNode;
         node_address.write(node);
 
-        self.head.next = Some(&mut *node_address);
+        previous.next = Some(&mut *node_address);
     }
 
     pub fn allocate_free_node(
@@ -109,7 +346,14 @@ impl LinkedListAllocator {
         size: usize,
         align: usize,
     ) -> Result<usize, ()> {
-        let start = ROUND_UP!(node.address(), align);
+        let node_size = core::mem::size_of::<StaticLinkedListNode>();
+        let mut start = ROUND_UP!(node.address(), align);
+
+        // Space left before the block must also be able to contain Node information
+        if start > node.address() && start - node.address() < node_size {
+            start = ROUND_UP!(node.address() + node_size, align);
+        }
+
         let end = start.checked_add(size).ok_or(())?;
 
         // Node not big enough
@@ -123,7 +367,7 @@ impl LinkedListAllocator {
         // a free region (of node.end - node.start - size). If the newly created free region
         // cannot contain Node information, then it cannot be tracked anymore by our allocator.
         // This would generate an unrecoverable memory leak over time.
-        if excess_size > 0 && excess_size < core::mem::size_of::<StaticLinkedListNode>() {
+        if excess_size > 0 && excess_size < node_size {
             return Err(());
         }
 
@@ -185,9 +429,24 @@ pub fn setup_heap() {
     let heap_address = heap_page_start.as_ptr() as usize;
     let heap_size = PAGE_SIZE * HEAP_PAGES;
 
-    unsafe { HEAP_ALLOCATOR.lock().init(heap_address, heap_size) };
+    unsafe {
+        let mut allocator = HEAP_ALLOCATOR.lock();
+        allocator.list.init(heap_address, heap_size);
+        allocator.start = heap_address;
+        allocator.end = heap_address + heap_size;
+    }
 
     // Enable heap usage across the system
     IS_HEAP_ENABLED.store(true, Ordering::Relaxed);
     println!("[KERNEL] Allocated {} Heap Pages", HEAP_PAGES);
 }
+
+/// Statistics of each slab cache, from the smallest objects to the biggest
+pub fn get_heap_stats() -> [SlabStats; NUMBER_SLAB_CACHES] {
+    let allocator = HEAP_ALLOCATOR.lock();
+
+    core::array::from_fn(|index| SlabStats {
+        size: get_slab_size(index),
+        ..allocator.caches[index].stats
+    })
+}
@@ -7,10 +7,14 @@ use core::sync::atomic::{AtomicUsize, Ordering};
 /// the free list at boot time, only when a page is freed.
 use lazy_static::lazy_static;
 
-use crate::{sync::spin_mutex::SpinMutex, x86::helpers::stosb, P2V, ROUND_UP};
+use crate::{println, sync::spin_mutex::SpinMutex, x86::helpers::stosb, P2V, ROUND_DOWN, ROUND_UP};
 
 use super::{
-    defs::{MemoryRegion, KERNEL_BASE, PAGE_SIZE},
+    defs::{
+        BootInfo, E820Entry, MemoryMap, MemoryRange, MemoryRegion, E820_ATTRIBUTE_VALID,
+        E820_USABLE, EXTENDED_MEMORY, KERNEL_BASE, MAX_MEMORY_MAP_ENTRIES, PAGE_SIZE,
+        PHYSICAL_DEVICE_SPACE,
+    },
     error::MemoryError,
 };
 
@@ -19,6 +23,7 @@ extern "C" {
 }
 
 pub static PHYSICAL_TOP: AtomicUsize = AtomicUsize::new(0xE000000);
+pub static MEMORY_MAP: SpinMutex<MemoryMap> = SpinMutex::new(MemoryMap::new());
 
 lazy_static! {
     pub static ref MEMORY_REGION: SpinMutex<MemoryRegion> = {
@@ -57,7 +62,7 @@ impl MemoryRegion {
     /// Gets the next page available and increase the counter. Once no more pages are available,
     /// it raises an OutOfMemory exception.
     pub fn next(&mut self, number_pages: usize) -> Result<*mut u8, MemoryError> {
-        if self.index as usize + PAGE_SIZE * number_pages > self.end {
+        if self.start + PAGE_SIZE * (self.index + number_pages) > self.end {
             return Err(MemoryError::OutOfMemory);
         }
 
@@ -67,6 +72,153 @@ impl MemoryRegion {
 
         Ok(address as *mut u8)
     }
+
+    /// Takes every page left in the region, returning its start and end addresses. Used to hand
+    /// the rest of the memory over to the frame allocator.
+    pub fn take_remaining(&mut self) -> (usize, usize) {
+        let start = self.start + PAGE_SIZE * self.index;
+        self.index = (self.end - self.start) / PAGE_SIZE;
+        (start, self.end)
+    }
 }
 
 unsafe impl Send for MemoryRegion {}
+
+/// The memory map tells which physical memory can be used by the Kernel. It is built from the
+/// entries provided by the BIOS, which may be unsorted, overlap, and leave holes (such as the
+/// memory reserved for ACPI tables). Only memory from EXTENDED_MEMORY to the device space is
+/// considered, since memory below it is mapped as I/O space.
+impl MemoryMap {
+    pub const fn new() -> Self {
+        MemoryMap {
+            entries: [E820Entry {
+                base: 0,
+                length: 0,
+                _type: 0,
+                attributes: 0,
+            }; MAX_MEMORY_MAP_ENTRIES],
+            number_entries: 0,
+            usable: [MemoryRange { start: 0, end: 0 }; MAX_MEMORY_MAP_ENTRIES],
+            number_usable: 0,
+        }
+    }
+
+    pub fn get_entries(&self) -> &[E820Entry] {
+        &self.entries[..self.number_entries]
+    }
+
+    pub fn get_usable(&self) -> &[MemoryRange] {
+        &self.usable[..self.number_usable]
+    }
+
+    /// Build the usable ranges from the entries of the BIOS. Memory is usable if an entry reports
+    /// it as usable and no other entry reserves it.
+    pub fn init(&mut self, entries: &[E820Entry]) {
+        self.number_entries = core::cmp::min(entries.len(), MAX_MEMORY_MAP_ENTRIES);
+        self.entries[..self.number_entries].copy_from_slice(&entries[..self.number_entries]);
+        self.number_usable = 0;
+
+        let entries = &entries[..self.number_entries];
+        let valid_entries = || {
+            entries
+                .iter()
+                .filter(|entry| entry.attributes & E820_ATTRIBUTE_VALID > 0)
+                .copied()
+        };
+
+        // Usable ranges only hold whole pages, and overlapping ones are merged
+        for entry in valid_entries().filter(|entry| entry._type == E820_USABLE) {
+            let (start, end) = get_entry_range(&entry);
+            let (start, end) = (ROUND_UP!(start, PAGE_SIZE), ROUND_DOWN!(end, PAGE_SIZE));
+            self.remove_usable(start, end);
+            self.add_usable(start, end);
+        }
+
+        // Pages partially reserved cannot be used either
+        for entry in valid_entries().filter(|entry| entry._type != E820_USABLE) {
+            let (start, end) = get_entry_range(&entry);
+            self.remove_usable(ROUND_DOWN!(start, PAGE_SIZE), ROUND_UP!(end, PAGE_SIZE));
+        }
+    }
+
+    /// Add a range of memory, keeping ranges sorted. The range must not overlap with any other.
+    pub fn add_usable(&mut self, start: usize, end: usize) {
+        if start >= end || self.number_usable == MAX_MEMORY_MAP_ENTRIES {
+            return;
+        }
+
+        let position = self
+            .get_usable()
+            .iter()
+            .position(|range| range.start > start)
+            .unwrap_or(self.number_usable);
+
+        self.usable
+            .copy_within(position..self.number_usable, position + 1);
+        self.usable[position] = MemoryRange { start, end };
+        self.number_usable += 1;
+    }
+
+    /// Remove a range of memory, splitting the usable ranges that overlap with it
+    pub fn remove_usable(&mut self, start: usize, end: usize) {
+        let mut index = 0;
+
+        while index < self.number_usable {
+            let range = self.usable[index];
+
+            if range.end <= start || range.start >= end {
+                index += 1;
+                continue;
+            }
+
+            self.usable
+                .copy_within((index + 1)..self.number_usable, index);
+            self.number_usable -= 1;
+
+            // Keep what is left on each side of the removed range
+            self.add_usable(range.start, core::cmp::min(range.end, start));
+            self.add_usable(core::cmp::max(range.start, end), range.end);
+            index = 0;
+        }
+    }
+}
+
+// Physical range of an entry, limited to the memory that can be mapped by the Kernel
+fn get_entry_range(entry: &E820Entry) -> (usize, usize) {
+    let limit = |address: u64| address.clamp(EXTENDED_MEMORY as u64, PHYSICAL_DEVICE_SPACE as u64);
+    let start = limit(entry.base);
+    let end = limit(entry.base.saturating_add(entry.length));
+    (start as usize, end as usize)
+}
+
+/// Read the memory map handed over by the bootloader, moving PHYSICAL_TOP to the end of usable
+/// memory. Must run before any page is allocated.
+pub fn setup_memory_map(boot_info: *const BootInfo) {
+    let boot_info = unsafe { &*(P2V!(boot_info as usize) as *const BootInfo) };
+    let number_entries = boot_info.number_memory_entries as usize;
+    let number_entries = core::cmp::min(number_entries, MAX_MEMORY_MAP_ENTRIES);
+
+    let mut memory_map = MEMORY_MAP.lock();
+    memory_map.init(&boot_info.memory_map[..number_entries]);
+
+    // Without a memory map, memory is assumed to go up to the default physical top
+    if memory_map.number_usable == 0 {
+        println!("[WARNING] BIOS did not provide a memory map");
+        memory_map.add_usable(EXTENDED_MEMORY, PHYSICAL_TOP.load(Ordering::Relaxed));
+    }
+
+    let physical_top = memory_map.get_usable().last().unwrap().end;
+    PHYSICAL_TOP.store(physical_top, Ordering::Relaxed);
+
+    let usable_size: usize = memory_map
+        .get_usable()
+        .iter()
+        .map(|range| range.end - range.start)
+        .sum();
+
+    println!(
+        "[KERNEL] Found {} MiB of Usable Memory (Top at 0x{:X})",
+        usable_size / 0x100000,
+        physical_top
+    );
+}
@@ -1,5 +1,6 @@
 pub mod defs;
 pub mod error;
+pub mod frame;
 pub mod gdt;
 pub mod heap;
 pub mod mem;
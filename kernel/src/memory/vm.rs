@@ -1,21 +1,36 @@
 use core::{panic, sync::atomic::Ordering};
 
+use alloc::{vec, vec::Vec};
+
 use lazy_static::lazy_static;
 
 use super::{
     defs::*,
     error::MemoryError,
-    heap::IS_HEAP_ENABLED,
-    mem::{mem_set, MEMORY_REGION, PHYSICAL_TOP},
+    frame::{get_block_order, FRAME_ALLOCATOR},
+    mem::{mem_move, mem_set, MEMORY_MAP, MEMORY_REGION, PHYSICAL_TOP},
+};
+
+#[cfg(feature = "pae")]
+use crate::x86::{
+    defs::{CPUID_EXTENDED_FEATURES, CPUID_NX_FLAG_BIT, EFER_NXE, MSR_EFER},
+    helpers::{cpuid, read_msr, write_msr},
 };
 
 use crate::{
+    apic::{
+        defs::IRQ_TLB_FLUSH,
+        local_apic::local_apic_send_irq,
+        mp::{get_my_cpu, CPUS, IS_CPU_MAPPED},
+    },
     println,
-    scheduler::{self, scheduler::SCHEDULER},
-    structures::heap_linked_list::HeapLinkedList,
-    sync::spin_mutex::SpinMutex,
-    x86::helpers::load_cr3,
-    P2V, PAGE_DIR_INDEX, PAGE_TABLE_INDEX, PTE_ADDRESS, ROUND_DOWN, V2P,
+    scheduler::{self, mmap::fault_in_page, process::lock_process_memory, scheduler::SCHEDULER},
+    sync::{
+        cpu_cli::{pop_cli, push_cli},
+        spin_mutex::SpinMutex,
+    },
+    x86::helpers::{load_cr3, read_cr3},
+    P2V, PAGE_DIR_INDEX, PAGE_TABLE_INDEX, PTE_ADDRESS, PTE_FLAGS, ROUND_DOWN, V2P,
 };
 
 extern "C" {
@@ -25,8 +40,9 @@ extern "C" {
 
 // Entirety of Kernel Virtual Memory. Those are segments of memory meant to be loaded by the
 // Setup Kernel Virtual Memory procedure, allowing certain portions to be written to and some to only be read.
+// Kernel Data and the rest of the memory are mapped according to the memory map (see MEMORY_MAP).
 lazy_static! {
-    static ref KERNEL_MEMORY_LAYOUT: SpinMutex<[MemoryLayoutEntry; 4]> = SpinMutex::new([
+    static ref KERNEL_MEMORY_LAYOUT: SpinMutex<[MemoryLayoutEntry; 3]> = SpinMutex::new([
         // I/O Address Space
         MemoryLayoutEntry {
             virt: KERNEL_BASE as *const usize,
@@ -41,13 +57,6 @@ lazy_static! {
             phys_end: unsafe { V2P!(&KERNEL_DATA as *const u8 as usize) },
             perm: 0,
         },
-        // Kernel Data + Memory
-        MemoryLayoutEntry {
-            virt: unsafe { &KERNEL_DATA as *const u8 as *const usize },
-            phys_start: unsafe { V2P!(&KERNEL_DATA as *const u8 as usize) },
-            phys_end: PHYSICAL_TOP.load(Ordering::Relaxed),
-            perm: PTE_W,
-        },
         // Other Devices
         MemoryLayoutEntry {
             virt: DEVICE_SPACE as *const usize,
@@ -59,53 +68,324 @@ lazy_static! {
 }
 
 pub static KERNEL_PAGE_DIR: SpinMutex<Option<usize>> = SpinMutex::new(None);
-pub static FREE_PAGE_LIST: SpinMutex<HeapLinkedList<usize>> = SpinMutex::new(HeapLinkedList::new());
 
-/// Perform the allocation of pages. Gives priority to pages in the free list. If there
-/// are no pages in the free list, then allocates from the static memory region. If no
-/// pages are avaialable, raise an exception.
+/// Perform the allocation of a block of 2^order pages. Pages come from the frame allocator, or,
+/// while it is not yet setup, from the static memory region. If no pages are avaialable, raise
+/// an exception.
+fn allocate_block<'a>(order: usize, usage: FrameUsage) -> Result<Page<'a>, MemoryError> {
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+
+    if !frame_allocator.is_enabled() {
+        let address = MEMORY_REGION.lock().next(1 << order)?;
+        return Ok(Page::new(address));
+    }
+
+    let address = frame_allocator.allocate(order, usage)?;
+    Ok(Page::new(address as *mut u8))
+}
+
+/// Allocate a page for the Kernel, such as a page table or a Kernel stack
 pub fn allocate_page<'a>() -> Result<Page<'a>, MemoryError> {
-    if IS_HEAP_ENABLED.load(Ordering::Relaxed) {
-        match FREE_PAGE_LIST.lock().pop() {
-            Some(address) => return Ok(Page::new(address as *mut u8)),
-            None => (),
+    allocate_block(0, FrameUsage::Kernel)
+}
+
+/// Allocate a page to be mapped into the memory of a user process
+pub fn allocate_user_page<'a>() -> Result<Page<'a>, MemoryError> {
+    allocate_block(0, FrameUsage::User)
+}
+
+/// Allocate physically contiguous pages for the Kernel. The number of pages is rounded up to a
+/// power of two. The whole block is freed by a single call to deallocate_page.
+pub fn allocate_pages<'a>(number_pages: usize) -> Result<Page<'a>, MemoryError> {
+    allocate_block(get_block_order(number_pages), FrameUsage::Kernel)
+}
+
+/// Allocate an empty page directory. With PAE, the page returned is the page directory pointer
+/// table, and the page directories it points to are allocated next to each other, so that they can
+/// be indexed as if they were a single one (see get_page_dir_entries).
+pub fn allocate_page_dir<'a>() -> Result<Page<'a>, MemoryError> {
+    let mut page_dir = allocate_page()?;
+    page_dir.zero();
+
+    #[cfg(feature = "pae")]
+    {
+        let page_dirs = match allocate_pages(NUMBER_PAGE_DIRS) {
+            Ok(page_dirs) => page_dirs,
+            Err(error) => {
+                deallocate_page(page_dir.as_ptr() as usize);
+                return Err(error);
+            }
         };
+
+        // Entries of the page directory pointer table only accept the present bit
+        let page_dir_pointers = page_dir.cast_to::<PageTableEntry>();
+        for i in 0..NUMBER_PAGE_DIRS {
+            let address = page_dirs.as_ptr() as usize + i * PAGE_SIZE;
+            Page::new(address as *mut u8).zero();
+            page_dir_pointers[i] = V2P!(address) as PageTableEntry | PTE_P;
+        }
     }
 
-    let address = MEMORY_REGION.lock().next(1)?;
-    return Ok(Page::new(address));
+    Ok(page_dir)
 }
 
-pub fn allocate_pages<'a>(number_pages: usize) -> Result<Page<'a>, MemoryError> {
-    let address = MEMORY_REGION.lock().next(number_pages)?;
-    Ok(Page::new(address))
+/// Entries of all the page directories of page_dir, indexed by PAGE_DIR_INDEX
+#[cfg(not(feature = "pae"))]
+pub fn get_page_dir_entries<'a>(page_dir: &'a mut Page) -> &'a mut [PageTableEntry] {
+    page_dir.cast_to::<PageTableEntry>()
+}
+
+/// Entries of all the page directories of page_dir, indexed by PAGE_DIR_INDEX
+#[cfg(feature = "pae")]
+pub fn get_page_dir_entries<'a>(page_dir: &'a mut Page) -> &'a mut [PageTableEntry] {
+    let page_dirs_address = P2V!(PTE_ADDRESS!(page_dir.cast_to::<PageTableEntry>()[0]));
+    unsafe {
+        core::slice::from_raw_parts_mut(
+            page_dirs_address as *mut PageTableEntry,
+            NUMBER_PAGE_DIR_ENTRIES,
+        )
+    }
 }
 
-/// TODO: Finish this
+/// Free a page directory, along with its page tables and the user pages mapped by them. Kernel
+/// memory is mapped by every page directory and is never freed. The page directory must not be
+/// loaded.
 pub fn deallocate_page_dir(page_dir: &mut Page) {
-    let page_dir_slice = page_dir.cast_to::<usize>();
+    let page_dir_slice = get_page_dir_entries(page_dir);
+
+    for i in 0..NUMBER_PAGE_DIR_ENTRIES {
+        let page_dir_entry = page_dir_slice[i];
+
+        if page_dir_entry & PTE_P == 0 {
+            continue;
+        }
 
-    for i in 0..NUMBER_PAGE_ENTRIES {
-        let page_entry = page_dir_slice[i * PAGE_ENTRY_SIZE];
+        let mut page_table = Page::new(P2V!(PTE_ADDRESS!(page_dir_entry)) as *mut u8);
 
-        if page_entry & PTE_P > 0 {
-            let page_address = P2V!(PTE_ADDRESS!(page_entry));
-            deallocate_page(page_address);
+        // Pages above KERNEL_BASE belong to the Kernel
+        if i < PAGE_DIR_INDEX!(KERNEL_BASE) {
+            for &page_table_entry in page_table.cast_to::<PageTableEntry>().iter() {
+                if page_table_entry & PTE_P > 0 {
+                    deallocate_page(P2V!(PTE_ADDRESS!(page_table_entry)));
+                }
+            }
         }
+
+        deallocate_page(page_table.as_ptr() as usize);
     }
 
+    #[cfg(feature = "pae")]
+    deallocate_page(page_dir_slice.as_ptr() as usize);
+
     deallocate_page(page_dir.as_ptr() as usize);
 }
 
-/// Add pages to the Free List. The Free List can only be used if Heap is enabled.
+/// Drop a reference to the page (or block of pages). Once its last reference is dropped, the
+/// page is returned to the frame allocator. Pages can only be freed once it is setup.
 pub fn deallocate_page(page_address: usize) {
     assert!(page_address % PAGE_SIZE == 0);
 
-    if IS_HEAP_ENABLED.load(Ordering::Relaxed) == false {
-        panic!("[ERROR] Cannot dealocate without heap");
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    if !frame_allocator.is_enabled() {
+        panic!("[ERROR] Cannot dealocate without frame allocator");
+    }
+
+    frame_allocator.deallocate(page_address);
+}
+
+/// Add a reference to the page, which is now mapped by one more page directory.
+pub fn share_page(page_address: usize) {
+    FRAME_ALLOCATOR.lock().share(page_address);
+}
+
+pub fn get_page_references(page_address: usize) -> usize {
+    FRAME_ALLOCATOR.lock().get_references(page_address)
+}
+
+/// Changes to the entries of a page directory only take effect once the TLB of every CPU that has
+/// it loaded is flushed, such as when threads of the same process run on several CPUs. Those CPUs
+/// are interrupted to flush their own TLB, and this waits until all of them did, so that pages
+/// that are no longer mapped can be freed right after. A CPU that waits for a lock with interrupts
+/// disabled still flushes its TLB when asked to (see SpinMutex::lock), and so does a CPU that waits
+/// here, so no CPU ever waits for another one that waits for it.
+pub fn flush_page_dir(page_dir: &Page) {
+    let page_dir_address = V2P!(page_dir.as_ptr() as usize);
+    if read_cr3() == page_dir_address {
+        load_cr3(page_dir_address);
+    }
+
+    if !IS_CPU_MAPPED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    push_cli();
+    let my_cpu = get_my_cpu();
+
+    // Generation of the TLB of every CPU asked to flush it, since they flushed it last
+    let mut requests = Vec::new();
+    for cpu in CPUS.iter().flatten() {
+        let is_other = cpu.apic_id != my_cpu.apic_id;
+        if is_other && cpu.page_dir.load(Ordering::SeqCst) == page_dir_address {
+            let generation = cpu.tlb_generation.load(Ordering::SeqCst);
+            cpu.tlb_flush.store(true, Ordering::SeqCst);
+            local_apic_send_irq(cpu.apic_id, IRQ_TLB_FLUSH);
+            requests.push((cpu, generation));
+        }
+    }
+
+    for (cpu, generation) in requests {
+        while cpu.tlb_generation.load(Ordering::SeqCst) == generation {
+            handle_tlb_flush();
+            core::hint::spin_loop();
+        }
+    }
+
+    pop_cli();
+}
+
+/// Flushes the TLB of this CPU if another one asked for it (see flush_page_dir)
+pub fn handle_tlb_flush() {
+    if !IS_CPU_MAPPED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let cpu = get_my_cpu();
+    if cpu.tlb_flush.swap(false, Ordering::SeqCst) {
+        load_cr3(read_cr3());
+        cpu.tlb_generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Loads the page directory at the given physical address on this CPU. It is recorded first, so
+/// that a CPU that changes its entries afterwards knows that this TLB must be flushed too.
+pub fn load_page_dir(page_dir_address: usize) {
+    if !IS_CPU_MAPPED.load(Ordering::Relaxed) {
+        load_cr3(page_dir_address);
+        return;
     }
 
-    FREE_PAGE_LIST.lock().push(page_address);
+    // Loading a page directory flushes the TLB, which answers any pending request
+    let cpu = get_my_cpu();
+    cpu.page_dir.store(page_dir_address, Ordering::SeqCst);
+    cpu.tlb_flush.store(false, Ordering::SeqCst);
+    load_cr3(page_dir_address);
+    cpu.tlb_generation.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Resolve a write to a copy-on-write page of the user memory, shared with other page directories
+/// by fork. The writer gets its own copy of the page, unless it holds the last reference to it, in
+/// which case the page simply becomes writable again. The caller holds the memory lock of the
+/// process (see lock_process_memory).
+pub fn copy_on_write(page_dir: &mut Page, virtual_address: usize) -> Result<(), MemoryError> {
+    let error = MemoryError::InvalidUserAddress(virtual_address as u32);
+
+    if virtual_address >= KERNEL_BASE {
+        return Err(error);
+    }
+
+    let page_table_entry = walk_page_dir(page_dir, virtual_address, false).or(Err(error))?;
+    let entry = unsafe { *page_table_entry };
+
+    let required_flags = PTE_P | PTE_U | PTE_COW;
+    if entry & required_flags != required_flags {
+        return Err(error);
+    }
+
+    let page_address = P2V!(PTE_ADDRESS!(entry));
+    let flags = (PTE_FLAGS!(entry) | PTE_W) & !PTE_COW;
+
+    if get_page_references(page_address) == 1 {
+        unsafe { *page_table_entry = PTE_ADDRESS!(entry) as PageTableEntry | flags };
+        flush_page_dir(page_dir);
+    } else {
+        let mut page = allocate_user_page()?;
+        unsafe { mem_move(page_address as *mut u8, page.as_mut_ptr(), PAGE_SIZE) };
+        unsafe { *page_table_entry = V2P!(page.as_ptr() as usize) as PageTableEntry | flags };
+
+        // Other CPUs must stop using the original before this reference to it is dropped
+        flush_page_dir(page_dir);
+        deallocate_page(page_address);
+    }
+
+    Ok(())
+}
+
+/// Reserve the user pages from start to end without allocating them. Reserved pages are only
+/// backed by memory, with the given permissions, once they are first accessed (see
+/// back_lazy_page). Pages that are already mapped or reserved are left untouched.
+pub fn reserve_lazy_pages(
+    page_dir: &mut Page,
+    start: usize,
+    end: usize,
+    perm: PageTableEntry,
+) -> Result<(), MemoryError> {
+    let mut current_address = ROUND_DOWN!(start, PAGE_SIZE);
+    while current_address < end {
+        let page_table_entry = walk_page_dir(page_dir, current_address, true)?;
+
+        if unsafe { *page_table_entry } == 0 {
+            unsafe { *page_table_entry = PTE_LAZY | perm };
+        }
+
+        current_address += PAGE_SIZE;
+    }
+
+    Ok(())
+}
+
+/// Back a reserved page of the user memory with a zeroed page, which starts with data. Fails if
+/// the page at virtual_address was never reserved, as it does not belong to the process.
+pub fn back_lazy_page(
+    page_dir: &mut Page,
+    virtual_address: usize,
+    data: &[u8],
+) -> Result<(), MemoryError> {
+    let error = MemoryError::InvalidUserAddress(virtual_address as u32);
+
+    if virtual_address >= KERNEL_BASE {
+        return Err(error);
+    }
+
+    let page_table_entry = walk_page_dir(page_dir, virtual_address, false).or(Err(error))?;
+    let entry = unsafe { *page_table_entry };
+
+    if entry & (PTE_P | PTE_LAZY) != PTE_LAZY {
+        return Err(error);
+    }
+
+    let mut page = allocate_user_page()?;
+    page.zero();
+    page.cast_to::<u8>()[..data.len()].copy_from_slice(data);
+
+    let flags = PTE_FLAGS!(entry) & !PTE_LAZY;
+    let page_address = V2P!(page.as_ptr() as usize) as PageTableEntry;
+    unsafe { *page_table_entry = page_address | flags | PTE_P };
+    Ok(())
+}
+
+/// Unmap the user pages from start to end, freeing the ones that were backed. Reserved pages are
+/// simply forgotten. Pages are only freed once no CPU can reach them through its TLB anymore.
+pub fn unmap_user_pages(page_dir: &mut Page, start: usize, end: usize) {
+    assert!(end <= KERNEL_BASE);
+
+    let mut pages = Vec::new();
+    let mut current_address = ROUND_DOWN!(start, PAGE_SIZE);
+    while current_address < end {
+        if let Ok(page_table_entry) = walk_page_dir(page_dir, current_address, false) {
+            let entry = unsafe { *page_table_entry };
+
+            if entry & PTE_P > 0 {
+                pages.push(P2V!(PTE_ADDRESS!(entry)));
+            }
+
+            unsafe { *page_table_entry = 0 };
+        }
+
+        current_address += PAGE_SIZE;
+    }
+
+    flush_page_dir(page_dir);
+    pages.into_iter().for_each(deallocate_page);
 }
 
 /// Walk Page Directory uses the provided virtual memory address (virtual_address) to index
@@ -115,17 +395,17 @@ pub fn walk_page_dir(
     page_dir: &mut Page,
     virtual_address: usize,
     should_allocate: bool,
-) -> Result<*mut usize, MemoryError> {
+) -> Result<*mut PageTableEntry, MemoryError> {
     let mut page_table: Page;
 
     let pg_dir_offset = PAGE_DIR_INDEX!(virtual_address as usize);
-    let pg_dir_slice = page_dir.cast_to::<usize>();
+    let pg_dir_slice = get_page_dir_entries(page_dir);
     let pg_dir_entry = pg_dir_slice[pg_dir_offset];
     let is_entry_present = (pg_dir_entry & PTE_P) > 0;
 
     // If entry is already present, set page table simply as the address pointed by the entry
     if is_entry_present == true {
-        page_table = Page::new(P2V!(pg_dir_entry & !0xFFF) as *mut u8);
+        page_table = Page::new(P2V!(PTE_ADDRESS!(pg_dir_entry)) as *mut u8);
     } else {
         // Since page was not found, we need to allocate
         if !should_allocate {
@@ -136,16 +416,144 @@ pub fn walk_page_dir(
         page_table = allocate_page()?;
         page_table.zero();
 
-        pg_dir_slice[pg_dir_offset] = V2P!(page_table.as_ptr() as usize) | PTE_P | PTE_W | PTE_U;
+        let page_table_address = V2P!(page_table.as_ptr() as usize) as PageTableEntry;
+        pg_dir_slice[pg_dir_offset] = page_table_address | PTE_P | PTE_W | PTE_U;
     }
 
     // Find page table entry
-    let page_table_data = page_table.cast_to::<usize>();
+    let page_table_data = page_table.cast_to::<PageTableEntry>();
     let page_table_offset = PAGE_TABLE_INDEX!(virtual_address as usize);
     let page_table_entry = &mut page_table_data[page_table_offset];
 
     // Return the page table entry pointer
-    Ok(page_table_entry as *mut usize)
+    Ok(page_table_entry as *mut PageTableEntry)
+}
+
+/// Translate a user virtual address into the kernel address of the same byte. Fails if the page is
+/// not mapped, cannot be accessed by the user (such as the stack guard page) or, when write is
+/// set, cannot be written by the user. Reserved pages are backed, and copy-on-write pages are
+/// copied when write is set. The caller holds the memory lock of the process until it is done with
+/// the address, since another thread could unmap the page meanwhile.
+fn translate_user_address(
+    page_dir: &mut Page,
+    virtual_address: usize,
+    write: bool,
+) -> Result<usize, MemoryError> {
+    let error = MemoryError::InvalidUserAddress(virtual_address as u32);
+
+    if virtual_address >= KERNEL_BASE {
+        return Err(error);
+    }
+
+    let page_table_entry_pointer =
+        walk_page_dir(page_dir, virtual_address, false).or(Err(error))?;
+    let mut page_table_entry = unsafe { *page_table_entry_pointer };
+
+    // A reserved page is backed before the Kernel accesses it, as if the process had touched it
+    if page_table_entry & PTE_LAZY > 0 {
+        fault_in_page(page_dir, virtual_address)?;
+        page_table_entry = unsafe { *page_table_entry_pointer };
+    }
+
+    // A page shared by fork must be copied before the Kernel writes into it
+    if write && page_table_entry & PTE_COW > 0 {
+        copy_on_write(page_dir, virtual_address)?;
+        page_table_entry = unsafe { *page_table_entry_pointer };
+    }
+
+    let required_flags = if write {
+        PTE_P | PTE_U | PTE_W
+    } else {
+        PTE_P | PTE_U
+    };
+    if page_table_entry & required_flags != required_flags {
+        return Err(error);
+    }
+
+    Ok(P2V!(PTE_ADDRESS!(page_table_entry)) + virtual_address % PAGE_SIZE)
+}
+
+/// Check that the whole range [virtual_address, virtual_address + length) belongs to the user
+/// memory of page_dir, so that it can be read (or written, if write is set) by the Kernel on
+/// behalf of the user.
+pub fn check_user_range(
+    page_dir: &mut Page,
+    virtual_address: usize,
+    length: usize,
+    write: bool,
+) -> Result<(), MemoryError> {
+    let _memory_lock = lock_process_memory();
+    check_locked_user_range(page_dir, virtual_address, length, write)
+}
+
+/// Same as check_user_range, for callers that already hold the memory lock of the process
+fn check_locked_user_range(
+    page_dir: &mut Page,
+    virtual_address: usize,
+    length: usize,
+    write: bool,
+) -> Result<(), MemoryError> {
+    let end_address = virtual_address
+        .checked_add(length)
+        .filter(|&end_address| end_address <= KERNEL_BASE)
+        .ok_or(MemoryError::InvalidUserAddress(virtual_address as u32))?;
+
+    let mut current_address = ROUND_DOWN!(virtual_address, PAGE_SIZE);
+    while current_address < end_address {
+        translate_user_address(page_dir, current_address, write)?;
+        current_address += PAGE_SIZE;
+    }
+
+    Ok(())
+}
+
+/// Copy length bytes from the user memory of page_dir into the Kernel. Every system call must read
+/// user memory through this function, as user pointers cannot be trusted.
+pub fn copy_from_user(
+    page_dir: &mut Page,
+    virtual_address: usize,
+    length: usize,
+) -> Result<Vec<u8>, MemoryError> {
+    // Validate the range before allocating the buffer, since length is chosen by the user
+    let _memory_lock = lock_process_memory();
+    check_locked_user_range(page_dir, virtual_address, length, false)?;
+
+    let mut buffer = vec![0; length];
+    let mut count = 0;
+    while count < length {
+        let address = translate_user_address(page_dir, virtual_address + count, false)?;
+        let byte_count = core::cmp::min(length - count, PAGE_SIZE - address % PAGE_SIZE);
+        let source = unsafe { core::slice::from_raw_parts(address as *const u8, byte_count) };
+
+        buffer[count..(count + byte_count)].copy_from_slice(source);
+        count += byte_count;
+    }
+
+    Ok(buffer)
+}
+
+/// Copy data from the Kernel into the user memory of page_dir. The whole range is validated
+/// before anything is written.
+pub fn copy_to_user(
+    page_dir: &mut Page,
+    virtual_address: usize,
+    data: &[u8],
+) -> Result<(), MemoryError> {
+    let _memory_lock = lock_process_memory();
+    check_locked_user_range(page_dir, virtual_address, data.len(), true)?;
+
+    let mut count = 0;
+    while count < data.len() {
+        let address = translate_user_address(page_dir, virtual_address + count, true)?;
+        let byte_count = core::cmp::min(data.len() - count, PAGE_SIZE - address % PAGE_SIZE);
+        let destination =
+            unsafe { core::slice::from_raw_parts_mut(address as *mut u8, byte_count) };
+
+        destination.copy_from_slice(&data[count..(count + byte_count)]);
+        count += byte_count;
+    }
+
+    Ok(())
 }
 
 /// Map a continuous range of physical pages into the provided page directory, starting at
@@ -156,7 +564,7 @@ pub fn map_pages(
     virtual_address: usize,
     size: usize,
     mut physical_address: usize,
-    perm: usize,
+    perm: PageTableEntry,
 ) -> Result<*mut u8, MemoryError> {
     assert!(size > 0);
 
@@ -174,7 +582,7 @@ pub fn map_pages(
         }
 
         // Map the page entry to the physical address
-        unsafe { *page_table_entry = physical_address | perm | PTE_P }
+        unsafe { *page_table_entry = physical_address as PageTableEntry | perm | PTE_P }
 
         if current_address >= end_address {
             break;
@@ -187,12 +595,8 @@ pub fn map_pages(
     Ok(start_address)
 }
 
-/// Maps each one of the entries of KERNEL_MEMORY_LAYOUT into a new page directory,
-/// later switching CR3 to this new page directory.
-pub fn setup_kernel_page_tables<'a>() -> Result<Page<'a>, MemoryError> {
-    let mut page_dir: Page = allocate_page().expect("[ERROR] Failed to allocate page");
-    page_dir.zero();
-
+/// Maps each one of the entries of KERNEL_MEMORY_LAYOUT and the usable memory into page_dir
+fn map_kernel_memory(page_dir: &mut Page) -> Result<(), MemoryError> {
     // Physical top cannot be above the device space
     let physical_top = PHYSICAL_TOP.load(Ordering::Relaxed);
     if P2V!(physical_top) > DEVICE_SPACE {
@@ -202,7 +606,7 @@ pub fn setup_kernel_page_tables<'a>() -> Result<Page<'a>, MemoryError> {
     // Map each memory region as defined in the Kernel Memory Layout
     for entry in KERNEL_MEMORY_LAYOUT.lock().iter() {
         map_pages(
-            &mut page_dir,
+            page_dir,
             entry.virt as usize,
             entry.phys_end.wrapping_sub(entry.phys_start),
             entry.phys_start,
@@ -210,6 +614,29 @@ pub fn setup_kernel_page_tables<'a>() -> Result<Page<'a>, MemoryError> {
         )?;
     }
 
+    // Map Kernel Data and the usable memory above it, skipping the holes reserved by the BIOS
+    let kernel_data = unsafe { V2P!(&KERNEL_DATA as *const u8 as usize) };
+    for range in MEMORY_MAP.lock().get_usable() {
+        let start = core::cmp::max(range.start, kernel_data);
+        if start < range.end {
+            map_pages(page_dir, P2V!(start), range.end - start, start, PTE_W)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Maps each one of the entries of KERNEL_MEMORY_LAYOUT into a new page directory,
+/// later switching CR3 to this new page directory.
+pub fn setup_kernel_page_tables<'a>() -> Result<Page<'a>, MemoryError> {
+    let mut page_dir: Page = allocate_page_dir()?;
+
+    // A page directory that cannot be fully mapped must not leak its page tables
+    if let Err(error) = map_kernel_memory(&mut page_dir) {
+        deallocate_page_dir(&mut page_dir);
+        return Err(error);
+    }
+
     Ok(page_dir)
 }
 
@@ -218,6 +645,9 @@ pub fn setup_kernel_page_tables<'a>() -> Result<Page<'a>, MemoryError> {
 /// Here, we need to map the Kernel's memory layout to the one defined in KERNEL_MEMORY_LAYOUT,
 /// ensuring access to the entirety of the physical space.
 pub fn setup_vm() {
+    #[cfg(feature = "pae")]
+    enable_no_execute();
+
     let page_dir = setup_kernel_page_tables().expect("[ERR] Failed to Setup Virtual Memory");
     let page_dir_ptr = page_dir.as_ptr();
 
@@ -229,6 +659,18 @@ pub fn setup_vm() {
     println!("[KERNEL] Virtual Memory Initialized");
 }
 
+/// Pages can only be made non-executable once the CPU is told to honour PTE_NX, which must happen
+/// before any page directory using it is loaded.
+#[cfg(feature = "pae")]
+pub fn enable_no_execute() {
+    let (_, _, edx) = cpuid(CPUID_EXTENDED_FEATURES);
+    if edx & CPUID_NX_FLAG_BIT == 0 {
+        panic!("[FATAL] No-Execute pages unavailable");
+    }
+
+    write_msr(MSR_EFER, read_msr(MSR_EFER) | EFER_NXE);
+}
+
 unsafe impl Send for MemoryLayoutEntry {}
 unsafe impl Send for Page<'_> {}
 
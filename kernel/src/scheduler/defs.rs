@@ -1,7 +1,13 @@
 pub mod process {
     use alloc::{string::String, sync::Arc, vec::Vec};
 
-    use crate::sync::spin_mutex::SpinMutex;
+    use crate::{
+        filesystem::{
+            file::{FileDescriptor, MAX_OPEN_FILES},
+            fs::INodeReference,
+        },
+        sync::spin_mutex::SpinMutex,
+    };
 
     #[derive(Debug, Copy, Clone, PartialEq)]
     pub enum ProcessState {
@@ -11,6 +17,7 @@ pub mod process {
         READY,
         KILLED,
         SLEEPING,
+        ZOMBIE, // Exited, but not yet waited for by its parent
     }
 
     #[repr(C)]
@@ -72,10 +79,50 @@ pub mod process {
         pub trapframe: Option<*mut TrapFrame>,
         pub kernel_stack: Option<*mut usize>,
         pub mem_size: usize,
+        pub heap_start: usize,
         pub parent: Option<Arc<SpinMutex<Process>>>,
         pub sleep_object: usize,
+        pub exit_code: i32,
         pub current_working_directory: String,
         pub name: String,
+        pub open_files: [Option<FileDescriptor>; MAX_OPEN_FILES],
+        pub mappings: Vec<MemoryMapping>, // Sorted by address
+        pub priority: usize,              // Highest level the process can be boosted to
+        pub level: usize,                 // Current level of the feedback queue (see mlfq.rs)
+        pub slice_ticks: usize,           // Timer ticks run at the current level
+        pub user_ticks: usize,            // Timer ticks spent running user code
+        pub kernel_ticks: usize,          // Timer ticks spent in the Kernel
+        pub context_switches: usize,      // Times the process has been switched to
+        pub start_tick: usize,            // Uptime when created (see irqs::TICKS)
+        pub thread_count: usize,          // Threads sharing the memory of a leader, itself included
+        pub leader: Option<Arc<SpinMutex<Process>>>, // Process whose memory a thread shares
+        pub killed: bool, // Set when another thread calls exec (see end_other_threads)
+        pub adopted: bool, // Reparented to init when its parent exited (see reap_orphans)
+    }
+
+    /// Snapshot of a process, as returned by the PROCESS_STATS system call
+    #[derive(Debug, Clone)]
+    pub struct ProcessStats {
+        pub pid: usize,
+        pub parent_pid: Option<usize>,
+        pub state: ProcessState,
+        pub name: String,
+        pub mem_size: usize,
+        pub user_ticks: usize,
+        pub kernel_ticks: usize,
+        pub context_switches: usize,
+        pub start_tick: usize,
+    }
+
+    /// Region of the user memory created by the MMAP system call, from start to end
+    #[derive(Debug, Clone)]
+    pub struct MemoryMapping {
+        pub start: usize,
+        pub end: usize,
+        pub writable: bool,
+        pub inode: Option<Arc<INodeReference>>, // Only used by file mappings
+        pub offset: u32,                        // Offset into the file of the start of the mapping
+        pub segment: Option<usize>,             // Key of the shared memory segment, if any
     }
 
     pub struct ProcessList {
@@ -84,6 +131,21 @@ pub mod process {
         pub next_pid: usize,
     }
 
+    // The first process, which adopts every orphan
+    pub const INIT_PROCESS_ID: usize = 0;
+
+    // Wait Flags (arg2 of the WAIT system call)
+    pub const WAIT_ANY_CHILD: usize = usize::MAX; // Wait for any child instead of a given pid (-1)
+    pub const WNOHANG: usize = 0x1; // Return immediately if no child has exited
+
+    // Mmap Flags (arg1 of the MMAP system call)
+    pub const PROT_WRITE: usize = 0x2; // Mapping can be written (anonymous mappings only)
+    pub const MAP_ANONYMOUS: usize = 0x20; // Mapping is zeroed instead of backed by a file
+
+    // Process Stats (PROCESS_STATS system call)
+    pub const PROCESS_NAME_LENGTH: usize = 16; // Longer names are truncated, shorter are NUL padded
+    pub const NO_PARENT: usize = usize::MAX; // Parent pid of a process without parent
+
     pub const TRAPFRAME_SIZE: usize = core::mem::size_of::<TrapFrame>() as usize;
     pub const CONTEXT_SIZE: usize = core::mem::size_of::<Context>() as usize;
 }
@@ -98,14 +160,44 @@ pub mod scheduler {
     // Number of processes that can run at the same time.
     pub const NUM_PROCESS: usize = 1000;
 
+    // Multilevel Feedback Queue (see mlfq.rs). Processes of level 0 run first.
+    pub const NUMBER_PRIORITY_LEVELS: usize = 4;
+    pub const TIME_SLICES: [usize; NUMBER_PRIORITY_LEVELS] = [1, 2, 4, 8]; // Timer ticks per level
+    pub const BOOST_INTERVAL: usize = 50; // Timer ticks between boosts of every process
+
+    #[derive(Debug)]
     pub enum SchedulerState {
         READY,
         BUSY,
     }
 
+    /// Each CPU has its own scheduler (see CPU::scheduler), with the process it is running and the
+    /// context to return to once that process gives the CPU back.
+    #[derive(Debug)]
     pub struct Scheduler {
         pub current_process: Option<Arc<SpinMutex<Process>>>,
         pub context: *mut Context,
         pub status: SchedulerState,
     }
+
+    /// Handle to the scheduler of the CPU that uses it (see SCHEDULER)
+    pub struct CpuScheduler;
+}
+
+pub mod shm {
+    use alloc::vec::Vec;
+
+    /// Set of frames mapped into the memory of every process that attached it
+    pub struct SharedSegment {
+        pub key: usize,
+        pub frames: Vec<usize>, // Kernel addresses of the frames, in order
+        pub attachments: usize, // Number of mappings of the segment, across all processes
+    }
+
+    pub struct SharedMemory {
+        pub segments: Vec<SharedSegment>,
+    }
+
+    // Largest segment that can be created (in pages)
+    pub const MAX_SEGMENT_PAGES: usize = 1024;
 }
@@ -3,12 +3,26 @@ pub enum ELFError {
     ELFOverflow(u32, u32),
     InvalidMemorySize(u32, u32),
     InvalidELFMagic(u32),
+    ReadFailure, // The file is shorter than its headers claim, or could not be read
     KernelMappingFailure,
     MemoryAllocationFailure,
+    ArgumentsOverflow,
+    Killed, // Another thread of the process called exec first
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum ProcessError {
     SlotAllocationFailure,
     MemoryAllocationFailure,
+    NotFound,
+    NotPermitted,
+    InvalidPriority,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum SharedMemoryError {
+    AlreadyExists,
+    NotFound,
+    InvalidSize,
+    MemoryAllocationFailure,
 }
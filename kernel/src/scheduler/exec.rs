@@ -1,17 +1,22 @@
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 
 use crate::{
     debug::process::debug_elf,
-    filesystem::fs::{get_path_filename, read_inode_data, INode},
+    filesystem::fs::{get_inode, get_path_filename, lock_inode, read_inode_data, INode},
     memory::{
-        defs::{Page, KERNEL_BASE, PAGE_SIZE, PTE_U, PTE_W},
-        vm::{setup_kernel_page_tables, walk_page_dir},
+        defs::{Page, PageTableEntry, KERNEL_BASE, PAGE_SIZE, PTE_NX, PTE_U, PTE_W},
+        vm::{deallocate_page_dir, setup_kernel_page_tables, walk_page_dir},
     },
     scheduler::process::allocate_range,
-    P2V, PTE_ADDRESS, ROUND_UP,
+    P2V, PTE_ADDRESS, ROUND_DOWN, ROUND_UP,
 };
 
-use super::{error::ELFError, process::load_process_memory, scheduler::SCHEDULER};
+use super::{
+    error::ELFError,
+    process::{end_other_threads, load_process_memory},
+    scheduler::{switch_kernel_virtual_memory, SCHEDULER},
+    shm::release_segment,
+};
 
 pub const ELF_MAGIC: u32 = 0x464C457F;
 pub const ELF_HEADER_SIZE: usize = core::mem::size_of::<ELFHeader>();
@@ -20,6 +25,14 @@ pub const ELF_PROG_HEADER_SIZE: usize = core::mem::size_of::<ProgramHeader>();
 const DEFAULT_PROGRAM_STACK_SIZE: usize = 8192; // Stack size in bytes
 const DEFAULT_PROGRAM_HEAP_SIZE: usize = 4096; // Heap size in bytes
 
+// Permissions of a segment, as found in ProgramHeader::flags
+pub const PF_EXECUTE: u32 = 0x1;
+pub const PF_WRITE: u32 = 0x2;
+pub const PF_READ: u32 = 0x4;
+
+pub const MAX_ARGUMENTS: usize = 32; // Number of arguments a program can receive
+pub const MAX_ARGUMENTS_SIZE: usize = 2048; // Size of all arguments in bytes (must fit in a page)
+
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ProgramHeaderType {
@@ -77,28 +90,61 @@ pub struct ProgramHeader {
     pub align: u32,
 }
 
-fn get_elf_header(inode: &INode) -> ELFHeader {
+/// Read size bytes of the inode at offset. Fails if the file ends before them.
+fn read_elf_data(inode: &INode, offset: u32, size: usize) -> Result<Vec<u8>, ELFError> {
+    match read_inode_data(inode, offset, size as u32) {
+        Ok(data) if data.len() == size => Ok(data),
+        _ => Err(ELFError::ReadFailure),
+    }
+}
+
+fn get_elf_header(inode: &INode) -> Result<ELFHeader, ELFError> {
     // Read ELF Header of the inode
-    let mut data = read_inode_data(&inode, 0, ELF_HEADER_SIZE as u32);
-    unsafe { core::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut ELFHeader, 1)[0] }
+    let mut data = read_elf_data(inode, 0, ELF_HEADER_SIZE)?;
+    Ok(unsafe { core::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut ELFHeader, 1)[0] })
 }
 
-fn read_program_header(inode: &INode, offset: u32) -> ProgramHeader {
+fn read_program_header(inode: &INode, offset: u32) -> Result<ProgramHeader, ELFError> {
     // Read program header block of the inode at offset
-    let data = read_inode_data(&inode, offset, ELF_PROG_HEADER_SIZE as u32);
+    let data = read_elf_data(inode, offset, ELF_PROG_HEADER_SIZE)?;
 
     // Convert data into a Program Header
-    unsafe { *(data.as_slice().as_ptr() as *const ProgramHeader) }
+    Ok(unsafe { *(data.as_slice().as_ptr() as *const ProgramHeader) })
+}
+
+/// Page permissions of a segment. Segments that cannot be written, such as the program code, are
+/// mapped read-only, and segments that cannot be executed are mapped non-executable (only honoured
+/// with PAE, see PTE_NX).
+fn get_segment_permissions(prog_header: &ProgramHeader) -> PageTableEntry {
+    let mut perm = PTE_U;
+
+    if prog_header.flags & PF_WRITE > 0 {
+        perm |= PTE_W;
+    }
+
+    if prog_header.flags & PF_EXECUTE == 0 {
+        perm |= PTE_NX;
+    }
+
+    perm
 }
 
-/// Allocate the user stack alongside one guard-page to detect stack overflow
-pub fn prepare_stack(page_dir: &mut Page, address: usize) -> Result<(usize, usize), ELFError> {
+/// Allocate the user stack alongside one guard-page to detect stack overflow. The arguments are
+/// copied to the top of the stack, following the i386 System V layout: the strings, the argv
+/// array (terminated by a null pointer), and finally argv and argc. Since _start is entered
+/// like any other cdecl function, a fake return address sits on top of the stack.
+pub fn prepare_stack(
+    page_dir: &mut Page,
+    address: usize,
+    arguments: &[String],
+) -> Result<(usize, usize), ELFError> {
     let address = ROUND_UP!(address, PAGE_SIZE);
     let stack_size = ROUND_UP!(DEFAULT_PROGRAM_STACK_SIZE, PAGE_SIZE) / PAGE_SIZE + 1;
 
-    let Ok(end_address) = allocate_range(page_dir, address, address + stack_size * PAGE_SIZE) else {
+    let end_address = address + stack_size * PAGE_SIZE;
+    if allocate_range(page_dir, address, end_address, PTE_W | PTE_U | PTE_NX).is_err() {
         return Err(ELFError::MemoryAllocationFailure);
-    };
+    }
 
     // This is a guard page, used to detect stack overflows, since it cannot be writen by the user
     let page_table_entry = walk_page_dir(page_dir, address, false).unwrap();
@@ -106,23 +152,53 @@ pub fn prepare_stack(page_dir: &mut Page, address: usize) -> Result<(usize, usiz
 
     let last_page_index = address + (stack_size - 1) * PAGE_SIZE;
     let page_table_entry = walk_page_dir(page_dir, last_page_index, false).unwrap();
-    let page_table_address = unsafe { PTE_ADDRESS!(P2V!(*page_table_entry)) };
-    let mut page = Page::new(page_table_address as *mut u8);
+    let page_table_address = unsafe { P2V!(PTE_ADDRESS!(*page_table_entry)) };
+    let page_data =
+        unsafe { core::slice::from_raw_parts_mut(page_table_address as *mut u8, PAGE_SIZE) };
 
-    let page_data = page.cast_to::<usize>();
     let mut esp = address + stack_size * PAGE_SIZE; // Move ESP to top of the stack
 
-    page_data[PAGE_SIZE / 4 - 1] = 0xAAAAAAAA;
-    page_data[PAGE_SIZE / 4 - 2] = 2;
-    page_data[PAGE_SIZE / 4 - 3] = 0xFFFFFFFF; // Return trap
+    // Copy the strings, null-terminated, just like C programs expect them
+    let mut argv = Vec::with_capacity(arguments.len() + 1);
+    for argument in arguments {
+        esp = esp
+            .checked_sub(argument.len() + 1)
+            .filter(|&esp| esp >= last_page_index)
+            .ok_or(ELFError::ArgumentsOverflow)?;
+
+        let offset = esp - last_page_index;
+        page_data[offset..(offset + argument.len())].copy_from_slice(argument.as_bytes());
+        page_data[offset + argument.len()] = 0;
+        argv.push(esp);
+    }
+
+    argv.push(0);
+
+    // Address of argc must be aligned to 16 bytes, as if _start had been called
+    let word_size = core::mem::size_of::<usize>();
+    let argc_address = ROUND_DOWN!(esp - (argv.len() + 2) * word_size, 16);
+    esp = argc_address - word_size;
+    if esp < last_page_index {
+        return Err(ELFError::ArgumentsOverflow);
+    }
+
+    let mut words = Vec::with_capacity(argv.len() + 3);
+    words.push(0xFFFFFFFF); // Return trap
+    words.push(arguments.len()); // argc
+    words.push(argc_address + 2 * word_size); // argv
+    words.extend(argv);
+
+    for (index, word) in words.iter().enumerate() {
+        let offset = esp - last_page_index + index * word_size;
+        page_data[offset..(offset + word_size)].copy_from_slice(&word.to_le_bytes());
+    }
 
-    esp -= 3 * core::mem::size_of::<usize>();
     Ok((esp, end_address))
 }
 
 pub fn decode_elf(inode: &INode) -> Result<(Page, ELFHeader, usize), ELFError> {
     // Check if this is an ELF Executable. No support to other formats yet
-    let header = get_elf_header(inode);
+    let header = get_elf_header(inode)?;
 
     if header.magic != ELF_MAGIC {
         return Err(ELFError::InvalidELFMagic(header.magic));
@@ -132,11 +208,23 @@ pub fn decode_elf(inode: &INode) -> Result<(Page, ELFHeader, usize), ELFError> {
         return Err(ELFError::KernelMappingFailure);
     };
 
+    // A program that fails to load must not leak the memory allocated so far
+    match load_program(&mut page_dir, inode, &header) {
+        Ok(highest_page_address) => Ok((page_dir, header, highest_page_address)),
+        Err(error) => {
+            deallocate_page_dir(&mut page_dir);
+            Err(error)
+        }
+    }
+}
+
+/// Load the segments of the program into page_dir. Returns the highest address used by them.
+fn load_program(page_dir: &mut Page, inode: &INode, header: &ELFHeader) -> Result<usize, ELFError> {
     // Load program headers into memory
     let mut highest_page_address = 0;
     let mut offset = header.program_header_offset as usize;
     for _ in 0..header.number_entries {
-        let prog_header = read_program_header(inode, offset as u32);
+        let prog_header = read_program_header(inode, offset as u32)?;
         offset += ELF_PROG_HEADER_SIZE;
 
         // Skip if this segment is not loadable
@@ -162,7 +250,8 @@ pub fn decode_elf(inode: &INode) -> Result<(Page, ELFHeader, usize), ELFError> {
         }
 
         // Allocate all required pages for this section to be loaded into memory
-        let Ok(_) = allocate_range(&mut page_dir, start_address, end_address) else {
+        let perm = get_segment_permissions(&prog_header);
+        let Ok(_) = allocate_range(page_dir, start_address, end_address, perm) else {
             return Err(ELFError::MemoryAllocationFailure);
         };
 
@@ -172,39 +261,73 @@ pub fn decode_elf(inode: &INode) -> Result<(Page, ELFHeader, usize), ELFError> {
 
         // Finally, load the program code into memory
         load_process_memory(
-            &mut page_dir,
+            page_dir,
             start_address as *const u8,
             inode,
             prog_header.offset as usize,
             prog_header.file_size as usize,
-        )
-        .unwrap();
+        )?;
     }
 
-    Ok((page_dir, header, highest_page_address))
+    Ok(highest_page_address)
 }
 
-pub fn exec(inode: &INode, path: &str) {
-    let mut scheduler = unsafe { SCHEDULER.lock() };
-    let process = scheduler.current_process.as_ref().unwrap();
+/// Replaces the memory of the current process with the program in inode. The current memory is
+/// only replaced once the new one is fully set up, so that a failed exec returns to the process.
+/// The other threads of the process are ended first, and the new program runs without threads.
+pub fn exec(inode_number: u32, path: &str, arguments: &[String]) -> Result<(), ELFError> {
+    // The program must not be written while it is being loaded
+    let inode_lock = lock_inode(inode_number);
+    let inode = get_inode(inode_number);
+    let program = decode_elf(&inode);
+    drop(inode_lock);
+
+    let (mut new_page_dir, header, highest_page_address) = program?;
 
-    let (mut new_page_dir, header, highest_page_address) =
-        decode_elf(inode).expect("[ERROR] Failed to decode ELF");
+    // Prepare process stack page
+    let (esp, highest_page_address) =
+        match prepare_stack(&mut new_page_dir, highest_page_address, arguments) {
+            Ok(stack) => stack,
+            Err(error) => {
+                deallocate_page_dir(&mut new_page_dir);
+                return Err(error);
+            }
+        };
+
+    // Threads would keep running on the memory that is replaced
+    if !end_other_threads() {
+        deallocate_page_dir(&mut new_page_dir);
+        return Err(ELFError::Killed);
+    }
+
+    let scheduler = unsafe { SCHEDULER.lock() };
+    let process = scheduler.current_process.as_ref().unwrap();
 
     // Update process's page directory
-    process.lock().pgdir = Some(new_page_dir.as_mut_ptr() as *mut usize);
+    let old_page_dir = process
+        .lock()
+        .pgdir
+        .replace(new_page_dir.as_mut_ptr() as *mut usize);
     process.lock().name = get_path_filename(path);
+    process.lock().mem_size = highest_page_address;
+    process.lock().heap_start = highest_page_address;
+    let old_mappings = core::mem::take(&mut process.lock().mappings);
 
-    // Prepare process stack page
-    let Ok((esp, highest_page_address)) = prepare_stack(&mut new_page_dir, highest_page_address) else {
-        panic!("[FATAL] Execution Failed");
-    };
+    // The old page directory is loaded, so the one of the Kernel replaces it before it is freed
+    if let Some(old_page_dir) = old_page_dir {
+        unsafe { switch_kernel_virtual_memory() };
+        deallocate_page_dir(&mut Page::new(old_page_dir as *mut u8));
+    }
 
-    process.lock().mem_size = highest_page_address;
+    old_mappings
+        .iter()
+        .filter_map(|mapping| mapping.segment)
+        .for_each(release_segment);
 
     unsafe { (*process.lock().trapframe.unwrap()).esp = esp };
     unsafe { (*process.lock().trapframe.unwrap()).eip = header.entry as usize };
 
     // Return to scheduler
     unsafe { scheduler.resume() };
+    Ok(())
 }
@@ -0,0 +1,83 @@
+use alloc::{boxed::Box, string::String};
+use core::mem::size_of;
+
+use crate::{apic::mp::get_my_cpu, interrupts::system_calls::exit};
+
+use super::{
+    defs::process::{ProcessState, CONTEXT_SIZE},
+    error::ProcessError,
+    process::{reap_orphans, spawn_process},
+    scheduler::PROCESS_LIST,
+    sleep::sleep_ticks,
+};
+
+type KernelFunction = Box<dyn FnOnce() + Send>;
+
+const ORPHAN_REAPER_INTERVAL: usize = 10; // Timer ticks between two passes of the orphan reaper
+
+/// Spawns a kernel thread that runs function on its own kernel stack, under the scheduler like any
+/// other process. Kernel threads have no user memory, parent nor trapframe, so the scheduler reaps
+/// them once function returns. As the Kernel runs with interrupts disabled, they are never
+/// preempted, so they should sleep (see sleep_ticks) or yield once in a while.
+/// Returns the pid of the thread.
+pub fn spawn_kernel_thread<F>(name: &str, function: F) -> Result<usize, ProcessError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let pid = unsafe { spawn_process()? };
+    let process = unsafe { PROCESS_LIST.lock().get_pid(pid).unwrap() };
+    let mut process_lock = process.lock();
+
+    let function = Box::new(Box::new(function) as KernelFunction);
+    let context = process_lock.context.unwrap();
+
+    unsafe {
+        // Above the context sits the return address of the entry, and above it, its argument
+        let stack = context as *mut usize;
+        *stack.add(CONTEXT_SIZE / size_of::<usize>() + 1) = Box::into_raw(function) as usize;
+        (*context).eip = kernel_thread_start as *const () as usize;
+    }
+
+    process_lock.name = String::from(name);
+    process_lock.trapframe = None;
+    process_lock.mem_size = 0;
+    process_lock.heap_start = 0;
+    process_lock.state = ProcessState::READY;
+    Ok(pid)
+}
+
+/// Spawns a kernel thread that runs function every interval timer ticks, forever. This suits
+/// background work of the Kernel, such as writing dirty blocks back to the disk.
+pub fn spawn_kernel_worker<F>(
+    name: &str,
+    interval: usize,
+    mut function: F,
+) -> Result<usize, ProcessError>
+where
+    F: FnMut() + Send + 'static,
+{
+    spawn_kernel_thread(name, move || loop {
+        function();
+        sleep_ticks(interval);
+    })
+}
+
+/// Starts the workers that run in the background for as long as the Kernel runs. Must be called
+/// once the init process exists.
+pub fn spawn_kernel_workers() {
+    spawn_kernel_worker("orphan_reaper", ORPHAN_REAPER_INTERVAL, reap_orphans)
+        .expect("[FATAL] Failed to start orphan reaper");
+}
+
+/// Start of every kernel thread, which the scheduler switches to while holding PROCESS_LIST
+unsafe extern "C" fn kernel_thread_start(function: *mut KernelFunction) -> ! {
+    // Interrupts stay disabled, as in the rest of the Kernel
+    get_my_cpu().set_interrupt_state(false);
+    PROCESS_LIST.force_unlock();
+
+    let function = *Box::from_raw(function);
+    function();
+
+    exit(0);
+    unreachable!("[FATAL] Kernel thread resumed after exit");
+}
@@ -0,0 +1,84 @@
+/// Multilevel feedback queue scheduling, enabled by the "mlfq" feature. Each process sits on one
+/// of NUMBER_PRIORITY_LEVELS levels, and ready processes of lower levels run first. A process that
+/// runs for the whole time slice of its level is demoted to the next one, so CPU bound processes
+/// sink while interactive ones, which sleep before their slice is over, stay on top. Every
+/// BOOST_INTERVAL ticks, each process goes back to the level of its priority, so that none starves.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::sync::Arc;
+
+use crate::sync::spin_mutex::SpinMutex;
+
+use super::{
+    defs::{
+        process::{Process, ProcessList, ProcessState},
+        scheduler::{BOOST_INTERVAL, NUMBER_PRIORITY_LEVELS, TIME_SLICES},
+    },
+    scheduler::PROCESS_LIST,
+};
+
+// Timer ticks run by processes, across every CPU
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+impl ProcessList {
+    /// Picks the ready process of the lowest level. Processes of the same level take turns,
+    /// starting after the last process picked.
+    pub fn get_next_ready(&mut self) -> Option<&Arc<SpinMutex<Process>>> {
+        let length = self.list.len();
+        let mut next: Option<(usize, usize)> = None; // Index and level of the process
+
+        for offset in 0..length {
+            let index = (self.next_to_visit + offset) % length;
+            let process = self.list[index].lock();
+
+            let is_better = next.map_or(true, |(_, level)| process.level < level);
+            if process.state == ProcessState::READY && is_better {
+                next = Some((index, process.level));
+            }
+        }
+
+        let (index, _) = next?;
+        self.next_to_visit = index + 1;
+        Some(&self.list[index])
+    }
+
+    /// Moves every process back to the level of its priority
+    fn boost(&mut self) {
+        for process in self.list.iter() {
+            let mut process = process.lock();
+            process.level = process.priority;
+            process.slice_ticks = 0;
+        }
+    }
+
+    fn has_ready_below(&self, level: usize) -> bool {
+        self.list.iter().any(|process| {
+            let process = process.lock();
+            process.state == ProcessState::READY && process.level < level
+        })
+    }
+}
+
+/// Accounts a timer tick to the running process. Returns whether it must give the CPU up, either
+/// because it has used up its time slice, or because a process of a lower level is ready.
+pub fn tick(process: &Arc<SpinMutex<Process>>) -> bool {
+    let mut process_list = unsafe { PROCESS_LIST.lock() };
+
+    if TICKS.fetch_add(1, Ordering::Relaxed) % BOOST_INTERVAL == BOOST_INTERVAL - 1 {
+        process_list.boost();
+    }
+
+    let mut process_lock = process.lock();
+    process_lock.slice_ticks += 1;
+
+    if process_lock.slice_ticks >= TIME_SLICES[process_lock.level] {
+        process_lock.level = core::cmp::min(process_lock.level + 1, NUMBER_PRIORITY_LEVELS - 1);
+        process_lock.slice_ticks = 0;
+        return true;
+    }
+
+    let level = process_lock.level;
+    drop(process_lock);
+
+    process_list.has_ready_below(level)
+}
@@ -0,0 +1,185 @@
+/// Memory mappings are regions of the user memory created by the MMAP system call. They are placed
+/// right below KERNEL_BASE, and each new mapping takes the highest range that is still free, so the
+/// mappings grow down towards the heap. Anonymous mappings start zeroed, while file mappings hold
+/// the content of a file and cannot be written. Either way, pages are only backed once they are
+/// first accessed (see fault_in_page), so mapping a big file costs nothing until it is read.
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    filesystem::fs::{get_inode, lock_inode, read_inode_data, INodeReference},
+    memory::{
+        defs::{Page, KERNEL_BASE, PAGE_SIZE, PTE_NX, PTE_U, PTE_W},
+        error::MemoryError,
+        vm::{back_lazy_page, reserve_lazy_pages, unmap_user_pages},
+    },
+    ROUND_DOWN, ROUND_UP,
+};
+
+use super::{
+    defs::process::{MemoryMapping, Process},
+    process::lock_process_memory,
+    scheduler::SCHEDULER,
+};
+
+impl Process {
+    /// Start of the highest free range between the break and KERNEL_BASE that fits size bytes
+    pub fn find_mapping_address(&self, size: usize) -> Option<usize> {
+        let mut end = KERNEL_BASE;
+
+        for mapping in self.mappings.iter().rev() {
+            if end - mapping.end >= size {
+                return Some(end - size);
+            }
+
+            end = mapping.start;
+        }
+
+        end.checked_sub(size)
+            .filter(|&start| start >= ROUND_UP!(self.mem_size, PAGE_SIZE))
+    }
+
+    /// Lowest address of the mappings, which the heap cannot grow past
+    pub fn get_mappings_start(&self) -> usize {
+        self.mappings
+            .first()
+            .map_or(KERNEL_BASE, |mapping| mapping.start)
+    }
+
+    /// Record a new mapping, keeping the mappings sorted by address
+    pub fn add_mapping(&mut self, mapping: MemoryMapping) {
+        let index = self
+            .mappings
+            .partition_point(|other| other.start < mapping.start);
+        self.mappings.insert(index, mapping);
+    }
+
+    fn get_mapping(&self, address: usize) -> Option<&MemoryMapping> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.start <= address && address < mapping.end)
+    }
+}
+
+/// Maps size bytes into the memory of the current process. The mapping is zeroed if inode is None,
+/// otherwise it holds the content of the file from offset, and keeps the inode allocated for as
+/// long as it exists. Returns the start of the mapping.
+pub fn map_memory(
+    size: usize,
+    writable: bool,
+    inode: Option<Arc<INodeReference>>,
+    offset: u32,
+) -> Result<usize, MemoryError> {
+    let _memory_lock = lock_process_memory();
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let mut process = process.lock();
+    let mut page_dir = Page::new(process.pgdir.unwrap() as *mut u8);
+
+    let size = ROUND_UP!(size, PAGE_SIZE);
+    let start = process
+        .find_mapping_address(size)
+        .ok_or(MemoryError::MemorySpaceViolation)?;
+    let perm = if writable { PTE_W | PTE_U } else { PTE_U } | PTE_NX;
+
+    if let Err(error) = reserve_lazy_pages(&mut page_dir, start, start + size, perm) {
+        unmap_user_pages(&mut page_dir, start, start + size);
+        return Err(error);
+    }
+
+    let mapping = MemoryMapping {
+        start,
+        end: start + size,
+        writable,
+        inode,
+        offset,
+        segment: None,
+    };
+
+    process.add_mapping(mapping);
+    Ok(start)
+}
+
+/// Unmaps the pages of the mappings of the current process from address to address + size.
+/// Mappings that are only partly unmapped are split, and memory that does not belong to any
+/// mapping (such as the heap) is left untouched. Shared memory segments can only be detached as a
+/// whole (see detach_segment), so they are left untouched as well.
+pub fn unmap_memory(address: usize, size: usize) {
+    let _memory_lock = lock_process_memory();
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let mut process = process.lock();
+    let mut page_dir = Page::new(process.pgdir.unwrap() as *mut u8);
+
+    let end = address + ROUND_UP!(size, PAGE_SIZE);
+    let mut mappings = Vec::new();
+    let mut removed_mappings = Vec::new();
+
+    for mapping in core::mem::take(&mut process.mappings) {
+        if mapping.end <= address || mapping.start >= end || mapping.segment.is_some() {
+            mappings.push(mapping);
+            continue;
+        }
+
+        let unmap_start = core::cmp::max(mapping.start, address);
+        let unmap_end = core::cmp::min(mapping.end, end);
+        unmap_user_pages(&mut page_dir, unmap_start, unmap_end);
+
+        if mapping.start < unmap_start {
+            mappings.push(MemoryMapping {
+                end: unmap_start,
+                ..mapping.clone()
+            });
+        }
+
+        if unmap_end < mapping.end {
+            mappings.push(MemoryMapping {
+                start: unmap_end,
+                offset: mapping.offset + (unmap_end - mapping.start) as u32,
+                ..mapping.clone()
+            });
+        }
+
+        removed_mappings.push(mapping);
+    }
+
+    process.mappings = mappings;
+    drop(process);
+
+    // Dropping the last reference to an unlinked inode frees it, which writes to the disk, so the
+    // removed mappings are only dropped without holding the process lock
+    drop(removed_mappings);
+}
+
+/// Backs a reserved page of the current process, on its first access. Pages of file mappings are
+/// filled with the content of the file, which is read without holding any spin lock, as reading
+/// the disk may sleep. The caller holds the memory lock of the process (see lock_process_memory).
+pub fn fault_in_page(page_dir: &mut Page, virtual_address: usize) -> Result<(), MemoryError> {
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let mapping = process.lock().get_mapping(virtual_address).cloned();
+
+    let Some(MemoryMapping {
+        start,
+        inode: Some(inode),
+        offset,
+        ..
+    }) = mapping
+    else {
+        return back_lazy_page(page_dir, virtual_address, &[]);
+    };
+
+    // Pages past the end of the file stay zeroed. The mapping keeps the inode allocated, even if it
+    // was unlinked, so it still holds the content of the mapped file.
+    let inode_lock = lock_inode(inode.inode_number);
+    let inode = get_inode(inode.inode_number);
+    let file_offset = offset + (ROUND_DOWN!(virtual_address, PAGE_SIZE) - start) as u32;
+    let data = if file_offset < inode.size {
+        read_inode_data(&inode, file_offset, PAGE_SIZE as u32).ok()
+    } else {
+        Some(Vec::new())
+    };
+    drop(inode_lock);
+
+    let Some(data) = data else {
+        return Err(MemoryError::ReadFailure);
+    };
+
+    back_lazy_page(page_dir, virtual_address, &data)
+}
@@ -1,6 +1,20 @@
 pub mod defs;
 pub mod error;
 pub mod exec;
+pub mod kthread;
+pub mod mmap;
 pub mod process;
 pub mod scheduler;
+pub mod shm;
 pub mod sleep;
+
+// Scheduling policy, picked by the "mlfq" feature
+#[cfg(feature = "mlfq")]
+pub mod mlfq;
+#[cfg(not(feature = "mlfq"))]
+pub mod round_robin;
+
+#[cfg(feature = "mlfq")]
+pub use mlfq as policy;
+#[cfg(not(feature = "mlfq"))]
+pub use round_robin as policy;
@@ -1,43 +1,55 @@
-use core::{panic, slice::from_raw_parts_mut};
+use core::{mem::size_of, panic, slice::from_raw_parts_mut, sync::atomic::Ordering};
 
 use alloc::{string::String, sync::Arc, vec::Vec};
 
 use super::{
     defs::{
         process::{
-            Context, Process, ProcessList, ProcessState, TrapFrame, CONTEXT_SIZE, TRAPFRAME_SIZE,
+            Context, Process, ProcessList, ProcessState, ProcessStats, TrapFrame, CONTEXT_SIZE,
+            INIT_PROCESS_ID, TRAPFRAME_SIZE, WAIT_ANY_CHILD, WNOHANG,
         },
-        scheduler,
+        scheduler::NUMBER_PRIORITY_LEVELS,
     },
-    error::ProcessError,
-    scheduler::{PROCESS_LIST, SCHEDULER},
-    sleep::sleep,
+    error::{ELFError, ProcessError},
+    scheduler::{fork_return, switch_kernel_virtual_memory, PROCESS_LIST, SCHEDULER},
+    shm::{release_segment, share_segment},
+    sleep::{sleep, sleep_killable},
 };
 
 use crate::{
     apic::mp::get_my_cpu,
-    filesystem::fs::{read_inode_data, INode},
+    filesystem::{
+        file::{open_console, FileDescriptor},
+        fs::{normalize_path, read_inode_data, INode},
+    },
+    interrupts::irqs::TICKS,
     memory::{
         defs::{
-            Page, KERNEL_BASE, KERNEL_DATA_SEGMENT, PAGE_SIZE, PTE_P, PTE_U, PTE_W,
+            Page, PageTableEntry, KERNEL_BASE, KERNEL_DATA_SEGMENT, NUMBER_PAGE_ENTRIES,
+            PAGE_DIR_SHIFT, PAGE_SIZE, PTE_COW, PTE_NX, PTE_P, PTE_SHARED, PTE_U, PTE_W,
             TASK_STATE_SEGMENT, USER_CODE_SEGMENT, USER_DATA_SEGMENT,
         },
         error::MemoryError,
         mem::mem_move,
-        vm::{allocate_page, map_pages, setup_kernel_page_tables, walk_page_dir},
+        vm::{
+            allocate_page, allocate_user_page, deallocate_page, deallocate_page_dir,
+            flush_page_dir, get_page_dir_entries, load_page_dir, map_pages, reserve_lazy_pages,
+            setup_kernel_page_tables, share_page, unmap_user_pages, walk_page_dir,
+        },
     },
     println,
     sync::{
         cpu_cli::{pop_cli, push_cli},
+        sleep_lock::{SleepLock, SleepLockGuard},
         spin_mutex::{SpinMutex, SpinMutexGuard},
     },
-    x86::{
-        defs::PrivilegeLevel,
-        helpers::{load_cr3, ltr},
-    },
-    P2V, PTE_ADDRESS, PTE_FLAGS, ROUND_DOWN, ROUND_UP, V2P,
+    x86::{defs::PrivilegeLevel, helpers::ltr},
+    P2V, PAGE_DIR_INDEX, PTE_ADDRESS, PTE_FLAGS, ROUND_DOWN, ROUND_UP, V2P,
 };
 
+// Locks of the memory of processes, by address of their page directory (see lock_process_memory)
+static MEMORY_LOCKS: SleepLock<usize> = SleepLock::new();
+
 impl ProcessList {
     pub const fn new() -> Self {
         ProcessList {
@@ -78,28 +90,6 @@ impl ProcessList {
             }
         }
     }
-
-    pub fn get_next_ready(&mut self) -> Option<&Arc<SpinMutex<Process>>> {
-        if self.next_to_visit >= self.list.len() {
-            self.next_to_visit = 0;
-        }
-
-        let mut index = self.next_to_visit;
-        for process in self.list.iter().skip(self.next_to_visit) {
-            if process.lock().state == ProcessState::READY {
-                break;
-            }
-
-            index += 1;
-        }
-
-        self.next_to_visit = index + 1;
-        if index >= self.list.len() {
-            return None;
-        }
-
-        Some(&self.list[index])
-    }
 }
 
 impl Process {
@@ -107,6 +97,7 @@ impl Process {
         Process {
             state: ProcessState::EMPTY,
             mem_size: Default::default(),
+            heap_start: Default::default(),
             current_working_directory: String::from("/"),
             name: String::from(""),
             context: None,
@@ -114,11 +105,44 @@ impl Process {
             kernel_stack: None,
             pgdir: None,
             sleep_object: 0,
+            exit_code: 0,
             parent: None,
+            open_files: Default::default(),
+            mappings: Vec::new(),
+            priority: 0,
+            level: 0,
+            slice_ticks: 0,
+            user_ticks: 0,
+            kernel_ticks: 0,
+            context_switches: 0,
+            start_tick: 0,
+            leader: None,
+            thread_count: 1,
+            killed: false,
+            adopted: false,
             pid,
         }
     }
 
+    /// Stores the open file in the lowest free descriptor slot and returns the descriptor.
+    pub fn allocate_file_descriptor(&mut self, file: FileDescriptor) -> Option<usize> {
+        let descriptor = self.open_files.iter().position(|file| file.is_none())?;
+        self.open_files[descriptor] = Some(file);
+        Some(descriptor)
+    }
+
+    pub fn get_file_descriptor(&self, descriptor: usize) -> Option<FileDescriptor> {
+        match self.open_files.get(descriptor) {
+            Some(Some(file)) => Some(Arc::clone(file)),
+            _ => None,
+        }
+    }
+
+    /// Paths given by the process may be relative to its current working directory
+    pub fn resolve_path(&self, path: &str) -> String {
+        normalize_path(&self.current_working_directory, path)
+    }
+
     pub fn set_trapframe(&mut self, trapframe: TrapFrame) {
         unsafe { *self.trapframe.unwrap() = trapframe };
     }
@@ -156,18 +180,22 @@ pub unsafe fn spawn_process() -> Result<usize, ProcessError> {
 
     process.kernel_stack = Some(kernel_page_pointer as *mut usize);
     process.mem_size = PAGE_SIZE;
+    process.heap_start = PAGE_SIZE;
     process.state = ProcessState::EMBRYO;
+    process.start_tick = TICKS.load(Ordering::Relaxed);
 
     // Setup Trapframe Layout
     esp = esp.sub(TRAPFRAME_SIZE);
     process.trapframe = Some(esp as *mut TrapFrame);
 
+    // Fork return returns into trap return
+    esp = esp.sub(size_of::<usize>());
+    *(esp as *mut usize) = trap_return as *const () as usize;
+
     // Setup Context Layout
     esp = esp.sub(CONTEXT_SIZE);
     process.context = Some(esp as *mut Context);
-
-    // Create Trap Return
-    (*process.context.unwrap()).eip = trap_return as *const () as usize;
+    (*process.context.unwrap()).eip = fork_return as *const () as usize;
 
     let Some(pid) = process_list.insert_process(process) else {
         return Err(ProcessError::SlotAllocationFailure);
@@ -199,11 +227,15 @@ unsafe fn set_user_tss(process: &SpinMutexGuard<Process>) {
 /// switched while in DPL 0. Kernel becomes unavailable only when in DPL 3.
 pub unsafe fn switch_user_virtual_memory(process: &Arc<SpinMutex<Process>>) {
     let process_lock = process.lock();
-    let page_dir = process_lock.pgdir.unwrap() as usize;
+
+    // Kernel threads have no user memory, and keep running on the Kernel page directory
+    let Some(page_dir) = process_lock.pgdir else {
+        return;
+    };
 
     push_cli();
     set_user_tss(&process_lock);
-    load_cr3(V2P!(page_dir));
+    load_page_dir(V2P!(page_dir as usize));
     pop_cli();
 }
 
@@ -213,7 +245,7 @@ pub unsafe fn setup_user_virtual_memory(page_dir: &mut Page, address: *const usi
         panic!("[FATAL] User Virtual Memory is bigger than one page");
     }
 
-    let mut memory_page = allocate_page().expect("[ERROR] Failed to allocate page");
+    let mut memory_page = allocate_user_page().expect("[ERROR] Failed to allocate page");
     let virtual_address = 0;
     let page_size = PAGE_SIZE;
     let phys_address = V2P!(memory_page.as_ptr() as usize);
@@ -256,32 +288,43 @@ pub unsafe fn spawn_init_process() {
     (*process_lock.trapframe.unwrap()).ss = user_data_selector;
     (*process_lock.trapframe.unwrap()).eflags = 0x200;
 
+    // Setup standard input, output and error, inherited by every other process
+    let console = open_console();
+    for _ in 0..3 {
+        process_lock.allocate_file_descriptor(Arc::clone(&console));
+    }
+
     // Setup Misc
     process_lock.name = String::from("kernel_init");
     process_lock.state = ProcessState::READY;
 }
 
 /// TODO: Use Map Pages instead of allocate range
-/// Perform virtual memory mapping on a given range. Useful to map multiple pages at once.
+/// Perform virtual memory mapping on a given range, with the given permissions. Useful to map
+/// multiple pages at once.
 pub fn allocate_range(
     page_dir: &mut Page,
     mut start_address: usize,
     end_address: usize,
+    perm: PageTableEntry,
 ) -> Result<usize, MemoryError> {
     start_address = ROUND_DOWN!(start_address, PAGE_SIZE);
 
     while start_address < end_address {
-        let mut page = allocate_page().expect("[ERROR] Failed to allocate page");
+        let mut page = allocate_user_page()?;
         page.zero();
 
         // Map page into a process's virtual memory
-        map_pages(
+        if let Err(error) = map_pages(
             page_dir,
             start_address,
             PAGE_SIZE,
             V2P!(page.as_ptr() as usize),
-            PTE_W | PTE_U,
-        )?;
+            perm,
+        ) {
+            deallocate_page(page.as_ptr() as usize);
+            return Err(error);
+        }
 
         start_address += PAGE_SIZE;
     }
@@ -295,7 +338,7 @@ pub fn load_process_memory(
     inode: &INode, // Index node from which data will be extracted
     offset: usize, // Offset from which data should start to be moved
     size: usize, // Amount of data to load into the process memory
-) -> Result<(), ()> {
+) -> Result<(), ELFError> {
     if offset > PAGE_SIZE {
         panic!("[ERROR] ELF Offset bigger than a full page");
     }
@@ -305,7 +348,7 @@ pub fn load_process_memory(
     while counter < size {
         // Get page that will be modified
         let page_table_entry = walk_page_dir(page_dir, address as usize + counter, false).unwrap();
-        let page_address = unsafe { P2V!(*page_table_entry & !0xFFF) };
+        let page_address = unsafe { P2V!(PTE_ADDRESS!(*page_table_entry)) };
         let mut page = Page::new(page_address as *mut u8);
         let mut page_slice = page.cast_to::<u8>();
 
@@ -317,7 +360,11 @@ pub fn load_process_memory(
         println!("0x{:X} - {}", first_page_offset, byte_count);
 
         // Read program's data
-        let inode_data = read_inode_data(inode, (offset + counter) as u32, byte_count as u32);
+        let inode_data = read_inode_data(inode, (offset + counter) as u32, byte_count as u32)
+            .map_err(|_| ELFError::ReadFailure)?;
+        if inode_data.len() != byte_count {
+            return Err(ELFError::ReadFailure);
+        }
 
         // Write data into the page
         page_slice[first_page_offset..(first_page_offset + byte_count)]
@@ -330,59 +377,90 @@ pub fn load_process_memory(
     Ok(())
 }
 
-pub fn resize_current_process_memory(amount: usize) -> Result<usize, MemoryError> {
-    let scheduler = unsafe { SCHEDULER.lock() };
-    let mut process = scheduler.current_process.as_ref().unwrap();
-    let current_size = process.lock().mem_size;
-    let page_dir = &mut Page::new(process.lock().pgdir.unwrap() as *mut u8);
-
-    if amount == 0 {
-        return Ok(current_size);
-    }
-
-    if current_size + amount > KERNEL_BASE {
-        return Err(MemoryError::MemorySpaceViolation);
-    }
-
-    println!("RESIZING by {}", amount);
-
-    let mut lower_boundary = ROUND_UP!(current_size, PAGE_SIZE);
-    while lower_boundary < current_size + amount {
-        // Allocate new pages to the current process
-        let mut page = allocate_page()?;
-        page.zero();
-
-        // Map page into a process's virtual memory
-        map_pages(
-            page_dir,
-            lower_boundary,
-            PAGE_SIZE,
-            V2P!(page.as_ptr() as usize),
-            PTE_W | PTE_U,
-        )?;
+/// Locks the memory of the current process until the guard is dropped. It is held while page faults
+/// are resolved and while memory is mapped or unmapped, which the threads of a process would
+/// otherwise do to the memory they share at the same time: two threads could back the same page,
+/// or one could back a page that another one has just unmapped. Since resolving a fault may sleep
+/// on the disk, this is a sleep lock, which must be taken before any spin lock.
+pub fn lock_process_memory() -> SleepLockGuard<'static, usize> {
+    let leader = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let page_dir = leader.lock().pgdir.map_or(0, |page_dir| page_dir as usize);
+    MEMORY_LOCKS.lock(page_dir)
+}
 
-        lower_boundary += PAGE_SIZE;
+/// Moves the break of the current process (the end of its memory) by amount bytes. Returns the
+/// previous break. Growing only reserves the new pages, which are backed on first access, while
+/// shrinking unmaps the pages above the new break. The break cannot move below the start of the
+/// heap, nor past the memory mappings.
+pub fn resize_current_process_memory(amount: isize) -> Result<usize, MemoryError> {
+    let _memory_lock = lock_process_memory();
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+
+    // Threads share the break, so it stays locked until it has moved
+    let mut process = process.lock();
+    let current_size = process.mem_size;
+    let page_dir = &mut Page::new(process.pgdir.unwrap() as *mut u8);
+
+    let new_size = current_size
+        .checked_add_signed(amount)
+        .filter(|&new_size| new_size >= process.heap_start)
+        .filter(|&new_size| new_size <= process.get_mappings_start())
+        .ok_or(MemoryError::MemorySpaceViolation)?;
+
+    if new_size > current_size {
+        reserve_lazy_pages(page_dir, current_size, new_size, PTE_W | PTE_U | PTE_NX)?;
+    } else if new_size < current_size {
+        unmap_user_pages(page_dir, ROUND_UP!(new_size, PAGE_SIZE), current_size);
     }
 
-    process.lock().mem_size = lower_boundary;
+    process.mem_size = new_size;
     Ok(current_size)
 }
 
-pub fn fork() {
-    let new_process_pid = unsafe { spawn_process().unwrap() };
+/// Duplicates the current process. Returns the pid of the new process to the parent, while the
+/// new process itself returns 0. Only the calling thread is duplicated, and the new process is a
+/// child of the whole current process, which any of its threads can wait for.
+pub fn fork() -> Result<usize, ProcessError> {
+    let memory_lock = lock_process_memory();
+    let new_process_pid = unsafe { spawn_process()? };
     let new_process = unsafe { PROCESS_LIST.lock().get_pid(new_process_pid).unwrap() };
-    let mut kernel_pgdir = setup_kernel_page_tables().unwrap();
+    let Ok(mut kernel_pgdir) = setup_kernel_page_tables() else {
+        reap_process(&new_process);
+        return Err(ProcessError::MemoryAllocationFailure);
+    };
     new_process.lock().pgdir = Some(kernel_pgdir.as_mut_ptr() as *mut usize);
 
-    let mut scheduler = unsafe { SCHEDULER.lock() };
+    let scheduler = unsafe { SCHEDULER.lock() };
     let process = scheduler.current_process.as_ref().unwrap();
-    let mut src_page_dir = Page::new(process.lock().pgdir.unwrap() as *mut u8);
 
-    unsafe { copy_process_virtual_memory(&mut src_page_dir, &mut kernel_pgdir) };
+    // Only the calling thread is copied, so the child is a process without threads, which runs on
+    // a copy of the memory of the leader
+    let leader = scheduler.get_current_leader().unwrap();
+    let mut src_page_dir = Page::new(leader.lock().pgdir.unwrap() as *mut u8);
 
-    new_process.lock().mem_size = process.lock().mem_size;
-    new_process.lock().parent = Some(Arc::clone(&process));
-    new_process.lock().name = process.lock().name.clone();
+    if unsafe { share_process_virtual_memory(&mut src_page_dir, &mut kernel_pgdir) }.is_err() {
+        drop(scheduler);
+        reap_process(&new_process);
+        return Err(ProcessError::MemoryAllocationFailure);
+    }
+
+    new_process.lock().mem_size = leader.lock().mem_size;
+    new_process.lock().heap_start = leader.lock().heap_start;
+
+    // The child has every segment of the parent attached as well
+    let mappings = leader.lock().mappings.clone();
+    mappings
+        .iter()
+        .filter_map(|mapping| mapping.segment)
+        .for_each(share_segment);
+    new_process.lock().mappings = mappings;
+
+    new_process.lock().parent = Some(Arc::clone(&leader));
+    new_process.lock().name = leader.lock().name.clone();
+    new_process.lock().current_working_directory = leader.lock().current_working_directory.clone();
+    new_process.lock().open_files = leader.lock().open_files.clone();
+    new_process.lock().priority = process.lock().priority;
+    new_process.lock().level = process.lock().priority;
     new_process.lock().state = ProcessState::READY;
 
     // Copy trapframe
@@ -390,92 +468,459 @@ pub fn fork() {
     unsafe { *new_process.lock().trapframe.unwrap() = parent_trapframe };
     unsafe { (*new_process.lock().trapframe.unwrap()).eax = 0 }; // Return 0 on child process
 
+    drop(memory_lock);
     unsafe { scheduler.resume() };
+    Ok(new_process_pid)
+}
+
+/// Creates a thread of the current process, which shares its memory and open files, and returns
+/// the pid of the thread. The thread starts in user space at entry, with its stack pointer at
+/// stack. Threads are children of the process that created the first one (their leader), and are
+/// reaped by join instead of wait. Threads may run on several CPUs at once, which all flush their
+/// TLB whenever the memory they share loses a mapping (see flush_page_dir).
+pub fn clone(entry: usize, stack: usize) -> Result<usize, ProcessError> {
+    let thread_pid = unsafe { spawn_process()? };
+    let thread = unsafe { PROCESS_LIST.lock().get_pid(thread_pid).unwrap() };
+
+    let scheduler = unsafe { SCHEDULER.lock() };
+    let process = scheduler.current_process.as_ref().unwrap();
+    let leader = scheduler.get_current_leader().unwrap();
+
+    let mut trapframe = unsafe { *process.lock().trapframe.unwrap() };
+    trapframe.eip = entry;
+    trapframe.esp = stack;
+    trapframe.eax = 0;
+
+    let priority = process.lock().priority;
+    let (page_dir, name) = {
+        let mut leader_lock = leader.lock();
+        leader_lock.thread_count += 1;
+        (leader_lock.pgdir, leader_lock.name.clone())
+    };
+
+    let mut thread_lock = thread.lock();
+    unsafe { *thread_lock.trapframe.unwrap() = trapframe };
+    thread_lock.pgdir = page_dir;
+    thread_lock.mem_size = 0; // Counted by the leader
+    thread_lock.heap_start = 0;
+    thread_lock.thread_count = 0;
+    thread_lock.leader = Some(Arc::clone(&leader));
+    thread_lock.parent = Some(leader);
+    thread_lock.name = name;
+    thread_lock.priority = priority;
+    thread_lock.level = priority;
+    thread_lock.state = ProcessState::READY;
+    Ok(thread_pid)
 }
 
-pub unsafe fn copy_process_virtual_memory(src_page_dir: &mut Page, dst_page_dir: &mut Page) {
-    let page_entries = PAGE_SIZE / core::mem::size_of::<usize>();
-    let src_page_dir_data = src_page_dir.cast_to::<usize>();
-    let dst_page_dir_data = dst_page_dir.cast_to::<usize>();
+/// Sets the priority of the process pid, which must be the current process or one of its children.
+/// The process moves to the level of its new priority right away, and is boosted back to it later
+/// on (see mlfq.rs). Round-robin scheduling ignores priorities. Returns the previous priority.
+pub fn set_priority(pid: usize, priority: usize) -> Result<usize, ProcessError> {
+    if priority >= NUMBER_PRIORITY_LEVELS {
+        return Err(ProcessError::InvalidPriority);
+    }
 
-    for i in 0..page_entries {
-        if i * PAGE_SIZE.pow(2) > KERNEL_BASE {
-            break;
+    let current_process = unsafe { SCHEDULER.lock().get_current_process().unwrap() };
+    let process = unsafe { PROCESS_LIST.lock() }
+        .list
+        .get(pid)
+        .map(Arc::clone)
+        .ok_or(ProcessError::NotFound)?;
+
+    let mut process_lock = if Arc::ptr_eq(&process, &current_process) {
+        process.lock()
+    } else {
+        let process_lock = process.lock();
+        let is_child = match process_lock.parent.as_ref() {
+            Some(parent) => Arc::ptr_eq(parent, &current_process),
+            None => false,
+        };
+
+        if !is_child {
+            return Err(ProcessError::NotPermitted);
         }
 
+        process_lock
+    };
+
+    if process_lock.state == ProcessState::EMPTY {
+        return Err(ProcessError::NotFound);
+    }
+
+    let previous_priority = process_lock.priority;
+    process_lock.priority = priority;
+    process_lock.level = priority;
+    process_lock.slice_ticks = 0;
+    Ok(previous_priority)
+}
+
+/// Takes a snapshot of every process that is not EMPTY, in the order of the process list.
+pub fn get_process_stats() -> Vec<ProcessStats> {
+    let process_list = unsafe { PROCESS_LIST.lock() };
+    let mut stats = Vec::new();
+
+    for process in process_list.list.iter() {
+        let process_lock = process.lock();
+        if process_lock.state == ProcessState::EMPTY {
+            continue;
+        }
+
+        let parent = process_lock.parent.as_ref().map(Arc::clone);
+        let mut process_stats = ProcessStats {
+            pid: process_lock.pid,
+            parent_pid: None,
+            state: process_lock.state,
+            name: process_lock.name.clone(),
+            mem_size: process_lock.mem_size,
+            user_ticks: process_lock.user_ticks,
+            kernel_ticks: process_lock.kernel_ticks,
+            context_switches: process_lock.context_switches,
+            start_tick: process_lock.start_tick,
+        };
+        drop(process_lock);
+
+        // The parent is only locked once the child is unlocked, as two processes are never locked
+        // at the same time
+        process_stats.parent_pid = parent.map(|parent| parent.lock().pid);
+        stats.push(process_stats);
+    }
+
+    stats
+}
+
+/// Share the user memory of src_page_dir with dst_page_dir. Page tables are copied, but pages are
+/// not: writable pages become read-only copy-on-write pages in both page directories, so that a
+/// page is only copied once either process writes to it (see copy_on_write).
+pub unsafe fn share_process_virtual_memory(
+    src_page_dir: &mut Page,
+    dst_page_dir: &mut Page,
+) -> Result<(), MemoryError> {
+    let src_page_dir_data = get_page_dir_entries(src_page_dir);
+    let dst_page_dir_data = get_page_dir_entries(dst_page_dir);
+
+    for i in 0..PAGE_DIR_INDEX!(KERNEL_BASE) {
         let src_page_dir_entry = src_page_dir_data[i];
-        let src_page_dir_flags = PTE_FLAGS!(src_page_dir_entry);
 
         // Empty page directory entry
         if src_page_dir_entry & PTE_P == 0 {
             continue;
         }
 
-        // Allocate page directory entry
-        let new_page_dir = allocate_page().expect("[ERROR] Failed to allocate page");
-        let new_page_dir_address = V2P!(new_page_dir.as_ptr() as usize);
-        dst_page_dir_data[i] = new_page_dir_address | src_page_dir_flags;
+        // Allocate page table
+        let mut dst_page_table = allocate_page()?;
+        dst_page_table.zero();
+        let dst_page_table_address = V2P!(dst_page_table.as_ptr() as usize) as PageTableEntry;
+        dst_page_dir_data[i] = dst_page_table_address | PTE_FLAGS!(src_page_dir_entry);
 
-        // Avoid calling walk_page_dir here due to lookup slowdown
-        let dst_page_dir_entry = dst_page_dir_data[i];
-        let dst_page_dir_address = P2V!(PTE_ADDRESS!(dst_page_dir_entry)) as *mut usize;
-        let dst_page_table_data = from_raw_parts_mut(dst_page_dir_address, PAGE_SIZE / 4);
+        let src_page_table_address = P2V!(PTE_ADDRESS!(src_page_dir_entry)) as *mut PageTableEntry;
+        let src_page_table_data = from_raw_parts_mut(src_page_table_address, NUMBER_PAGE_ENTRIES);
+        let dst_page_table_data = dst_page_table.cast_to::<PageTableEntry>();
 
-        let src_page_dir_entry = src_page_dir_data[i];
-        let src_page_dir_address = P2V!(PTE_ADDRESS!(src_page_dir_entry)) as *mut usize;
-        let src_page_table_data = from_raw_parts_mut(src_page_dir_address, PAGE_SIZE / 4);
+        for j in 0..NUMBER_PAGE_ENTRIES {
+            let mut page_table_entry = src_page_table_data[j];
 
-        for j in 0..page_entries {
-            // Empty page table entry
-            if src_page_table_data[j] & PTE_P == 0 {
+            // Empty page table entry, or a reserved page that is not backed yet
+            if page_table_entry & PTE_P == 0 {
+                dst_page_table_data[j] = page_table_entry;
                 continue;
             }
 
-            let src_page_address = PTE_ADDRESS!(src_page_table_data[j]);
-            let src_page_flags = PTE_FLAGS!(src_page_table_data[j]);
-            let new_page = allocate_page().expect("[ERROR] Failed to allocate page");
-            let new_page_address = V2P!(new_page.as_ptr() as usize);
-            dst_page_table_data[j] = new_page_address | src_page_flags;
-
-            mem_move(
-                P2V!(src_page_address) as *mut u8,
-                P2V!(new_page_address) as *mut u8,
-                PAGE_SIZE,
-            );
+            // Pages of shared memory segments stay shared
+            if page_table_entry & PTE_W > 0 && page_table_entry & PTE_SHARED == 0 {
+                page_table_entry = (page_table_entry & !PTE_W) | PTE_COW;
+                src_page_table_data[j] = page_table_entry;
+            }
+
+            dst_page_table_data[j] = page_table_entry;
+            share_page(P2V!(PTE_ADDRESS!(page_table_entry)));
         }
     }
+
+    // Pages of the source are no longer writable
+    flush_page_dir(src_page_dir);
+    Ok(())
+}
+
+/// Whether the current process has been killed by another of its threads calling exec (see
+/// end_other_threads)
+pub fn is_current_killed() -> bool {
+    let process = unsafe { SCHEDULER.lock().get_current_process() };
+    process.map_or(false, |process| process.lock().killed)
 }
 
-pub fn wait() {
+/// Ends every other thread of the current process, so that exec can replace the memory they share.
+/// The other threads are killed, and exit once they are about to return to user space, while the
+/// current thread waits for them. A thread other than the leader then takes the place of the
+/// leader, so that the process keeps its pid, its parent and its children. Returns false if the
+/// current thread has been killed itself, by another thread that called exec first.
+pub fn end_other_threads() -> bool {
     let scheduler = unsafe { SCHEDULER.lock() };
-    let process_list = unsafe { PROCESS_LIST.lock() };
-    let parent = scheduler.current_process.as_ref().unwrap();
+    let process = scheduler.get_current_process().unwrap();
+    let leader = scheduler.get_current_leader().unwrap();
+    drop(scheduler);
+
+    // Threads keep running until they are zombies, and may create more threads in the meantime
+    let mut process_list = loop {
+        let process_list = unsafe { PROCESS_LIST.lock() };
+        if process.lock().killed {
+            return false;
+        }
 
-    loop {
-        let mut has_children = false;
+        let mut has_live_threads = false;
+        for thread in process_list.list.iter() {
+            let mut thread_lock = thread.lock();
 
-        // Check if any children are still alive
-        for process in process_list.list.iter() {
-            if process.lock().parent.is_none() {
+            let is_thread = match thread_lock.leader.as_ref() {
+                Some(thread_leader) => Arc::ptr_eq(thread_leader, &leader),
+                None => Arc::ptr_eq(thread, &leader),
+            };
+
+            if !is_thread
+                || Arc::ptr_eq(thread, &process)
+                || thread_lock.state == ProcessState::ZOMBIE
+            {
                 continue;
             }
 
-            let parent_pid = process.lock().parent.as_ref().unwrap().lock().pid;
-            if parent_pid != parent.lock().pid {
-                continue;
+            has_live_threads = true;
+            if !thread_lock.killed {
+                thread_lock.killed = true;
+
+                // Threads waiting for good, such as on an empty pipe, give up (see sleep_killable)
+                if thread_lock.state == ProcessState::SLEEPING {
+                    thread_lock.state = ProcessState::READY;
+                }
             }
+        }
+
+        if !has_live_threads {
+            break process_list;
+        }
+
+        // Exiting threads wake up their leader (see exit)
+        let leader_address = leader.as_ref() as *const SpinMutex<Process> as usize;
+        sleep(leader_address, process_list);
+    };
+
+    // Threads that have exited are not joined anymore. PROCESS_LIST is held, so they are already
+    // off their kernel stack.
+    let zombie_threads: Vec<_> = process_list
+        .list
+        .iter()
+        .filter(|thread| !Arc::ptr_eq(thread, &process))
+        .filter(|thread| {
+            let thread_lock = thread.lock();
+            let is_thread = match thread_lock.leader.as_ref() {
+                Some(thread_leader) => Arc::ptr_eq(thread_leader, &leader),
+                None => false,
+            };
+            is_thread && thread_lock.state == ProcessState::ZOMBIE
+        })
+        .cloned()
+        .collect();
+
+    zombie_threads.iter().for_each(|thread| {
+        reap_process(thread);
+    });
+
+    if Arc::ptr_eq(&process, &leader) {
+        return true;
+    }
+
+    // The leader has exited but kept everything the process owns, as the current thread was still
+    // running. The current thread takes its slot in the process list, and so its pid.
+    let (leader_pid, pid) = (leader.lock().pid, process.lock().pid);
+    process_list.list.swap(leader_pid, pid);
+
+    {
+        let mut leader_lock = leader.lock();
+        let mut process_lock = process.lock();
+        process_lock.pid = leader_pid;
+        leader_lock.pid = pid;
+
+        process_lock.leader = None;
+        process_lock.thread_count = 1;
+        process_lock.parent = leader_lock.parent.take();
+        process_lock.adopted = leader_lock.adopted;
+        process_lock.pgdir = leader_lock.pgdir.take();
+        process_lock.mem_size = leader_lock.mem_size;
+        process_lock.heap_start = leader_lock.heap_start;
+        process_lock.mappings = core::mem::take(&mut leader_lock.mappings);
+        process_lock.open_files = core::mem::take(&mut leader_lock.open_files);
+        process_lock.current_working_directory =
+            core::mem::take(&mut leader_lock.current_working_directory);
+        process_lock.start_tick = leader_lock.start_tick;
+    }
+
+    process_list.list.iter().for_each(|child| {
+        let mut child_lock = child.lock();
+        let is_child = match child_lock.parent.as_ref() {
+            Some(parent) => Arc::ptr_eq(parent, &leader),
+            None => false,
+        };
+
+        if is_child {
+            child_lock.parent = Some(Arc::clone(&process));
+        }
+    });
+
+    reap_process(&leader);
+    true
+}
+
+/// Wait for a child to exit and release its resources. The child is selected by pid, or can be
+/// any child with WAIT_ANY_CHILD. Returns the pid and exit code of the child. With WNOHANG, returns
+/// a pid of 0 if no child has exited yet. Fails if the process has no such child.
+/// Threads are not waited for (see join).
+pub fn wait(pid: usize, flags: usize) -> Option<(usize, i32)> {
+    // Children belong to the process as a whole, so that any of its threads can wait for them
+    let parent = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    wait_for_child(&parent, pid, flags, false)
+}
+
+/// Wait for the thread tid of the current process to exit and release its resources. Any thread
+/// can join any other thread of its process. Returns the pid and exit code of the thread, and fails
+/// if the process has no such thread.
+pub fn join(tid: usize) -> Option<(usize, i32)> {
+    let scheduler = unsafe { SCHEDULER.lock() };
+    let process = scheduler.get_current_process().unwrap();
+    let leader = scheduler.get_current_leader().unwrap();
+    drop(scheduler);
+
+    // A thread would wait for itself forever
+    if process.lock().pid == tid {
+        return None;
+    }
+
+    wait_for_child(&leader, tid, 0, true)
+}
+
+/// Wait for a child of parent to exit (see wait). Only threads are selected if thread is set, and
+/// only other children otherwise.
+fn wait_for_child(
+    parent: &Arc<SpinMutex<Process>>,
+    pid: usize,
+    flags: usize,
+    thread: bool,
+) -> Option<(usize, i32)> {
+    loop {
+        let mut has_children = false;
+        let mut zombie = None;
 
-            if process.lock().state == ProcessState::KILLED {
+        // Check if any children are still alive. PROCESS_LIST is held until the process sleeps, so
+        // a child cannot exit unnoticed in between.
+        let process_list = unsafe { PROCESS_LIST.lock() };
+        for process in process_list.list.iter() {
+            let process_lock = process.lock();
+
+            let is_child = match process_lock.parent.as_ref() {
+                Some(process_parent) => Arc::ptr_eq(process_parent, parent),
+                None => false,
+            };
+
+            if !is_child
+                || process_lock.leader.is_some() != thread
+                || (pid != WAIT_ANY_CHILD && process_lock.pid != pid)
+            {
                 continue;
             }
 
             has_children = true;
+
+            // A process keeps its memory until its last thread exits, and is not done before
+            if process_lock.state == ProcessState::ZOMBIE && process_lock.pgdir.is_none() {
+                zombie = Some(Arc::clone(process));
+                break;
+            }
+        }
+
+        // Reaped while PROCESS_LIST is held, so that nobody else reaps it too (see reap_orphans)
+        if let Some(zombie) = zombie {
+            return Some(reap_process(&zombie));
         }
 
         if has_children == false {
-            return;
+            return None;
         }
 
-        sleep(parent.as_ref() as *const SpinMutex<Process> as usize);
+        if flags & WNOHANG > 0 {
+            return Some((0, 0));
+        }
+
+        // A killed process gives up, and exits on its way back to user space
+        let parent_address = parent.as_ref() as *const SpinMutex<Process> as usize;
+        if sleep_killable(parent_address, process_list) {
+            return None;
+        }
     }
 }
+
+/// Reap the orphans adopted by init that have exited. Init is a user program, which may never wait
+/// for its children, so this is done in the background by a kernel worker (see kthread.rs).
+pub fn reap_orphans() {
+    let process_list = unsafe { PROCESS_LIST.lock() };
+    let init_process = process_list.get_pid(INIT_PROCESS_ID).unwrap();
+
+    for process in process_list.list.iter() {
+        let process_lock = process.lock();
+
+        // Children that init created itself are left for init to wait for
+        let is_orphan = match process_lock.parent.as_ref() {
+            Some(parent) => Arc::ptr_eq(parent, &init_process) && process_lock.adopted,
+            None => false,
+        };
+
+        // PROCESS_LIST is held, so an exited process is already off its kernel stack
+        if is_orphan && process_lock.state == ProcessState::ZOMBIE && process_lock.pgdir.is_none() {
+            drop(process_lock);
+            reap_process(process);
+        }
+    }
+}
+
+/// Release the user memory of a process that will never return to user space, such as one that
+/// exits. Its page directory is loaded, so the one of the Kernel is loaded in its place.
+pub fn free_process_memory(process: &Arc<SpinMutex<Process>>) {
+    // Everything is taken at once, as the process may be reaped as soon as its page directory is
+    // gone (see wait_for_child)
+    let (page_dir, mappings) = {
+        let mut process = process.lock();
+        let Some(page_dir) = process.pgdir.take() else {
+            return;
+        };
+
+        process.mem_size = 0;
+        process.heap_start = 0;
+        (page_dir, core::mem::take(&mut process.mappings))
+    };
+
+    unsafe { switch_kernel_virtual_memory() };
+    deallocate_page_dir(&mut Page::new(page_dir as *mut u8));
+
+    // Frames of a segment are only freed once no other process has it attached
+    mappings
+        .iter()
+        .filter_map(|mapping| mapping.segment)
+        .for_each(release_segment);
+}
+
+/// Release the kernel stack of a zombie (along with the memory of a process that failed to fork),
+/// freeing its slot in the process list.
+/// Returns the pid and exit code of the process.
+pub fn reap_process(process: &Arc<SpinMutex<Process>>) -> (usize, i32) {
+    let mut process_lock = process.lock();
+    let pid = process_lock.pid;
+    let exit_code = process_lock.exit_code;
+
+    if let Some(kernel_stack) = process_lock.kernel_stack {
+        deallocate_page(kernel_stack as usize);
+    }
+
+    if let Some(page_dir) = process_lock.pgdir {
+        deallocate_page_dir(&mut Page::new(page_dir as *mut u8));
+    }
+
+    *process_lock = Process::new(pid);
+    (pid, exit_code)
+}
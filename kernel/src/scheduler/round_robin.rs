@@ -0,0 +1,36 @@
+/// Round-robin scheduling, used unless the "mlfq" feature is enabled. Ready processes take turns
+/// in the order of the process list, and each one gives the CPU up on every timer tick.
+use alloc::sync::Arc;
+
+use crate::sync::spin_mutex::SpinMutex;
+
+use super::defs::process::{Process, ProcessList, ProcessState};
+
+impl ProcessList {
+    pub fn get_next_ready(&mut self) -> Option<&Arc<SpinMutex<Process>>> {
+        if self.next_to_visit >= self.list.len() {
+            self.next_to_visit = 0;
+        }
+
+        let mut index = self.next_to_visit;
+        for process in self.list.iter().skip(self.next_to_visit) {
+            if process.lock().state == ProcessState::READY {
+                break;
+            }
+
+            index += 1;
+        }
+
+        self.next_to_visit = index + 1;
+        if index >= self.list.len() {
+            return None;
+        }
+
+        Some(&self.list[index])
+    }
+}
+
+/// Accounts a timer tick to the running process. Returns whether it must give the CPU up.
+pub fn tick(_process: &Arc<SpinMutex<Process>>) -> bool {
+    true
+}
@@ -3,21 +3,26 @@ use alloc::sync::Arc;
 use crate::{
     apic::mp::get_my_cpu,
     memory::defs::KERNEL_BASE,
-    memory::vm::KERNEL_PAGE_DIR,
-    println,
-    scheduler::process::switch_user_virtual_memory,
-    sync::spin_mutex::SpinMutex,
-    x86::helpers::{load_cr3, sti},
+    memory::vm::{load_page_dir, KERNEL_PAGE_DIR},
+    scheduler::process::{reap_process, switch_user_virtual_memory},
+    sync::{
+        cpu_cli::{pop_cli, push_cli},
+        spin_mutex::{SpinMutex, SpinMutexGuard},
+    },
+    x86::helpers::sti,
     V2P,
 };
 
 use super::defs::{
     process::{Context, Process, ProcessList, ProcessState, TrapFrame},
-    scheduler::{Scheduler, SchedulerState},
+    scheduler::{CpuScheduler, Scheduler, SchedulerState},
 };
 
 pub static mut PROCESS_LIST: SpinMutex<ProcessList> = SpinMutex::new(ProcessList::new());
-pub static mut SCHEDULER: SpinMutex<Scheduler> = SpinMutex::new(Scheduler::new());
+
+/// Scheduler of the CPU running the caller. Every CPU runs its own scheduler, while they all pick
+/// processes from PROCESS_LIST.
+pub static mut SCHEDULER: CpuScheduler = CpuScheduler;
 
 extern "C" {
     fn switch(scheduler_context: *mut *mut Context, process_context: *mut Context);
@@ -39,6 +44,14 @@ impl Scheduler {
         }
     }
 
+    /// Process that owns the memory and open files of the current process. Threads share those of
+    /// the process that created them (see clone), while every other process owns its own.
+    pub fn get_current_leader(&self) -> Option<Arc<SpinMutex<Process>>> {
+        let process = self.current_process.as_ref()?;
+        let leader = process.lock().leader.clone();
+        Some(leader.unwrap_or_else(|| Arc::clone(process)))
+    }
+
     pub fn set_trapframe(&mut self, trapframe: *mut TrapFrame) {
         let process = self.current_process.as_ref().unwrap();
         let process_lock = process.lock();
@@ -60,31 +73,6 @@ impl Scheduler {
         }
     }
 
-    pub unsafe fn resume(&mut self) {
-        let process = self.current_process.as_ref().unwrap();
-        let mut process_lock = process.lock();
-
-        // Handle current process state
-        process_lock.state = if process_lock.state == ProcessState::RUNNING {
-            ProcessState::READY
-        } else {
-            process_lock.state
-        };
-
-        // Prepare process context for switching
-        let process_context = process_lock.context.as_mut().unwrap();
-
-        SCHEDULER.force_unlock();
-        PROCESS_LIST.force_unlock();
-        process.force_unlock();
-
-        let cpu = get_my_cpu();
-        let enable_interrupts = cpu.get_interrupt_state();
-
-        switch(process_context, self.context);
-        cpu.set_interrupt_state(enable_interrupts);
-    }
-
     pub unsafe fn run_scheduler(&mut self) {
         SCHEDULER.force_unlock();
 
@@ -93,30 +81,43 @@ impl Scheduler {
 
             let mut process_list = PROCESS_LIST.lock();
 
-            // No need to unlock here, lock is dropped automatically
             let Some(process) = process_list.get_next_ready() else {
-                PROCESS_LIST.force_unlock();
+                drop(process_list);
                 core::hint::spin_loop(); // Hint to the processor that it should save power here
                 continue;
             };
 
             // println!("RUNNING {}", process.lock().name);
 
+            let process = Arc::clone(process);
             let mut process_lock = process.lock();
             let process_context = process_lock.context.expect("[FATAL] No Context");
             process_lock.state = ProcessState::RUNNING;
+            process_lock.context_switches += 1;
+            drop(process_lock);
 
             // Update Scheduler and Process States
             self.status = SchedulerState::BUSY;
-            self.current_process = Some(Arc::clone(process));
+            self.current_process = Some(Arc::clone(&process));
 
-            PROCESS_LIST.force_unlock();
-            process.force_unlock();
+            // PROCESS_LIST stays locked until the process runs on its own stack, and it is locked
+            // again before the process switches back, so no other CPU can pick it in between
+            core::mem::forget(process_list);
 
             unsafe {
-                switch_user_virtual_memory(process);
+                switch_user_virtual_memory(&process);
                 switch(&mut self.context, process_context);
                 switch_kernel_virtual_memory();
+
+                // Processes without parent, such as kernel threads, have nobody to wait for them,
+                // so they are reaped here, once they are off their kernel stack
+                let process_lock = process.lock();
+                if process_lock.parent.is_none() && process_lock.state == ProcessState::ZOMBIE {
+                    drop(process_lock);
+                    reap_process(&process);
+                }
+
+                PROCESS_LIST.force_unlock();
             };
 
             self.current_process = None;
@@ -137,10 +138,74 @@ impl Scheduler {
     }
 }
 
+impl CpuScheduler {
+    pub fn lock(&self) -> SpinMutexGuard<'static, Scheduler> {
+        // The CPU must not change between finding its scheduler and locking it
+        push_cli();
+        let scheduler = get_my_cpu().scheduler.lock();
+        pop_cli();
+
+        scheduler
+    }
+
+    pub unsafe fn force_unlock(&self) {
+        get_my_cpu().scheduler.force_unlock();
+    }
+}
+
+impl SpinMutexGuard<'_, Scheduler> {
+    /// Gives the CPU back to the scheduler until the current process is picked again. The process
+    /// may be picked by the scheduler of another CPU, so the guard is released here instead of
+    /// being left to the caller.
+    pub unsafe fn resume(self) {
+        let process_list = PROCESS_LIST.lock();
+
+        let process_context = {
+            let mut process_lock = self.current_process.as_ref().unwrap().lock();
+
+            // Handle current process state
+            process_lock.state = if process_lock.state == ProcessState::RUNNING {
+                ProcessState::READY
+            } else {
+                process_lock.state
+            };
+
+            // Prepare process context for switching
+            process_lock.context.as_mut().unwrap() as *mut *mut Context
+        };
+
+        let scheduler_context = self.context;
+        drop(self);
+
+        // PROCESS_LIST is released by the scheduler once it runs on its own stack again
+        core::mem::forget(process_list);
+        let enable_interrupts = get_my_cpu().get_interrupt_state();
+
+        switch(process_context, scheduler_context);
+
+        // The scheduler that picked the process holds PROCESS_LIST on its behalf
+        get_my_cpu().set_interrupt_state(enable_interrupts);
+        PROCESS_LIST.force_unlock();
+    }
+}
+
+/// Start of every new process, which the scheduler switches to while holding PROCESS_LIST. Returns
+/// into trap_return with the value of eax set in the trapframe, such as 0 for a forked child.
+pub unsafe extern "C" fn fork_return() -> usize {
+    let trapframe = SCHEDULER.lock().get_trapframe().unwrap();
+
+    // Interrupts stay disabled until the process is back in user space
+    get_my_cpu().set_interrupt_state(false);
+    PROCESS_LIST.force_unlock();
+
+    (*trapframe).eax
+}
+
 pub unsafe fn switch_kernel_virtual_memory() {
-    load_cr3(V2P!(KERNEL_PAGE_DIR.lock().unwrap()));
+    load_page_dir(V2P!(KERNEL_PAGE_DIR.lock().unwrap()));
 }
 
+/// Runs the scheduler of the CPU, which never returns. Every CPU ends up here once it is set up.
 pub fn setup_scheduler() {
     unsafe {
         SCHEDULER.lock().run_scheduler();
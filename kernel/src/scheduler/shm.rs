@@ -0,0 +1,184 @@
+/// Shared memory segments are sets of frames mapped into the memory of several processes at once,
+/// so that a write by one of them is seen right away by the others. A segment is identified by a
+/// key chosen by the process that creates it, and lives as long as some process has it attached.
+/// Attached segments are placed among the memory mappings (see mmap.rs), and fork keeps sharing
+/// their pages instead of copying them on write (see PTE_SHARED).
+use alloc::vec::Vec;
+
+use crate::{
+    memory::{
+        defs::{Page, KERNEL_BASE, PAGE_SIZE, PTE_NX, PTE_SHARED, PTE_U, PTE_W},
+        vm::{allocate_user_page, deallocate_page, map_pages, share_page, unmap_user_pages},
+    },
+    sync::spin_mutex::SpinMutex,
+    V2P,
+};
+
+use super::{
+    defs::{
+        process::{MemoryMapping, Process},
+        shm::{SharedMemory, SharedSegment, MAX_SEGMENT_PAGES},
+    },
+    error::SharedMemoryError,
+    process::lock_process_memory,
+    scheduler::SCHEDULER,
+};
+
+pub static SHARED_MEMORY: SpinMutex<SharedMemory> = SpinMutex::new(SharedMemory::new());
+
+impl SharedMemory {
+    pub const fn new() -> Self {
+        SharedMemory {
+            segments: Vec::new(),
+        }
+    }
+
+    fn get_segment(&mut self, key: usize) -> Option<&mut SharedSegment> {
+        self.segments.iter_mut().find(|segment| segment.key == key)
+    }
+}
+
+/// Maps the frames of the segment into the memory of the process. The segment holds a reference
+/// to each of its frames, and so does every mapping of it.
+fn map_segment(
+    process: &mut Process,
+    segment: &mut SharedSegment,
+) -> Result<usize, SharedMemoryError> {
+    let size = segment.frames.len() * PAGE_SIZE;
+    let start = process
+        .find_mapping_address(size)
+        .ok_or(SharedMemoryError::MemoryAllocationFailure)?;
+    let mut page_dir = Page::new(process.pgdir.unwrap() as *mut u8);
+
+    for (index, &frame) in segment.frames.iter().enumerate() {
+        let address = start + index * PAGE_SIZE;
+        let perm = PTE_W | PTE_U | PTE_NX | PTE_SHARED;
+
+        if map_pages(&mut page_dir, address, PAGE_SIZE, V2P!(frame), perm).is_err() {
+            unmap_user_pages(&mut page_dir, start, address);
+            return Err(SharedMemoryError::MemoryAllocationFailure);
+        }
+
+        share_page(frame);
+    }
+
+    process.add_mapping(MemoryMapping {
+        start,
+        end: start + size,
+        writable: true,
+        inode: None,
+        offset: 0,
+        segment: Some(segment.key),
+    });
+
+    segment.attachments += 1;
+    Ok(start)
+}
+
+/// Creates a zeroed segment of size bytes under key, and attaches it to the current process.
+/// Returns the address of the segment.
+pub fn create_segment(key: usize, size: usize) -> Result<usize, SharedMemoryError> {
+    if size == 0 || size > MAX_SEGMENT_PAGES * PAGE_SIZE {
+        return Err(SharedMemoryError::InvalidSize);
+    }
+
+    let _memory_lock = lock_process_memory();
+
+    let mut shared_memory = SHARED_MEMORY.lock();
+    if shared_memory.get_segment(key).is_some() {
+        return Err(SharedMemoryError::AlreadyExists);
+    }
+
+    let mut segment = SharedSegment {
+        key,
+        frames: Vec::new(),
+        attachments: 0,
+    };
+
+    for _ in 0..size.div_ceil(PAGE_SIZE) {
+        let Ok(mut page) = allocate_user_page() else {
+            segment.frames.into_iter().for_each(deallocate_page);
+            return Err(SharedMemoryError::MemoryAllocationFailure);
+        };
+
+        page.zero();
+        segment.frames.push(page.as_ptr() as usize);
+    }
+
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let address = map_segment(&mut process.lock(), &mut segment);
+
+    match address {
+        Ok(_) => shared_memory.segments.push(segment),
+        Err(_) => segment.frames.into_iter().for_each(deallocate_page),
+    }
+
+    address
+}
+
+/// Attaches the segment created under key to the current process. Returns the address of the
+/// segment, which may differ from one process to another.
+pub fn attach_segment(key: usize) -> Result<usize, SharedMemoryError> {
+    let _memory_lock = lock_process_memory();
+    let mut shared_memory = SHARED_MEMORY.lock();
+    let segment = shared_memory
+        .get_segment(key)
+        .ok_or(SharedMemoryError::NotFound)?;
+
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let mut process_lock = process.lock();
+    map_segment(&mut process_lock, segment)
+}
+
+/// Detaches the segment mapped at address from the current process
+pub fn detach_segment(address: usize) -> Result<(), SharedMemoryError> {
+    let _memory_lock = lock_process_memory();
+    let process = unsafe { SCHEDULER.lock().get_current_leader().unwrap() };
+    let mut process_lock = process.lock();
+
+    let index = process_lock
+        .mappings
+        .iter()
+        .position(|mapping| mapping.start == address && mapping.segment.is_some())
+        .ok_or(SharedMemoryError::NotFound)?;
+    let mapping = process_lock.mappings.remove(index);
+
+    let mut page_dir = Page::new(process_lock.pgdir.unwrap() as *mut u8);
+    unmap_user_pages(&mut page_dir, mapping.start, mapping.end);
+    drop(process_lock);
+
+    release_segment(mapping.segment.unwrap());
+    Ok(())
+}
+
+/// Counts one more attachment of the segment, whose pages were mapped by fork
+pub fn share_segment(key: usize) {
+    let mut shared_memory = SHARED_MEMORY.lock();
+    let segment = shared_memory
+        .get_segment(key)
+        .expect("[ERROR] Attached segment does not exist");
+
+    segment.attachments += 1;
+}
+
+/// Counts one less attachment of the segment, whose pages were unmapped. Once the last attachment
+/// is gone, the segment is destroyed and its frames are freed.
+pub fn release_segment(key: usize) {
+    let mut shared_memory = SHARED_MEMORY.lock();
+    let segment = shared_memory
+        .get_segment(key)
+        .expect("[ERROR] Attached segment does not exist");
+
+    segment.attachments -= 1;
+    if segment.attachments > 0 {
+        return;
+    }
+
+    let index = shared_memory
+        .segments
+        .iter()
+        .position(|segment| segment.key == key)
+        .unwrap();
+    let segment = shared_memory.segments.remove(index);
+    segment.frames.into_iter().for_each(deallocate_page);
+}
@@ -1,4 +1,11 @@
-use crate::{scheduler::defs::process::ProcessState, x86::helpers::cli};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    interrupts::irqs::TICKS,
+    scheduler::defs::process::{ProcessList, ProcessState},
+    sync::spin_mutex::SpinMutexGuard,
+    x86::helpers::cli,
+};
 
 use super::scheduler::{PROCESS_LIST, SCHEDULER};
 
@@ -6,20 +13,51 @@ use super::scheduler::{PROCESS_LIST, SCHEDULER};
 /// the object it is waiting for is ready. Sleep is accompanied by the wakeup method, which
 /// together are capable of putting processes to sleep and then adding them back to the queue
 /// once the wakeup signal is emitted.
-pub fn sleep(object: usize) {
-    let mut scheduler_lock = unsafe { SCHEDULER.lock() };
+///
+/// The guard holds the lock under which the caller found that it must wait. It is only released
+/// once PROCESS_LIST is held, which wakeup needs as well, so that a wakeup emitted by another CPU
+/// right after the check cannot be missed. The lock is not held anymore once sleep returns.
+pub fn sleep<T: ?Sized>(object: usize, guard: SpinMutexGuard<T>) {
+    sleep_process(object, guard, false);
+}
+
+/// Same as sleep, for waits that may never end, such as a read of an empty pipe. A killed process
+/// is not put to sleep (see end_other_threads), so that it can return to user space and exit.
+/// Returns whether the process was killed, in which case the caller must give up waiting.
+pub fn sleep_killable<T: ?Sized>(object: usize, guard: SpinMutexGuard<T>) -> bool {
+    sleep_process(object, guard, true)
+}
+
+fn sleep_process<T: ?Sized>(object: usize, guard: SpinMutexGuard<T>, killable: bool) -> bool {
+    let scheduler_lock = unsafe { SCHEDULER.lock() };
 
     let current_process_lock = scheduler_lock
         .get_current_process()
         .expect("[ERROR] Sleep on empty scheduler");
 
+    let process_list = unsafe { PROCESS_LIST.lock() };
+
+    // Processes are killed while PROCESS_LIST is held, so this cannot miss one
+    if killable && current_process_lock.lock().killed {
+        return true;
+    }
+
+    // Both guards would hold the same lock, which must stay locked until the scheduler takes over
+    if guard.belongs_to(unsafe { &PROCESS_LIST }) {
+        core::mem::forget(guard);
+    } else {
+        drop(guard);
+    }
+
     let mut current_process = current_process_lock.lock();
 
     // Put process to sleep and release lock
     current_process.state = ProcessState::SLEEPING;
     current_process.sleep_object = object;
-    unsafe { current_process_lock.force_unlock() };
+    drop(current_process);
 
+    // Resume keeps PROCESS_LIST locked until the process is off the CPU
+    core::mem::forget(process_list);
     unsafe { scheduler_lock.resume() };
 
     // Ensure interrupts are clear until the execution returns to the process
@@ -28,6 +66,7 @@ pub fn sleep(object: usize) {
     // Clean up process from sleep and reacquire lock
     let mut current_process = current_process_lock.lock();
     current_process.sleep_object = 0;
+    killable && current_process.killed
 }
 
 /// Wakeup is a signal to all processes waiting on an object that the object they request is
@@ -35,6 +74,11 @@ pub fn sleep(object: usize) {
 /// list and wakes up all processes that rely on the provided object.
 pub fn wakeup(object: usize) {
     let mut process_list = unsafe { PROCESS_LIST.lock() };
+    wakeup_locked(&mut process_list, object);
+}
+
+/// Same as wakeup, for callers that already hold PROCESS_LIST
+pub fn wakeup_locked(process_list: &mut ProcessList, object: usize) {
     process_list.list.iter_mut().for_each(|process_lock| {
         let mut process = process_lock.lock();
         if process.sleep_object == object && process.state == ProcessState::SLEEPING {
@@ -42,3 +86,22 @@ pub fn wakeup(object: usize) {
         }
     });
 }
+
+/// Puts the current process to sleep for at least the given number of timer ticks. The timer
+/// wakes up every process sleeping on TICKS once per tick, and each one checks whether it has
+/// slept for long enough.
+pub fn sleep_ticks(ticks: usize) {
+    let start = TICKS.load(Ordering::Relaxed);
+
+    loop {
+        // The timer needs PROCESS_LIST to wake sleepers up, so no tick is missed after the check
+        let process_list = unsafe { PROCESS_LIST.lock() };
+        if TICKS.load(Ordering::Relaxed).wrapping_sub(start) >= ticks {
+            return;
+        }
+
+        if sleep_killable(&TICKS as *const AtomicUsize as usize, process_list) {
+            return;
+        }
+    }
+}
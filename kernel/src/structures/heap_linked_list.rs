@@ -51,6 +51,20 @@ impl<T> HeapLinkedList<T> {
         }
     }
 
+    /// Remove the first value matching the predicate, keeping the order of the other values.
+    pub fn remove<F: Fn(&T) -> bool>(&mut self, predicate: F) -> Option<T> {
+        let mut current = &mut self.head;
+
+        while current.as_ref().is_some_and(|node| !predicate(&node.value)) {
+            current = &mut current.as_mut().unwrap().next;
+        }
+
+        let node = current.take()?;
+        *current = node.next;
+        self.size -= 1;
+        Some(node.value)
+    }
+
     pub fn is_empty(&self) -> bool {
         return self.head.is_none();
     }
@@ -1,2 +1,3 @@
 pub mod cpu_cli;
+pub mod sleep_lock;
 pub mod spin_mutex;
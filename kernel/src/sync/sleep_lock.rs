@@ -0,0 +1,49 @@
+use alloc::vec::Vec;
+
+use crate::scheduler::sleep::{sleep, wakeup};
+
+use super::spin_mutex::SpinMutex;
+
+/// Locks held by processes across operations that may sleep, such as disk accesses, which a
+/// SpinMutex cannot be held across. A process that finds a lock taken sleeps until it is released
+/// instead of spinning. Each lock is identified by a key, so that one SleepLock can stand for a
+/// lock per inode. Unlike SpinMutex, these locks are not recursive, and only processes can take
+/// them.
+pub struct SleepLock<K: Copy + PartialEq> {
+    held: SpinMutex<Vec<K>>, // Keys of the locks currently taken
+}
+
+/// A guard of one of the locks of a SleepLock, released when it falls out of scope
+pub struct SleepLockGuard<'a, K: Copy + PartialEq> {
+    lock: &'a SleepLock<K>,
+    key: K,
+}
+
+impl<K: Copy + PartialEq> SleepLock<K> {
+    pub const fn new() -> Self {
+        SleepLock {
+            held: SpinMutex::new(Vec::new()),
+        }
+    }
+
+    /// Takes the lock identified by key, sleeping until no other process holds it
+    pub fn lock(&self, key: K) -> SleepLockGuard<'_, K> {
+        loop {
+            let mut held = self.held.lock();
+            if !held.contains(&key) {
+                held.push(key);
+                return SleepLockGuard { lock: self, key };
+            }
+
+            // Waiters of every key share the same sleep object, and check their own key again
+            sleep(self as *const Self as usize, held);
+        }
+    }
+}
+
+impl<'a, K: Copy + PartialEq> Drop for SleepLockGuard<'a, K> {
+    fn drop(&mut self) {
+        self.lock.held.lock().retain(|key| *key != self.key);
+        wakeup(self.lock as *const SleepLock<K> as usize);
+    }
+}
@@ -8,9 +8,14 @@ use core::option::Option::{self, None, Some};
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 use crate::apic::mp::{get_my_cpu, IS_CPU_MAPPED};
+use crate::memory::vm::handle_tlb_flush;
 
 use super::cpu_cli::{pop_cli, push_cli};
 
+// Owner of a lock that is not held. Otherwise, a CPU could take the previous owner of a lock for
+// the current one, and enter it as if it was already holding it.
+const NO_CPU: u8 = u8::MAX;
+
 pub struct SpinMutex<T: ?Sized> {
     lock: AtomicBool,
     cpu: AtomicU8,
@@ -22,6 +27,7 @@ pub struct SpinMutex<T: ?Sized> {
 #[derive(Debug)]
 pub struct SpinMutexGuard<'a, T: ?Sized + 'a> {
     lock: &'a AtomicBool,
+    cpu: &'a AtomicU8,
     data: &'a mut T,
 }
 
@@ -34,7 +40,7 @@ impl<T> SpinMutex<T> {
         SpinMutex {
             lock: AtomicBool::new(false),
             data: UnsafeCell::new(data),
-            cpu: AtomicU8::new(0),
+            cpu: AtomicU8::new(NO_CPU),
         }
     }
 
@@ -70,6 +76,9 @@ impl<T: ?Sized> SpinMutex<T> {
         while self.lock.compare_and_swap(false, true, Ordering::Acquire) != false {
             // If the update was successful, load should return true
             while self.lock.load(Ordering::Relaxed) {
+                // The holder may be waiting for this CPU to flush its TLB (see flush_page_dir)
+                handle_tlb_flush();
+
                 // Once this is the case, relax the CPU
                 cpu_relax();
             }
@@ -84,19 +93,23 @@ impl<T: ?Sized> SpinMutex<T> {
         self.obtain_lock();
         SpinMutexGuard {
             lock: &self.lock,
+            cpu: &self.cpu,
             data: unsafe { &mut *self.data.get() },
         }
     }
 
     pub unsafe fn force_unlock(&self) {
+        self.cpu.store(NO_CPU, Ordering::Relaxed);
         self.lock.store(false, Ordering::Release);
         pop_cli();
     }
 
     pub fn try_lock(&self) -> Option<SpinMutexGuard<T>> {
         if self.lock.compare_and_swap(false, true, Ordering::Acquire) == false {
+            self.cpu.store(get_current_cpu_id(), Ordering::Relaxed);
             Some(SpinMutexGuard {
                 lock: &self.lock,
+                cpu: &self.cpu,
                 data: unsafe { &mut *self.data.get() },
             })
         } else {
@@ -122,6 +135,13 @@ impl<T: ?Sized + Default> Default for SpinMutex<T> {
     }
 }
 
+impl<'a, T: ?Sized> SpinMutexGuard<'a, T> {
+    /// Whether the guard holds the lock of mutex
+    pub fn belongs_to<U: ?Sized>(&self, mutex: &SpinMutex<U>) -> bool {
+        core::ptr::eq(self.lock, &mutex.lock)
+    }
+}
+
 impl<'a, T: ?Sized> Deref for SpinMutexGuard<'a, T> {
     type Target = T;
     fn deref<'b>(&'b self) -> &'b T {
@@ -138,6 +158,7 @@ impl<'a, T: ?Sized> DerefMut for SpinMutexGuard<'a, T> {
 impl<'a, T: ?Sized> Drop for SpinMutexGuard<'a, T> {
     fn drop(&mut self) {
         if self.lock.load(Ordering::Relaxed) == true {
+            self.cpu.store(NO_CPU, Ordering::Relaxed);
             self.lock.store(false, Ordering::Release);
             pop_cli();
         }
@@ -14,3 +14,11 @@ pub enum PrivilegeLevel {
 
 pub type ShortSegmentDescriptor = u64;
 pub type LongSegmentDescriptor = u128;
+
+// ********** Model Specific Registers **********
+
+pub const MSR_EFER: u32 = 0xC0000080; // Extended Feature Enable Register
+pub const EFER_NXE: u64 = 1 << 11; // No-Execute Enable
+
+pub const CPUID_EXTENDED_FEATURES: usize = 0x80000001;
+pub const CPUID_NX_FLAG_BIT: usize = 1 << 20;
@@ -203,6 +203,37 @@ pub fn stosb(address: usize, value: u8, length: usize) {
     }
 }
 
+// ************ Model Specific Registers ************
+
+#[inline]
+pub fn read_msr(msr: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!(
+            "rdmsr",
+            in("ecx") msr,
+            out("eax") low,
+            out("edx") high,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    (high as u64) << 32 | low as u64
+}
+
+#[inline]
+pub fn write_msr(msr: u32, value: u64) {
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
 // ************ Multiprocessing ************
 
 #[inline]
@@ -0,0 +1,65 @@
+function usage() {
+    cat <<USAGE
+    Usage: $0 [--iterations N]
+
+    Boots BuzzOS with the crashtest program as init, which keeps rewriting files, and kills
+    QEMU at a random point. The file system image is then checked with fsck. Every boot after
+    the first one also recovers the log left by the previous run.
+
+    Options:
+        --iterations: number of times QEMU is killed (default 20)
+USAGE
+    exit 1
+}
+
+# Calculate Root Dir
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" &> /dev/null && pwd)"
+cd "${SCRIPT_DIR}/.."
+ROOT_DIR="$(pwd)"
+
+ITERATIONS=20
+
+while [[ $# -gt 0 ]]; do
+    case $1 in
+    --iterations)
+        ITERATIONS=$2
+        shift 2
+        ;;
+    -h | --help)
+        usage # run usage function on help
+        ;;
+    *)
+        usage # run usage function if wrong argument provided
+        ;;
+    esac
+done
+
+QEMU="qemu-system-i386"
+QEMU_OPTIONS="-nographic -smp 1 -M pc-i440fx-6.1 -no-shutdown -no-reboot -m 512"
+QEMU_STORAGE_DEVICE="-drive file=build/crash.img,index=1,media=disk,format=raw -drive file=build/buzz.img,index=0,media=disk,format=raw"
+
+# Same user programs, but booting into the crash test instead of the shell
+rm -rf build/crash
+mkdir -p build/crash
+cp build/user/* build/crash
+cp build/user/crashtest build/crash/init
+
+cd tools
+cargo build --quiet || exit 1
+../target/debug/mkfs ../build/crash.img ../build/crash || exit 1
+cd "${ROOT_DIR}"
+
+for ((i = 1; i <= ITERATIONS; i++)); do
+    # Leave enough time to boot, then stop at a random point of the write loop
+    DELAY="$((RANDOM % 4 + 2)).$((RANDOM % 10))"
+    echo "[CRASH TEST] Run ${i}/${ITERATIONS}, killing QEMU after ${DELAY}s"
+
+    timeout -s KILL "${DELAY}" ${QEMU} ${QEMU_STORAGE_DEVICE} ${QEMU_OPTIONS} > build/crash.log 2>&1
+
+    if ! target/debug/fsck build/crash.img; then
+        echo "[CRASH TEST] File system is inconsistent after run ${i} (see build/crash.log)"
+        exit 1
+    fi
+done
+
+echo "[CRASH TEST] File system survived ${ITERATIONS} crashes"
@@ -0,0 +1,80 @@
+function usage() {
+    cat <<USAGE
+    Usage: $0 [--timeout SECONDS]
+
+    Boots BuzzOS with the testrunner program as init, which runs every test program and prints
+    a summary once they are done. Fails if any test exits with a non-zero code, or if the
+    summary is not printed in time, as happens when the Kernel panics or a test hangs.
+
+    Options:
+        --timeout: seconds to wait for the tests to finish (default 300)
+USAGE
+    exit 1
+}
+
+# Calculate Root Dir
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" &> /dev/null && pwd)"
+cd "${SCRIPT_DIR}/.."
+ROOT_DIR="$(pwd)"
+
+TIMEOUT=300
+
+while [[ $# -gt 0 ]]; do
+    case $1 in
+    --timeout)
+        TIMEOUT=$2
+        shift 2
+        ;;
+    -h | --help)
+        usage # run usage function on help
+        ;;
+    *)
+        usage # run usage function if wrong argument provided
+        ;;
+    esac
+done
+
+QEMU="qemu-system-i386"
+QEMU_OPTIONS="-nographic -smp 4 -M pc-i440fx-6.1 -no-shutdown -no-reboot -m 512"
+QEMU_STORAGE_DEVICE="-drive file=build/test.img,index=1,media=disk,format=raw -drive file=build/buzz.img,index=0,media=disk,format=raw"
+
+# Same user programs, but booting into the test runner instead of the shell
+rm -rf build/test
+mkdir -p build/test
+cp build/user/* build/test
+cp build/user/testrunner build/test/init
+
+cd tools
+cargo build --quiet || exit 1
+../target/debug/mkfs ../build/test.img ../build/test || exit 1
+cd "${ROOT_DIR}"
+
+${QEMU} ${QEMU_STORAGE_DEVICE} ${QEMU_OPTIONS} > build/test.log 2>&1 &
+QEMU_PID=$!
+
+# Init never exits, so QEMU is stopped once the summary shows up
+for ((i = 0; i < TIMEOUT; i++)); do
+    sleep 1
+
+    if grep -q "\[TESTRUNNER\] All tests passed" build/test.log; then
+        kill "${QEMU_PID}"
+        echo "[TEST] All tests passed"
+        exit 0
+    fi
+
+    if grep -q "\[TESTRUNNER\] [0-9]* tests failed" build/test.log; then
+        kill "${QEMU_PID}"
+        grep "\[TESTRUNNER\]" build/test.log
+        echo "[TEST] Some tests failed (see build/test.log)"
+        exit 1
+    fi
+
+    if ! kill -0 "${QEMU_PID}" 2> /dev/null; then
+        echo "[TEST] QEMU stopped before the tests finished (see build/test.log)"
+        exit 1
+    fi
+done
+
+kill "${QEMU_PID}"
+echo "[TEST] Tests did not finish within ${TIMEOUT}s (see build/test.log)"
+exit 1
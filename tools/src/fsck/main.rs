@@ -0,0 +1,334 @@
+#![allow(dead_code)]
+use std::{collections::HashMap, env, fs, mem::size_of, process::exit};
+
+#[path = "../mkfs/defs.rs"]
+mod defs;
+use defs::*;
+
+// Checks that a file system image is consistent, as the kernel would see it after mounting it.
+// That is, a committed transaction left in the log is replayed first, then:
+//  - Every block used by an inode is a data block, marked in the bitmap and used only once
+//  - Every data block marked in the bitmap is used by an inode (no leaks)
+//  - Every directory entry points to an allocated inode, and every inode is reachable from root
+//  - The number of links of each inode matches the number of entries pointing to it
+//  - The size of each file matches the blocks allocated to it
+
+struct Image {
+    data: Vec<u8>,
+    errors: u32,
+}
+
+impl Image {
+    fn read_sector(&self, sector: u32) -> &[u8] {
+        let start = (sector * BLOCK_SIZE) as usize;
+        &self.data[start..(start + BLOCK_SIZE as usize)]
+    }
+
+    fn read_u32(&self, sector: u32, index: usize) -> u32 {
+        let offset = index * size_of::<u32>();
+        let bytes = &self.read_sector(sector)[offset..(offset + size_of::<u32>())];
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_sector(&mut self, sector: u32, data: &[u8]) {
+        let start = (sector * BLOCK_SIZE) as usize;
+        self.data[start..(start + BLOCK_SIZE as usize)].copy_from_slice(data);
+    }
+
+    fn read_inode(&self, inode_number: u32) -> INode {
+        let block_index = inode_number / INODE_PER_BLOCK + SUPER_BLOCK.inode_start_address;
+        let inode_start_address = ((inode_number % INODE_PER_BLOCK) * INODE_SIZE) as usize;
+        let data = &self.read_sector(block_index)[inode_start_address..];
+
+        let mut inode = INode {
+            _type: match data[0] {
+                0 => INodeType::FREE,
+                1 => INodeType::FILE,
+                2 => INodeType::DIRECTORY,
+                _type => {
+                    println!("[FSCK] Invalid type {} for inode {}", _type, inode_number);
+                    INodeType::FREE
+                }
+            },
+            major: data[1],
+            minor: data[2],
+            number_links: data[3],
+            size: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+            ..Default::default()
+        };
+
+        for (index, address) in inode.data.iter_mut().enumerate() {
+            let offset = 8 + index * size_of::<u32>();
+            *address = u32::from_le_bytes(data[offset..(offset + 4)].try_into().unwrap());
+        }
+
+        inode
+    }
+
+    fn is_block_used(&self, block_number: u32) -> bool {
+        let bitmap = self.read_sector(SUPER_BLOCK.bitmap_start_address);
+        bitmap[block_number as usize / 8] & (1 << (block_number % 8)) != 0
+    }
+
+    fn error(&mut self, message: String) {
+        println!("[FSCK] {}", message);
+        self.errors += 1;
+    }
+}
+
+// Install the blocks of a committed transaction, just like recover_log does on mount
+fn replay_log(image: &mut Image) {
+    let header = SUPER_BLOCK.log_start_address;
+    let count = image.read_u32(header, 0);
+
+    if count >= NUMBER_LOGS {
+        image.error(format!("Log header has an invalid count {}", count));
+        return;
+    }
+
+    for i in 0..count {
+        let destination = image.read_u32(header, 1 + i as usize);
+        if !(SUPER_BLOCK.inode_start_address..FILE_SYSTEM_SIZE).contains(&destination) {
+            image.error(format!(
+                "Log entry {} points to invalid block {}",
+                i, destination
+            ));
+            continue;
+        }
+
+        let data = image.read_sector(header + 1 + i).to_vec();
+        image.write_sector(destination, &data);
+    }
+
+    println!("[FSCK] Replayed {} block(s) from the log", count);
+}
+
+// Read the addresses held by an indirect block. A missing or invalid block holds no addresses.
+fn read_addresses(image: &Image, block: u32) -> Vec<u32> {
+    if !(NUMBER_META_BLOCKS..FILE_SYSTEM_SIZE).contains(&block) {
+        return vec![0; INDIRECT_DATA_ADDRESS_SIZE];
+    }
+
+    (0..INDIRECT_DATA_ADDRESS_SIZE)
+        .map(|index| image.read_u32(block, index))
+        .collect()
+}
+
+// List data blocks of an inode, in file order (zero where a block is missing), along with the
+// indirect blocks that hold their addresses.
+fn get_inode_blocks(image: &Image, inode: &INode) -> (Vec<u32>, Vec<u32>) {
+    let mut blocks: Vec<u32> = inode.data[..DIRECT_DATA_ADDRESS_SIZE].to_vec();
+    let mut address_blocks = vec![
+        inode.data[INDIRECT_INDEX],
+        inode.data[DOUBLE_INDIRECT_INDEX],
+    ];
+
+    blocks.extend(read_addresses(image, inode.data[INDIRECT_INDEX]));
+
+    for indirect in read_addresses(image, inode.data[DOUBLE_INDIRECT_INDEX]) {
+        blocks.extend(read_addresses(image, indirect));
+        address_blocks.push(indirect);
+    }
+
+    address_blocks.retain(|&block| block != 0);
+    (blocks, address_blocks)
+}
+
+fn read_directory(image: &Image, inode: &INode) -> Vec<(u32, String)> {
+    let entry_size = size_of::<DirectoryEntry>();
+    let (blocks, _) = get_inode_blocks(image, inode);
+    let mut entries = Vec::new();
+
+    for offset in (0..inode.size as usize).step_by(entry_size) {
+        let block = blocks[offset / BLOCK_SIZE as usize];
+        let start = offset % BLOCK_SIZE as usize;
+        let data = &image.read_sector(block)[start..(start + entry_size)];
+
+        let inode_number = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        let name = data[4..]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| byte as char)
+            .collect();
+
+        entries.push((inode_number, name));
+    }
+
+    entries
+}
+
+// Check that a block used by an inode is a data block, marked in the bitmap and not used by any
+// other inode.
+fn claim_block(image: &mut Image, owners: &mut HashMap<u32, u32>, inode_number: u32, block: u32) {
+    if !(NUMBER_META_BLOCKS..FILE_SYSTEM_SIZE).contains(&block) {
+        image.error(format!(
+            "Inode {} has invalid block {}",
+            inode_number, block
+        ));
+        return;
+    }
+
+    if !image.is_block_used(block) {
+        image.error(format!("Block {} of inode {} is free", block, inode_number));
+    }
+
+    if let Some(owner) = owners.insert(block, inode_number) {
+        image.error(format!(
+            "Block {} used by inodes {} and {}",
+            block, owner, inode_number
+        ));
+    }
+}
+
+fn check_blocks(image: &mut Image) {
+    let mut owners: HashMap<u32, u32> = HashMap::new();
+
+    for inode_number in 1..NUMBER_INODES {
+        let inode = image.read_inode(inode_number);
+        if inode._type == INodeType::FREE {
+            continue;
+        }
+
+        let (blocks, address_blocks) = get_inode_blocks(image, &inode);
+        let used_blocks = inode.size.div_ceil(BLOCK_SIZE) as usize;
+
+        if used_blocks > MAX_FILE_BLOCK as usize {
+            image.error(format!("Inode {} is too big", inode_number));
+            continue;
+        }
+
+        // Missing indirect blocks show up as holes in the data blocks they would address
+        for (index, &block) in blocks.iter().enumerate() {
+            if block == 0 {
+                if index < used_blocks {
+                    image.error(format!("Inode {} has a hole at {}", inode_number, index));
+                }
+                continue;
+            }
+
+            if index >= used_blocks {
+                image.error(format!(
+                    "Inode {} has block {} past its size",
+                    inode_number, block
+                ));
+            }
+
+            claim_block(image, &mut owners, inode_number, block);
+        }
+
+        for block in address_blocks {
+            claim_block(image, &mut owners, inode_number, block);
+        }
+    }
+
+    for block in NUMBER_META_BLOCKS..FILE_SYSTEM_SIZE {
+        if image.is_block_used(block) && !owners.contains_key(&block) {
+            image.error(format!(
+                "Block {} is marked as used but not referenced",
+                block
+            ));
+        }
+    }
+}
+
+fn check_directories(image: &mut Image) {
+    let mut references: HashMap<u32, u32> = HashMap::new();
+    let mut pending = vec![(1, 1)]; // Directories to visit, along with their parent
+    let mut visited = vec![false; NUMBER_INODES as usize];
+    visited[1] = true;
+
+    if image.read_inode(1)._type != INodeType::DIRECTORY {
+        image.error("Root is not a directory".to_string());
+        return;
+    }
+
+    while let Some((directory, parent)) = pending.pop() {
+        let inode = image.read_inode(directory);
+
+        for (inode_number, name) in read_directory(image, &inode) {
+            if inode_number != 0 && name == "." && inode_number != directory {
+                image.error(format!("Entry . of inode {} is wrong", directory));
+            }
+
+            if inode_number != 0 && name == ".." && inode_number != parent {
+                image.error(format!("Entry .. of inode {} is wrong", directory));
+            }
+
+            if inode_number == 0 || name == "." || name == ".." {
+                continue;
+            }
+
+            if inode_number >= NUMBER_INODES {
+                image.error(format!(
+                    "Entry {} points to invalid inode {}",
+                    name, inode_number
+                ));
+                continue;
+            }
+
+            let entry = image.read_inode(inode_number);
+            if entry._type == INodeType::FREE {
+                image.error(format!(
+                    "Entry {} points to free inode {}",
+                    name, inode_number
+                ));
+                continue;
+            }
+
+            *references.entry(inode_number).or_default() += 1;
+
+            if entry._type == INodeType::DIRECTORY && !visited[inode_number as usize] {
+                pending.push((inode_number, directory));
+            }
+
+            visited[inode_number as usize] = true;
+        }
+    }
+
+    for inode_number in 2..NUMBER_INODES {
+        let inode = image.read_inode(inode_number);
+        if inode._type == INodeType::FREE {
+            continue;
+        }
+
+        if !visited[inode_number as usize] {
+            image.error(format!("Inode {} is not reachable from root", inode_number));
+            continue;
+        }
+
+        let count = references.get(&inode_number).copied().unwrap_or(0);
+        if inode.number_links as u32 != count {
+            image.error(format!(
+                "Inode {} has {} link(s) but {} entries",
+                inode_number, inode.number_links, count
+            ));
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("fsck requires the image of the file system...");
+        exit(2);
+    }
+
+    let data = fs::read(&args[1]).expect("Failed to read image file");
+    if data.len() < (FILE_SYSTEM_SIZE * BLOCK_SIZE) as usize {
+        eprintln!("[FSCK] Image is smaller than the file system");
+        exit(2);
+    }
+
+    let mut image = Image { data, errors: 0 };
+
+    replay_log(&mut image);
+    check_blocks(&mut image);
+    check_directories(&mut image);
+
+    if image.errors > 0 {
+        println!("[FSCK] Found {} error(s)", image.errors);
+        exit(1);
+    }
+
+    println!("[FSCK] File system is consistent");
+}
@@ -31,9 +31,10 @@ pub struct INode {
     pub minor: u8,
     pub number_links: u8,
     pub size: u32,
-    // Data is broken into 12 direct blocks + 1 indirect block, just like it is done in memory
-    // with paging, that is, multiple levels of redirection before you get to the data.
-    pub data: [u32; DIRECT_DATA_ADDRESS_SIZE + 1],
+    // Data is broken into 12 direct blocks + 1 indirect block + 1 double-indirect block, just
+    // like it is done in memory with paging, that is, multiple levels of redirection before you
+    // get to the data.
+    pub data: [u32; DIRECT_DATA_ADDRESS_SIZE + 2],
 }
 
 #[repr(C)]
@@ -50,12 +51,17 @@ pub const BLOCK_SIZE: u32 = 512;
 pub const FILE_SYSTEM_SIZE: u32 = 1000;
 pub const NUMBER_META_BLOCKS: u32 = 2 + NUMBER_LOGS + NUMBER_INODE_BLOCKS + NUMBER_BITMAPS;
 pub const NUMBER_BLOCKS: u32 = FILE_SYSTEM_SIZE - NUMBER_META_BLOCKS;
-pub const MAX_FILE_BLOCK: u32 = (DIRECT_DATA_ADDRESS_SIZE + INDIRECT_DATA_ADDRESS_SIZE) as u32;
+pub const MAX_FILE_BLOCK: u32 = (DIRECT_DATA_ADDRESS_SIZE
+    + INDIRECT_DATA_ADDRESS_SIZE
+    + INDIRECT_DATA_ADDRESS_SIZE * INDIRECT_DATA_ADDRESS_SIZE)
+    as u32;
 
 // INodes
 pub const NUMBER_INODES: u32 = 200;
 pub const DIRECT_DATA_ADDRESS_SIZE: usize = 12;
 pub const INDIRECT_DATA_ADDRESS_SIZE: usize = BLOCK_SIZE as usize / size_of::<u32>();
+pub const INDIRECT_INDEX: usize = DIRECT_DATA_ADDRESS_SIZE; // Block of direct addresses
+pub const DOUBLE_INDIRECT_INDEX: usize = DIRECT_DATA_ADDRESS_SIZE + 1; // Block of indirect blocks
 pub const INODE_SIZE: u32 = size_of::<INode>() as u32;
 pub const INODE_PER_BLOCK: u32 = BLOCK_SIZE / INODE_SIZE;
 pub const NUMBER_INODE_BLOCKS: u32 = NUMBER_INODES / INODE_PER_BLOCK + 1; // Number of blocks required to house all inodes
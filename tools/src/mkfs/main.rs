@@ -100,8 +100,7 @@ fn get_direct_block(inode: &mut INode, block_number: u32) -> Option<u32> {
 
     // Block has not been allocated yet
     if current_block_value == 0 {
-        inode.data[block_number as usize] = NEXT_FREE_DATA_BLOCK.load(Ordering::Relaxed);
-        NEXT_FREE_DATA_BLOCK.fetch_add(1, Ordering::Relaxed);
+        inode.data[block_number as usize] = allocate_data_block();
         return Some(inode.data[block_number as usize]);
     }
 
@@ -109,61 +108,84 @@ fn get_direct_block(inode: &mut INode, block_number: u32) -> Option<u32> {
     Some(current_block_value)
 }
 
+fn allocate_data_block() -> u32 {
+    NEXT_FREE_DATA_BLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
+// Returns the block stored at index of an indirect block, allocating it if needed
+fn get_block_entry(indirect_block: u32, index: usize) -> u32 {
+    let mut indirect_block_data = read_sector(indirect_block);
+    let offset = index * size_of::<u32>();
+    let entry = &indirect_block_data[offset..(offset + size_of::<u32>())];
+    let entry = u32::from_le_bytes(entry.try_into().unwrap());
+
+    // Block has been allocated
+    if entry != 0 {
+        return entry;
+    }
+
+    // Allocate new block in the indirect block, and save it
+    let entry = allocate_data_block();
+    indirect_block_data[offset..(offset + size_of::<u32>())].copy_from_slice(&entry.to_le_bytes());
+    write_sector(indirect_block, indirect_block_data.to_vec());
+
+    entry
+}
+
 fn get_indirect_block(inode: &mut INode, block_number: u32) -> Option<u32> {
-    // Requested block is direct
-    if block_number < DIRECT_DATA_ADDRESS_SIZE as u32 {
+    // Requested block is direct or double-indirect
+    let indirect_block_number = (block_number as usize).checked_sub(DIRECT_DATA_ADDRESS_SIZE)?;
+    if indirect_block_number >= INDIRECT_DATA_ADDRESS_SIZE {
         return None;
     }
 
-    let mut current_indirect_value = inode.data[DIRECT_DATA_ADDRESS_SIZE];
-
     // Block has not been allocated yet
-    if current_indirect_value == 0 {
-        inode.data[DIRECT_DATA_ADDRESS_SIZE] = NEXT_FREE_DATA_BLOCK.load(Ordering::Relaxed);
-        NEXT_FREE_DATA_BLOCK.fetch_add(1, Ordering::Relaxed);
-        current_indirect_value = inode.data[DIRECT_DATA_ADDRESS_SIZE];
+    if inode.data[INDIRECT_INDEX] == 0 {
+        inode.data[INDIRECT_INDEX] = allocate_data_block();
     }
 
-    let mut indirect_block_data = read_sector(current_indirect_value);
-    let indirect_block_number = block_number as usize - DIRECT_DATA_ADDRESS_SIZE;
-    let indirect_block_index = indirect_block_number * std::mem::size_of::<u32>();
-
-    // Allocate new block in the indirect data block
-    if indirect_block_data[indirect_block_index] == 0 {
-        indirect_block_data[indirect_block_index..(indirect_block_index + 4)].copy_from_slice(
-            NEXT_FREE_DATA_BLOCK
-                .load(Ordering::Relaxed)
-                .to_le_bytes()
-                .as_ref(),
-        );
-
-        // Save indirect block
-        write_sector(
-            inode.data[DIRECT_DATA_ADDRESS_SIZE],
-            indirect_block_data.to_vec(),
-        );
+    Some(get_block_entry(
+        inode.data[INDIRECT_INDEX],
+        indirect_block_number,
+    ))
+}
 
-        NEXT_FREE_DATA_BLOCK.fetch_add(1, Ordering::Relaxed);
+// Double-indirect blocks hold the addresses of indirect blocks, each holding the addresses of
+// INDIRECT_DATA_ADDRESS_SIZE data blocks.
+fn get_double_indirect_block(inode: &mut INode, block_number: u32) -> Option<u32> {
+    // Requested block is direct or indirect
+    let double_block_number = (block_number as usize)
+        .checked_sub(DIRECT_DATA_ADDRESS_SIZE + INDIRECT_DATA_ADDRESS_SIZE)?;
+    if double_block_number >= INDIRECT_DATA_ADDRESS_SIZE * INDIRECT_DATA_ADDRESS_SIZE {
+        return None;
+    }
 
-        return Some(NEXT_FREE_DATA_BLOCK.load(Ordering::Relaxed) - 1);
+    // Block has not been allocated yet
+    if inode.data[DOUBLE_INDIRECT_INDEX] == 0 {
+        inode.data[DOUBLE_INDIRECT_INDEX] = allocate_data_block();
     }
 
-    let indirect_block = &indirect_block_data[indirect_block_index..(indirect_block_index + 4)];
-    let indirect_block = u32::from_le_bytes(indirect_block.try_into().unwrap());
-    Some(indirect_block)
+    let indirect_block = get_block_entry(
+        inode.data[DOUBLE_INDIRECT_INDEX],
+        double_block_number / INDIRECT_DATA_ADDRESS_SIZE,
+    );
+
+    Some(get_block_entry(
+        indirect_block,
+        double_block_number % INDIRECT_DATA_ADDRESS_SIZE,
+    ))
 }
 
 fn get_inode_data_block(inode: &mut INode, block_number: u32) -> u32 {
-    let Some(block) = get_direct_block(inode, block_number) else {
-        return get_indirect_block(inode, block_number).unwrap()
-    };
-
-    return block;
+    get_direct_block(inode, block_number)
+        .or_else(|| get_indirect_block(inode, block_number))
+        .or_else(|| get_double_indirect_block(inode, block_number))
+        .unwrap()
 }
 
 // This function writes the inode data into the data region. The idea is to break data into
-// blocks of BLOCK_SIZE, writing it first to the direct data addresses (12) and then the indirect
-// blocks (128).
+// blocks of BLOCK_SIZE, writing it first to the direct data addresses (12), then the indirect
+// blocks (128), and finally the double-indirect blocks (128 * 128).
 fn append_inode(inode_number: u32, mut data: *const u8, mut length: u32) {
     let mut inode = read_inode(inode_number);
     let mut size = inode.size;
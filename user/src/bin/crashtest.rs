@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+use user::libs::system_call::{close, open, print_message, write, O_CREATE, O_RDWR, O_TRUNC};
+
+const FILE_NAMES: [&str; 4] = ["/crash0", "/crash1", "/crash2", "/crash3"];
+const MAX_FILE_SIZE: usize = 12 * 512; // Files stay within the direct blocks of an inode
+const CHUNK_SIZE: usize = 1024;
+
+/// Rewrites a small set of files forever, with varying sizes. Used by scripts/crash_test.sh,
+/// which kills the machine at a random point and checks the file system image afterwards.
+#[no_mangle]
+pub extern "C" fn _start() {
+    let mut iteration: usize = 0;
+
+    loop {
+        let name = FILE_NAMES[iteration % FILE_NAMES.len()];
+        let size = (iteration * 1237) % MAX_FILE_SIZE + 1;
+
+        let Ok(descriptor) = open(name, O_CREATE | O_RDWR | O_TRUNC) else {
+            print_message("[CRASHTEST] Failed to open file");
+            loop {}
+        };
+
+        let buffer = [iteration as u8; CHUNK_SIZE];
+        let mut written = 0;
+        while written < size {
+            let count = core::cmp::min(size - written, CHUNK_SIZE);
+            if write(descriptor, &buffer[..count]) != Ok(count) {
+                print_message("[CRASHTEST] Failed to write file");
+                loop {}
+            }
+
+            written += count;
+        }
+
+        let _ = close(descriptor);
+        iteration += 1;
+    }
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use user::libs::system_call::{exit, fork, frame_stats, print_message, wait};
+
+const ITERATIONS: usize = 10000;
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+/// Forks a child that exits right away and reaps it
+fn fork_and_wait() {
+    match fork() {
+        Ok(0) => exit(0),
+        Ok(_) => {}
+        Err(_) => fail("[FORKTEST] Failed to fork"),
+    }
+
+    let mut status = 0;
+    if wait(&mut status).is_err() || status != 0 {
+        fail("[FORKTEST] Child failed");
+    }
+}
+
+/// Forks and reaps a child over and over. Everything used by a child (user pages, page tables,
+/// page directory and kernel stack) must be freed once it is reaped, so the number of free frames
+/// must be the same at the end. The slab caches of the Kernel heap keep an empty slab around
+/// instead of freeing its page, so a first child is forked before counting, to fill them.
+#[no_mangle]
+pub extern "C" fn _start() {
+    fork_and_wait();
+
+    let Ok(before) = frame_stats() else {
+        fail("[FORKTEST] Failed to read frame stats");
+    };
+
+    for _ in 0..ITERATIONS {
+        fork_and_wait();
+    }
+
+    let Ok(after) = frame_stats() else {
+        fail("[FORKTEST] Failed to read frame stats");
+    };
+
+    if after.free != before.free {
+        fail(&format!(
+            "[FORKTEST] Free frames went from {} to {}",
+            before.free, after.free
+        ));
+    }
+
+    print_message("[FORKTEST] Passed");
+    exit(0);
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+
+use user::libs::system_call::{
+    close, exit, mmap, munmap, open, print_message, write, MAP_ANONYMOUS, O_CREATE, O_RDONLY,
+    O_TRUNC, O_WRONLY, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const FILE_NAME: &str = "/mmapfile";
+const FILE_SIZE: usize = 3 * PAGE_SIZE + 100; // The last page is only partly used by the file
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+fn get_file_byte(offset: usize) -> u8 {
+    (offset % 251) as u8
+}
+
+/// Maps zeroed memory, writes into it and unmaps part of it.
+fn test_anonymous_mapping() {
+    let Ok(address) = mmap(3 * PAGE_SIZE, MAP_ANONYMOUS | PROT_WRITE, 0, 0) else {
+        fail("[MMAPTEST] Failed to map anonymous memory");
+    };
+
+    let memory = unsafe { core::slice::from_raw_parts_mut(address as *mut u8, 3 * PAGE_SIZE) };
+    if memory.iter().any(|&byte| byte != 0) {
+        fail("[MMAPTEST] Anonymous memory is not zeroed");
+    }
+
+    memory.fill(0xAB);
+    if memory.iter().any(|&byte| byte != 0xAB) {
+        fail("[MMAPTEST] Anonymous memory lost a write");
+    }
+
+    // Only the middle page goes away, the pages around it keep their content
+    if munmap(address + PAGE_SIZE, PAGE_SIZE).is_err() {
+        fail("[MMAPTEST] Failed to unmap the middle page");
+    }
+
+    if memory[0] != 0xAB || memory[3 * PAGE_SIZE - 1] != 0xAB {
+        fail("[MMAPTEST] Unmapping a page changed its neighbours");
+    }
+
+    if munmap(address, 3 * PAGE_SIZE).is_err() {
+        fail("[MMAPTEST] Failed to unmap anonymous memory");
+    }
+}
+
+/// Maps a file and checks that its content shows up in memory, starting at an offset
+fn test_file_mapping() {
+    let Ok(descriptor) = open(FILE_NAME, O_CREATE | O_WRONLY | O_TRUNC) else {
+        fail("[MMAPTEST] Failed to create file");
+    };
+
+    // Written in chunks, since the stack is small
+    let mut chunk = [0_u8; 256];
+    let mut offset = 0;
+    while offset < FILE_SIZE {
+        let length = core::cmp::min(chunk.len(), FILE_SIZE - offset);
+        for (index, byte) in chunk[..length].iter_mut().enumerate() {
+            *byte = get_file_byte(offset + index);
+        }
+
+        if write(descriptor, &chunk[..length]) != Ok(length) {
+            fail("[MMAPTEST] Failed to write file");
+        }
+
+        offset += length;
+    }
+
+    if close(descriptor).is_err() {
+        fail("[MMAPTEST] Failed to close file");
+    }
+
+    let Ok(descriptor) = open(FILE_NAME, O_RDONLY) else {
+        fail("[MMAPTEST] Failed to open file");
+    };
+
+    if mmap(PAGE_SIZE, PROT_WRITE, descriptor, 0).is_ok() {
+        fail("[MMAPTEST] File mapping cannot be writable");
+    }
+
+    let length = FILE_SIZE - PAGE_SIZE;
+    let Ok(address) = mmap(length, 0, descriptor, PAGE_SIZE) else {
+        fail("[MMAPTEST] Failed to map file");
+    };
+
+    // The mapping stays valid after the descriptor is closed
+    let _ = close(descriptor);
+
+    let memory = unsafe { core::slice::from_raw_parts(address as *const u8, 3 * PAGE_SIZE) };
+    for (index, &byte) in memory.iter().enumerate() {
+        let expected = if index < length {
+            get_file_byte(PAGE_SIZE + index)
+        } else {
+            0 // Past the end of the file
+        };
+
+        if byte != expected {
+            fail("[MMAPTEST] File mapping does not match the file");
+        }
+    }
+
+    if munmap(address, length).is_err() {
+        fail("[MMAPTEST] Failed to unmap file");
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start() {
+    test_anonymous_mapping();
+    test_file_mapping();
+
+    print_message("[MMAPTEST] Passed");
+    exit(0);
+}
@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+use user::libs::system_call::{close, exit, fork, pipe, print_message, read, wait, write};
+
+const MESSAGE: &[u8] = b"Hello from the other end of the pipe\n";
+const MESSAGE_COUNT: usize = 64; // Enough to fill the pipe, so that the producer has to wait
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+/// Connects a producer and a consumer process through a pipe, as a shell would for
+/// "producer | consumer". The consumer reads until the end of the file, which only comes once the
+/// producer has exited, and checks that every byte arrived in order.
+#[no_mangle]
+pub extern "C" fn _start() {
+    let Ok((read_end, write_end)) = pipe() else {
+        fail("[PIPETEST] Failed to create pipe");
+    };
+
+    match fork() {
+        Ok(0) => {
+            let _ = close(read_end);
+
+            for _ in 0..MESSAGE_COUNT {
+                if write(write_end, MESSAGE) != Ok(MESSAGE.len()) {
+                    fail("[PIPETEST] Failed to write pipe");
+                }
+            }
+
+            exit(0);
+        }
+        Ok(_) => {}
+        Err(_) => fail("[PIPETEST] Failed to fork"),
+    }
+
+    // The consumer must close its write end too, otherwise the end of the file never comes
+    let _ = close(write_end);
+
+    let mut buffer = [0; 100];
+    let mut total = 0;
+
+    loop {
+        let count = match read(read_end, &mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(_) => fail("[PIPETEST] Failed to read pipe"),
+        };
+
+        for (index, &byte) in buffer[..count].iter().enumerate() {
+            if byte != MESSAGE[(total + index) % MESSAGE.len()] {
+                fail("[PIPETEST] Data read does not match data written");
+            }
+        }
+
+        total += count;
+    }
+
+    if total != MESSAGE.len() * MESSAGE_COUNT {
+        fail("[PIPETEST] Pipe ended early");
+    }
+
+    let mut status = 0;
+    if wait(&mut status).is_err() || status != 0 {
+        fail("[PIPETEST] Producer failed");
+    }
+
+    print_message("[PIPETEST] Passed");
+    exit(0);
+}
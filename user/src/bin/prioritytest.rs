@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+use user::libs::errno::Errno;
+use user::libs::system_call::{
+    close, exit, fork, pipe, print_message, read, set_priority, wait, HIGHEST_PRIORITY,
+    LOWEST_PRIORITY,
+};
+
+const INIT_PID: usize = 0;
+const MISSING_PID: usize = 100000;
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+/// Forks a child that waits until its pipe is closed, and changes the priority of the child while
+/// it is alive. Processes can only change the priority of their own children, and only to a valid
+/// priority.
+#[no_mangle]
+pub extern "C" fn _start() {
+    let Ok((read_end, write_end)) = pipe() else {
+        fail("[PRIORITYTEST] Failed to create pipe");
+    };
+
+    let child = match fork() {
+        Ok(0) => {
+            let _ = close(write_end);
+            let mut buffer = [0; 1];
+            while read(read_end, &mut buffer) != Ok(0) {}
+            exit(0);
+        }
+        Ok(child) => child,
+        Err(_) => fail("[PRIORITYTEST] Failed to fork"),
+    };
+
+    let _ = close(read_end);
+
+    if set_priority(child, LOWEST_PRIORITY) != Ok(HIGHEST_PRIORITY) {
+        fail("[PRIORITYTEST] Child did not start with the highest priority");
+    }
+
+    if set_priority(child, HIGHEST_PRIORITY) != Ok(LOWEST_PRIORITY) {
+        fail("[PRIORITYTEST] Child priority was not changed");
+    }
+
+    if set_priority(child, LOWEST_PRIORITY + 1) != Err(Errno::InvalidArgument) {
+        fail("[PRIORITYTEST] Invalid priority was accepted");
+    }
+
+    if set_priority(INIT_PID, LOWEST_PRIORITY) != Err(Errno::NotPermitted) {
+        fail("[PRIORITYTEST] Priority of another process was changed");
+    }
+
+    if set_priority(MISSING_PID, LOWEST_PRIORITY) != Err(Errno::NoSuchProcess) {
+        fail("[PRIORITYTEST] Priority of a missing process was changed");
+    }
+
+    let _ = close(write_end);
+
+    let mut status = 0;
+    if wait(&mut status).is_err() || status != 0 {
+        fail("[PRIORITYTEST] Child failed");
+    }
+
+    print_message("[PRIORITYTEST] Passed");
+    exit(0);
+}
@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use user::libs::system_call::{
+    exit, print_message, process_stats, uptime, ProcessStats, NO_PARENT,
+};
+
+const INITIAL_CAPACITY: usize = 16;
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+/// Reads the stats of every process. The buffer grows until every process fits, as processes may
+/// be created between two reads.
+fn get_stats() -> Vec<ProcessStats> {
+    let mut stats = vec![ProcessStats::default(); INITIAL_CAPACITY];
+
+    loop {
+        let Ok(count) = process_stats(&mut stats) else {
+            fail("[PS] Failed to read process stats");
+        };
+
+        if count <= stats.len() {
+            stats.truncate(count);
+            return stats;
+        }
+
+        stats.resize(count * 2, ProcessStats::default());
+    }
+}
+
+/// Lists every process, with the timer ticks it has spent running in user mode (USER) and in the
+/// Kernel (SYS), and the uptime when it started (START).
+#[no_mangle]
+pub extern "C" fn _start() {
+    let stats = get_stats();
+
+    print_message(&format!("Uptime: {} ticks", uptime()));
+    print_message(&format!(
+        "{:>5} {:>5} {:<8} {:<16} {:>8} {:>6} {:>6} {:>8} {:>6}",
+        "PID", "PPID", "STATE", "NAME", "MEM", "USER", "SYS", "SWITCHES", "START"
+    ));
+
+    for process in stats.iter() {
+        let parent = match process.parent_pid {
+            NO_PARENT => String::from("-"),
+            parent_pid => parent_pid.to_string(),
+        };
+
+        print_message(&format!(
+            "{:>5} {:>5} {:<8} {:<16} {:>8} {:>6} {:>6} {:>8} {:>6}",
+            process.pid,
+            parent,
+            process.state(),
+            process.name(),
+            process.mem_size,
+            process.user_ticks,
+            process.kernel_ticks,
+            process.context_switches,
+            process.start_tick
+        ));
+    }
+
+    exit(0);
+}
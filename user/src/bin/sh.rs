@@ -3,6 +3,7 @@
 
 #[allow(unused_imports)]
 use user::libs::*;
+use user::libs::{arguments::Arguments, system_call::print_message};
 
 fn fib(count: usize) -> usize {
     if count == 0 {
@@ -16,6 +17,11 @@ fn fib(count: usize) -> usize {
 }
 
 #[no_mangle]
-pub extern "C" fn _start(argc: usize, argv: *const usize) {
+pub extern "C" fn _start(argc: usize, argv: *const *const u8) {
+    let arguments = unsafe { Arguments::new(argc, argv) };
+    for argument in arguments.iter() {
+        print_message(argument);
+    }
+
     unsafe { core::arch::asm!("mov eax, {}", in(reg) fib(30)) }
 }
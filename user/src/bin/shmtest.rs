@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+use user::libs::system_call::{
+    exit, fork, print_message, shm_attach, shm_create, shm_detach, wait,
+};
+
+const KEY: usize = 0x5348;
+const CHILDREN: usize = 8;
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+fn get_slots(address: usize) -> &'static mut [usize] {
+    unsafe { core::slice::from_raw_parts_mut(address as *mut usize, CHILDREN) }
+}
+
+/// Creates a shared memory segment and forks children that each write their own slot of it. Half
+/// of them use the segment inherited through fork, while the other half attach it again by key.
+/// The parent sees every write once the children exit.
+#[no_mangle]
+pub extern "C" fn _start() {
+    let Ok(address) = shm_create(KEY, CHILDREN * core::mem::size_of::<usize>()) else {
+        fail("[SHMTEST] Failed to create segment");
+    };
+
+    if shm_create(KEY, 1).is_ok() {
+        fail("[SHMTEST] Segment was created twice");
+    }
+
+    for child in 0..CHILDREN {
+        match fork() {
+            Ok(0) => {
+                let mut child_address = address;
+
+                if child % 2 == 1 {
+                    let Ok(new_address) = shm_attach(KEY) else {
+                        fail("[SHMTEST] Failed to attach segment");
+                    };
+
+                    child_address = new_address;
+                    let _ = shm_detach(address);
+                }
+
+                get_slots(child_address)[child] = child + 1;
+                exit(0);
+            }
+            Ok(_) => {}
+            Err(_) => fail("[SHMTEST] Failed to fork"),
+        }
+    }
+
+    for _ in 0..CHILDREN {
+        let mut status = 0;
+        if wait(&mut status).is_err() || status != 0 {
+            fail("[SHMTEST] Child failed");
+        }
+    }
+
+    for (child, &slot) in get_slots(address).iter().enumerate() {
+        if slot != child + 1 {
+            fail("[SHMTEST] Write of a child is missing");
+        }
+    }
+
+    // Once the last process detaches, the segment is gone
+    if shm_detach(address).is_err() || shm_attach(KEY).is_ok() {
+        fail("[SHMTEST] Segment outlived its last attachment");
+    }
+
+    print_message("[SHMTEST] Passed");
+    exit(0);
+}
@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use user::libs::system_call::{
+    close, exit, fork, heap_stats, pipe, print_message, wait, SlabStats, SLAB_CACHES,
+};
+
+const ITERATIONS: usize = 500;
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+fn get_stats() -> [SlabStats; SLAB_CACHES] {
+    let Ok(stats) = heap_stats() else {
+        fail("[SLABTEST] Failed to read heap stats");
+    };
+
+    stats
+}
+
+/// Creates and destroys a pipe and a process, which allocate (and free) Kernel objects of several
+/// sizes, such as processes, files and strings.
+fn churn_kernel_objects() {
+    let Ok((read_descriptor, write_descriptor)) = pipe() else {
+        fail("[SLABTEST] Failed to create pipe");
+    };
+
+    if close(read_descriptor).is_err() || close(write_descriptor).is_err() {
+        fail("[SLABTEST] Failed to close pipe");
+    }
+
+    match fork() {
+        Ok(0) => exit(0),
+        Ok(_) => {}
+        Err(_) => fail("[SLABTEST] Failed to fork"),
+    }
+
+    let mut status = 0;
+    if wait(&mut status).is_err() || status != 0 {
+        fail("[SLABTEST] Child failed");
+    }
+}
+
+/// Every Kernel object allocated while churning is freed once it is done, so each slab cache must
+/// end up with as many objects in use as it started with.
+#[no_mangle]
+pub extern "C" fn _start() {
+    // The first run may allocate objects that the Kernel keeps around, such as bigger lists
+    churn_kernel_objects();
+    let before = get_stats();
+
+    for _ in 0..ITERATIONS {
+        churn_kernel_objects();
+    }
+
+    let after = get_stats();
+
+    for (before, after) in before.iter().zip(after.iter()) {
+        if before.size != after.size || before.used != after.used {
+            fail(&format!(
+                "[SLABTEST] Cache of {} bytes went from {} to {} used objects",
+                before.size, before.used, after.used
+            ));
+        }
+    }
+
+    print_message("[SLABTEST] Passed");
+    exit(0);
+}
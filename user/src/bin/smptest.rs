@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user::libs::system_call::{exit, fork, print_message, shm_create, wait};
+
+const KEY: usize = 0x534D50;
+const CHILDREN: usize = 8;
+const INCREMENTS: usize = 100000;
+const PRIME_LIMIT: usize = 20000;
+const PRIMES_BELOW_LIMIT: i32 = 2262;
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+fn is_prime(number: usize) -> bool {
+    (2..number)
+        .take_while(|divisor| divisor * divisor <= number)
+        .all(|divisor| number % divisor != 0)
+}
+
+/// Deliberately slow, so that every child keeps its CPU busy for a while
+fn count_primes(limit: usize) -> i32 {
+    (2..limit).filter(|&number| is_prime(number)).count() as i32
+}
+
+/// Forks more CPU bound children than there are CPUs. Each one counts primes and exits with the
+/// count, while they all increment a counter in shared memory, so increments made by children
+/// running on different CPUs at the same time must all add up.
+#[no_mangle]
+pub extern "C" fn _start() {
+    let Ok(address) = shm_create(KEY, core::mem::size_of::<AtomicUsize>()) else {
+        fail("[SMPTEST] Failed to create segment");
+    };
+
+    let counter = unsafe { &*(address as *const AtomicUsize) };
+
+    for _ in 0..CHILDREN {
+        match fork() {
+            Ok(0) => {
+                for _ in 0..INCREMENTS {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+
+                exit(count_primes(PRIME_LIMIT));
+            }
+            Ok(_) => {}
+            Err(_) => fail("[SMPTEST] Failed to fork"),
+        }
+    }
+
+    for _ in 0..CHILDREN {
+        let mut status = 0;
+        if wait(&mut status).is_err() || status != PRIMES_BELOW_LIMIT {
+            fail("[SMPTEST] Child computed a wrong result");
+        }
+    }
+
+    if counter.load(Ordering::SeqCst) != CHILDREN * INCREMENTS {
+        fail("[SMPTEST] Increments were lost");
+    }
+
+    print_message("[SMPTEST] Passed");
+    exit(0);
+}
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+
+use user::libs::system_call::{exec, exit, fork, print_message, waitpid};
+
+const TESTS: [&str; 9] = [
+    "/pipetest",
+    "/forktest",
+    "/mmaptest",
+    "/shmtest",
+    "/wxtest",
+    "/slabtest",
+    "/smptest",
+    "/prioritytest",
+    "/threadtest",
+];
+
+/// Runs a test program in a child and returns its exit code
+fn run_test(path: &str) -> i32 {
+    let pid = match fork() {
+        Ok(0) => {
+            let error = exec(path, &[path]);
+            print_message(&format!(
+                "[TESTRUNNER] Failed to exec {}: {:?}",
+                path, error
+            ));
+            exit(1);
+        }
+        Ok(pid) => pid,
+        Err(_) => return -1,
+    };
+
+    let mut status = 0;
+    match waitpid(pid as isize, &mut status, 0) {
+        Ok(_) => status,
+        Err(_) => -1,
+    }
+}
+
+/// Runs every test program one after the other. Used by scripts/test.sh, which boots it as init
+/// and looks for the summary printed at the end. Init must never exit, so it waits forever once
+/// the tests are done.
+#[no_mangle]
+pub extern "C" fn _start() {
+    let mut failures = 0;
+
+    for path in TESTS {
+        let status = run_test(path);
+        if status != 0 {
+            print_message(&format!(
+                "[TESTRUNNER] {} failed with code {}",
+                path, status
+            ));
+            failures += 1;
+        }
+    }
+
+    if failures == 0 {
+        print_message("[TESTRUNNER] All tests passed");
+    } else {
+        print_message(&format!("[TESTRUNNER] {} tests failed", failures));
+    }
+
+    loop {}
+}
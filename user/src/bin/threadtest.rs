@@ -0,0 +1,207 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user::libs::arguments::Arguments;
+use user::libs::errno::Errno;
+use user::libs::system_call::{
+    close, exec, exit, fork, join, pipe, print_message, read, waitpid, write,
+};
+use user::libs::thread::{self, JoinHandle};
+
+const THREADS: usize = 8;
+const INCREMENTS: usize = 100000;
+const PRIME_LIMIT: usize = 20000;
+const PRIMES_BELOW_LIMIT: i32 = 2262;
+const MISSING_TID: usize = 100000;
+const MESSAGE: &[u8] = b"shared";
+const PROGRAM_PATH: &str = "/threadtest";
+const EXEC_ARGUMENT: &str = "exec";
+const EXEC_STATUS: i32 = 42;
+const FORK_STATUS: i32 = 7;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static READ_DESCRIPTOR: AtomicUsize = AtomicUsize::new(usize::MAX);
+static WRITE_DESCRIPTOR: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+fn is_prime(number: usize) -> bool {
+    (2..number)
+        .take_while(|divisor| divisor * divisor <= number)
+        .all(|divisor| number % divisor != 0)
+}
+
+/// Deliberately slow, so that every thread keeps its CPU busy for a while
+fn count_primes(limit: usize) -> i32 {
+    (2..limit).filter(|&number| is_prime(number)).count() as i32
+}
+
+/// Runs more CPU bound threads than there are CPUs. Each one counts primes and ends with the count,
+/// while they all increment the same counter, which lives in the memory they share.
+fn test_shared_memory() {
+    let mut handles: Vec<JoinHandle> = Vec::new();
+
+    for _ in 0..THREADS {
+        let handle = thread::spawn(|| {
+            for _ in 0..INCREMENTS {
+                COUNTER.fetch_add(1, Ordering::SeqCst);
+            }
+
+            count_primes(PRIME_LIMIT)
+        });
+
+        match handle {
+            Ok(handle) => handles.push(handle),
+            Err(_) => fail("[THREADTEST] Failed to spawn thread"),
+        }
+    }
+
+    for handle in handles {
+        if handle.join() != Ok(PRIMES_BELOW_LIMIT) {
+            fail("[THREADTEST] Thread computed a wrong result");
+        }
+    }
+
+    if COUNTER.load(Ordering::SeqCst) != THREADS * INCREMENTS {
+        fail("[THREADTEST] Increments were lost");
+    }
+}
+
+/// Descriptors opened by a thread belong to the whole process, so they outlive the thread
+fn test_shared_files() {
+    let handle = thread::spawn(|| match pipe() {
+        Ok((read_end, write_end)) => {
+            READ_DESCRIPTOR.store(read_end, Ordering::SeqCst);
+            WRITE_DESCRIPTOR.store(write_end, Ordering::SeqCst);
+            0
+        }
+        Err(_) => 1,
+    });
+
+    if handle.map(JoinHandle::join) != Ok(Ok(0)) {
+        fail("[THREADTEST] Thread failed to create pipe");
+    }
+
+    let read_end = READ_DESCRIPTOR.load(Ordering::SeqCst);
+    let write_end = WRITE_DESCRIPTOR.load(Ordering::SeqCst);
+    if write(write_end, MESSAGE) != Ok(MESSAGE.len()) {
+        fail("[THREADTEST] Pipe of the thread is not open");
+    }
+
+    let mut buffer = [0; MESSAGE.len()];
+    if read(read_end, &mut buffer) != Ok(MESSAGE.len()) || buffer != MESSAGE {
+        fail("[THREADTEST] Pipe lost the message");
+    }
+
+    let _ = close(read_end);
+    let _ = close(write_end);
+}
+
+/// Fork from a thread only copies that thread. The child belongs to the whole process, so another
+/// thread can wait for it.
+fn test_fork_from_thread() {
+    let handle = thread::spawn(|| match fork() {
+        Ok(0) => exit(FORK_STATUS),
+        Ok(pid) => pid as i32,
+        Err(_) => -1,
+    });
+
+    let pid = match handle.map(JoinHandle::join) {
+        Ok(Ok(pid)) if pid > 0 => pid as usize,
+        _ => fail("[THREADTEST] Thread failed to fork"),
+    };
+
+    let mut status = 0;
+    if waitpid(pid as isize, &mut status, 0) != Ok(pid) || status != FORK_STATUS {
+        fail("[THREADTEST] Child of a thread did not exit");
+    }
+}
+
+/// Sleeps on a pipe that nobody writes to, until exec ends the thread
+fn wait_on_pipe() -> i32 {
+    let mut buffer = [0; 1];
+    let _ = read(READ_DESCRIPTOR.load(Ordering::SeqCst), &mut buffer);
+    1
+}
+
+/// Runs this program again through exec, while one thread spins and another one sleeps
+fn exec_with_threads(exec_from_thread: bool) -> ! {
+    let Ok((read_end, write_end)) = pipe() else {
+        fail("[THREADTEST] Failed to create pipe");
+    };
+    READ_DESCRIPTOR.store(read_end, Ordering::SeqCst);
+    WRITE_DESCRIPTOR.store(write_end, Ordering::SeqCst);
+
+    let spinning = thread::spawn(|| loop {
+        core::hint::spin_loop();
+    });
+    if spinning.is_err() || thread::spawn(wait_on_pipe).is_err() {
+        fail("[THREADTEST] Failed to spawn thread");
+    }
+
+    if !exec_from_thread {
+        exec(PROGRAM_PATH, &[EXEC_ARGUMENT]);
+        fail("[THREADTEST] Exec failed");
+    }
+
+    // The leader sleeps as well, and is only woken up if exec fails
+    let handle = thread::spawn(|| {
+        exec(PROGRAM_PATH, &[EXEC_ARGUMENT]);
+        let _ = write(WRITE_DESCRIPTOR.load(Ordering::SeqCst), MESSAGE);
+        1
+    });
+
+    if handle.is_err() {
+        fail("[THREADTEST] Failed to spawn thread");
+    }
+
+    wait_on_pipe();
+    fail("[THREADTEST] Exec failed in a thread");
+}
+
+/// Exec ends every other thread of the process, and the new program keeps the pid of the process,
+/// whether exec is called by the leader or by another thread
+fn test_exec_with_threads() {
+    for exec_from_thread in [false, true] {
+        let pid = match fork() {
+            Ok(0) => exec_with_threads(exec_from_thread),
+            Ok(pid) => pid,
+            Err(_) => fail("[THREADTEST] Failed to fork"),
+        };
+
+        let mut status = 0;
+        if waitpid(pid as isize, &mut status, 0) != Ok(pid) || status != EXEC_STATUS {
+            fail("[THREADTEST] Exec did not replace a process with threads");
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start(argc: usize, argv: *const *const u8) {
+    // Run again by test_exec_with_threads, which only checks that exec got here
+    let arguments = unsafe { Arguments::new(argc, argv) };
+    if arguments.get(0) == Some(EXEC_ARGUMENT) {
+        exit(EXEC_STATUS);
+    }
+
+    test_shared_memory();
+    test_shared_files();
+    test_fork_from_thread();
+    test_exec_with_threads();
+
+    let mut status = 0;
+    if join(MISSING_TID, &mut status) != Err(Errno::NoChildProcess) {
+        fail("[THREADTEST] Joined a missing thread");
+    }
+
+    print_message("[THREADTEST] Passed");
+    exit(0);
+}
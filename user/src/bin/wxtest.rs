@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+use user::libs::system_call::{exit, fork, print_message, wait};
+
+const KILLED_EXIT_CODE: i32 = -1;
+const PATTERN: u32 = 0xDEADBEEF;
+
+static mut DATA: u32 = 0;
+
+fn fail(message: &str) -> ! {
+    print_message(message);
+    exit(1);
+}
+
+/// Forks a child that writes into the program code, which is mapped read-only, so the child must
+/// be killed instead of exiting on its own.
+fn test_write_to_code() {
+    match fork() {
+        Ok(0) => {
+            let code = _start as usize as *mut u32;
+            unsafe { code.write_volatile(PATTERN) };
+            exit(0);
+        }
+        Ok(_) => {}
+        Err(_) => fail("[WXTEST] Failed to fork"),
+    }
+
+    let mut status = 0;
+    if wait(&mut status).is_err() || status != KILLED_EXIT_CODE {
+        fail("[WXTEST] Write to the program code was allowed");
+    }
+}
+
+/// Writable segments, such as the data of the program, must stay writable
+fn test_write_to_data() {
+    unsafe {
+        let data = core::ptr::addr_of_mut!(DATA);
+        data.write_volatile(PATTERN);
+
+        if data.read_volatile() != PATTERN {
+            fail("[WXTEST] Program data lost a write");
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start() {
+    test_write_to_data();
+    test_write_to_code();
+
+    print_message("[WXTEST] Passed");
+    exit(0);
+}
@@ -0,0 +1,44 @@
+/// Arguments received by a program. The kernel places them on the stack before jumping to
+/// _start(argc, argv), where argv is a null-terminated array of null-terminated strings.
+pub struct Arguments {
+    argc: usize,
+    argv: *const *const u8,
+}
+
+impl Arguments {
+    /// # Safety
+    /// argc and argv must be the values received by _start, which point to the strings the kernel
+    /// copied at the top of the stack.
+    pub unsafe fn new(argc: usize, argv: *const *const u8) -> Self {
+        Arguments { argc, argv }
+    }
+
+    pub fn len(&self) -> usize {
+        self.argc
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.argc == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'static str> {
+        if index >= self.argc {
+            return None;
+        }
+
+        unsafe {
+            let argument = *self.argv.add(index);
+
+            let mut length = 0;
+            while *argument.add(length) != 0 {
+                length += 1;
+            }
+
+            core::str::from_utf8(core::slice::from_raw_parts(argument, length)).ok()
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        (0..self.argc).filter_map(|index| self.get(index))
+    }
+}
@@ -0,0 +1,86 @@
+/// Errors returned by System Calls. The kernel returns them in eax as negative values, using the
+/// usual Unix errno numbers, so any value from -1 to -4095 is an error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Errno {
+    NotPermitted,        // EPERM
+    NotFound,            // ENOENT
+    NoSuchProcess,       // ESRCH
+    ArgumentListTooLong, // E2BIG
+    InvalidExecutable,   // ENOEXEC
+    BadFileDescriptor,   // EBADF
+    NoChildProcess,      // ECHILD
+    TryAgain,            // EAGAIN
+    OutOfMemory,         // ENOMEM
+    BadAddress,          // EFAULT
+    AlreadyExists,       // EEXIST
+    NotADirectory,       // ENOTDIR
+    IsADirectory,        // EISDIR
+    InvalidArgument,     // EINVAL
+    TooManyOpenFiles,    // EMFILE
+    NoSpace,             // ENOSPC
+    TooManyLinks,        // EMLINK
+    BrokenPipe,          // EPIPE
+    NameTooLong,         // ENAMETOOLONG
+    InvalidSystemCall,   // ENOSYS
+    DirectoryNotEmpty,   // ENOTEMPTY
+    Unknown(usize),      // Code not known by this library
+}
+
+// Largest error code. Return values above usize::MAX - MAX_ERRNO are errors.
+pub const MAX_ERRNO: usize = 4095;
+
+impl Errno {
+    pub fn from_code(code: usize) -> Self {
+        match code {
+            1 => Errno::NotPermitted,
+            2 => Errno::NotFound,
+            3 => Errno::NoSuchProcess,
+            7 => Errno::ArgumentListTooLong,
+            8 => Errno::InvalidExecutable,
+            9 => Errno::BadFileDescriptor,
+            10 => Errno::NoChildProcess,
+            11 => Errno::TryAgain,
+            12 => Errno::OutOfMemory,
+            14 => Errno::BadAddress,
+            17 => Errno::AlreadyExists,
+            20 => Errno::NotADirectory,
+            21 => Errno::IsADirectory,
+            22 => Errno::InvalidArgument,
+            24 => Errno::TooManyOpenFiles,
+            28 => Errno::NoSpace,
+            31 => Errno::TooManyLinks,
+            32 => Errno::BrokenPipe,
+            36 => Errno::NameTooLong,
+            38 => Errno::InvalidSystemCall,
+            39 => Errno::DirectoryNotEmpty,
+            code => Errno::Unknown(code),
+        }
+    }
+
+    pub fn code(&self) -> usize {
+        match self {
+            Errno::NotPermitted => 1,
+            Errno::NotFound => 2,
+            Errno::NoSuchProcess => 3,
+            Errno::ArgumentListTooLong => 7,
+            Errno::InvalidExecutable => 8,
+            Errno::BadFileDescriptor => 9,
+            Errno::NoChildProcess => 10,
+            Errno::TryAgain => 11,
+            Errno::OutOfMemory => 12,
+            Errno::BadAddress => 14,
+            Errno::AlreadyExists => 17,
+            Errno::NotADirectory => 20,
+            Errno::IsADirectory => 21,
+            Errno::InvalidArgument => 22,
+            Errno::TooManyOpenFiles => 24,
+            Errno::NoSpace => 28,
+            Errno::TooManyLinks => 31,
+            Errno::BrokenPipe => 32,
+            Errno::NameTooLong => 36,
+            Errno::InvalidSystemCall => 38,
+            Errno::DirectoryNotEmpty => 39,
+            Errno::Unknown(code) => *code,
+        }
+    }
+}
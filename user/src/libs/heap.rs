@@ -22,8 +22,11 @@ impl<A> Locked<A> {
     }
 }
 
+const PAGE_SIZE: usize = 4096;
+
 pub struct LinkedListAllocator {
     head: StaticLinkedListNode,
+    heap_end: usize, // Current end of the memory of the process, once the heap has grown
 }
 
 #[global_allocator]
@@ -49,20 +52,23 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
             } else {
                 // No fragment fits the requirement, needs to ask the OS to increase
                 // available memory. OS can fail the allocation.
-                let current_end_address = super::system_call::sbrk(4096);
-                if current_end_address == -1 {
+                let Ok(current_end_address) = super::system_call::sbrk(PAGE_SIZE as isize) else {
                     return core::ptr::null_mut();
-                }
+                };
 
                 print_message("Allocating more memory");
-                allocator.add_free_node(current_end_address as usize, 4096);
+                allocator.heap_end = current_end_address + PAGE_SIZE;
+                allocator.free_region(current_end_address, PAGE_SIZE);
             }
         }
     }
 
     unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
         let (size, _) = LinkedListAllocator::size_align(_layout);
-        self.lock().add_free_node(_ptr as usize, size);
+        let mut allocator = self.lock();
+
+        allocator.free_region(_ptr as usize, size);
+        allocator.release_memory();
     }
 }
 
@@ -70,6 +76,7 @@ impl LinkedListAllocator {
     pub const fn new() -> Self {
         LinkedListAllocator {
             head: StaticLinkedListNode::new(0),
+            heap_end: 0,
         }
     }
 
@@ -94,6 +101,73 @@ impl LinkedListAllocator {
         self.head.next = Some(&mut *node_address);
     }
 
+    /// Add the region to the free nodes, merged with the free nodes right before and after it
+    pub unsafe fn free_region(&mut self, mut address: usize, mut size: usize) {
+        loop {
+            let (start, end) = (address, address + size);
+            let Some(node) =
+                self.take_node(|node| node.end_address() == start || node.address() == end)
+            else {
+                break;
+            };
+
+            address = core::cmp::min(address, node.address());
+            size += node.size;
+        }
+
+        self.add_free_node(address, size);
+    }
+
+    /// Give the whole pages of free memory at the end of the heap back to the OS. The part of the
+    /// free node before its first whole page stays in the heap.
+    pub unsafe fn release_memory(&mut self) {
+        let heap_end = self.heap_end;
+        let Some(node) = self.take_node(|node| node.end_address() == heap_end) else {
+            return;
+        };
+        let (address, size) = (node.address(), node.size);
+
+        let mut release_start = ROUND_UP!(address, PAGE_SIZE);
+        if release_start > address
+            && release_start - address < core::mem::size_of::<StaticLinkedListNode>()
+        {
+            release_start += PAGE_SIZE;
+        }
+
+        if release_start >= heap_end
+            || super::system_call::sbrk(-((heap_end - release_start) as isize)).is_err()
+        {
+            self.add_free_node(address, size);
+            return;
+        }
+
+        self.heap_end = release_start;
+        if release_start > address {
+            self.add_free_node(address, release_start - address);
+        }
+    }
+
+    /// Remove the first free node that matches the predicate from the list
+    fn take_node(
+        &mut self,
+        predicate: impl Fn(&StaticLinkedListNode) -> bool,
+    ) -> Option<&'static mut StaticLinkedListNode> {
+        let mut current_node = &mut self.head;
+
+        while let Some(ref mut node) = current_node.next {
+            if predicate(node) {
+                let next = node.next.take();
+                let ret = current_node.next.take();
+                current_node.next = next;
+                return ret;
+            } else {
+                current_node = current_node.next.as_mut().unwrap();
+            }
+        }
+
+        None
+    }
+
     pub fn allocate_free_node(
         node: &StaticLinkedListNode,
         size: usize,
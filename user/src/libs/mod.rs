@@ -1,5 +1,8 @@
+pub mod arguments;
+pub mod errno;
 pub mod heap;
 pub mod spin_mutex;
 pub mod static_linked_list;
 pub mod system_call;
+pub mod thread;
 pub mod utils;
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use super::errno::{Errno, MAX_ERRNO};
+
 #[repr(u32)]
 enum SystemCallTable {
     PrintTrapFrame = 0,
@@ -11,6 +13,114 @@ enum SystemCallTable {
     Wait = 7,
     Print = 8,
     Sbrk = 9,
+    Open = 10,
+    Read = 11,
+    Write = 12,
+    Close = 13,
+    Dup = 14,
+    Mkdir = 15,
+    Unlink = 16,
+    Link = 17,
+    Rename = 18,
+    Chdir = 19,
+    Pipe = 20,
+    FrameStats = 21,
+    Mmap = 22,
+    Munmap = 23,
+    ShmCreate = 24,
+    ShmAttach = 25,
+    ShmDetach = 26,
+    HeapStats = 27,
+    SetPriority = 28,
+    ProcessStats = 29,
+    Uptime = 30,
+    Clone = 31,
+    Join = 32,
+}
+
+// Open Flags
+pub const O_RDONLY: usize = 0x000;
+pub const O_WRONLY: usize = 0x001;
+pub const O_RDWR: usize = 0x002;
+pub const O_CREATE: usize = 0x200;
+pub const O_TRUNC: usize = 0x400;
+
+// Number of arguments a program can receive through exec
+pub const MAX_ARGUMENTS: usize = 32;
+
+// Wait Flags
+pub const WNOHANG: usize = 0x1;
+
+// Mmap Flags
+pub const PROT_WRITE: usize = 0x2;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+// Priorities, from the one that runs first to the one that runs last (MLFQ scheduler only)
+pub const HIGHEST_PRIORITY: usize = 0;
+pub const LOWEST_PRIORITY: usize = 3;
+
+// Standard File Descriptors
+pub const STDIN: usize = 0;
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+// Usage of the physical frames of the machine
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+    pub kernel: usize,
+    pub user: usize,
+}
+
+// Number of slab caches of the Kernel heap
+pub const SLAB_CACHES: usize = 7;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SlabStats {
+    pub size: usize,  // Size of the objects of the cache
+    pub slabs: usize, // Pages held by the cache
+    pub used: usize,  // Allocated objects
+    pub free: usize,  // Objects ready to be allocated
+}
+
+// Length of the names in ProcessStats. Longer names are truncated.
+pub const PROCESS_NAME_LENGTH: usize = 16;
+
+// Parent pid of a process without parent
+pub const NO_PARENT: usize = usize::MAX;
+
+// Names of the states of ProcessStats
+pub const PROCESS_STATES: [&str; 7] = [
+    "EMPTY", "EMBRYO", "RUNNING", "READY", "KILLED", "SLEEPING", "ZOMBIE",
+];
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProcessStats {
+    pub pid: usize,
+    pub parent_pid: usize,               // NO_PARENT if there is none
+    pub state: usize,                    // Index into PROCESS_STATES
+    pub name: [u8; PROCESS_NAME_LENGTH], // NUL padded
+    pub mem_size: usize,
+    pub user_ticks: usize,   // Timer ticks spent running the program
+    pub kernel_ticks: usize, // Timer ticks spent in the Kernel on behalf of the process
+    pub context_switches: usize,
+    pub start_tick: usize, // Uptime when the process was created
+}
+
+impl ProcessStats {
+    pub fn name(&self) -> &str {
+        let length = self.name.iter().position(|&byte| byte == 0);
+        let name = &self.name[..length.unwrap_or(PROCESS_NAME_LENGTH)];
+        core::str::from_utf8(name).unwrap_or("?")
+    }
+
+    pub fn state(&self) -> &'static str {
+        PROCESS_STATES.get(self.state).copied().unwrap_or("?")
+    }
 }
 
 struct SystemCall {
@@ -18,6 +128,7 @@ struct SystemCall {
     arg0: Option<usize>,
     arg1: Option<usize>,
     arg2: Option<usize>,
+    arg3: Option<usize>,
 }
 
 macro_rules! arg_setup {
@@ -36,67 +147,323 @@ impl SystemCall {
             arg0: None,
             arg1: None,
             arg2: None,
+            arg3: None,
         }
     }
 
     arg_setup!(arg0);
     arg_setup!(arg1);
     arg_setup!(arg2);
+    arg_setup!(arg3);
 
-    pub fn call(&self) -> usize {
+    /// Issues the System Call. Negative return values are turned into the matching Errno.
+    pub fn call(&self) -> Result<usize, Errno> {
         let arg0 = self.arg0.unwrap_or(0);
         let arg1 = self.arg1.unwrap_or(0);
         let arg2 = self.arg2.unwrap_or(0);
+        let arg3 = self.arg3.unwrap_or(0);
 
+        let out: usize;
         unsafe {
             core::arch::asm!(
               "int 64",
               in("edi") arg0,
               in("edx") arg1,
               in("ecx") arg2,
-              in("eax") self.number
+              in("ebx") arg3,
+              inlateout("eax") self.number => out
             )
         }
 
-        let mut out;
-        unsafe {
-            core::arch::asm!(
-              "mov {}, eax",
-              out(reg) out,
-            )
-        };
-
-        out
+        match out {
+            out if out > usize::MAX - MAX_ERRNO => Err(Errno::from_code(out.wrapping_neg())),
+            out => Ok(out),
+        }
     }
 }
 
-pub fn exec(path: &str) {
+/// Replaces the current program with the one at path, ending the other threads of the process.
+/// Only returns if exec fails.
+pub fn exec(path: &str, arguments: &[&str]) -> Errno {
     let str_address = path.as_ptr() as usize;
     let str_size = path.len();
 
-    SystemCall::new(SystemCallTable::Exec as usize)
+    if arguments.len() > MAX_ARGUMENTS {
+        return Errno::ArgumentListTooLong;
+    }
+
+    // Arguments are passed as (address, length) pairs
+    let mut argv = [[0_usize; 2]; MAX_ARGUMENTS];
+    for (index, argument) in arguments.iter().enumerate() {
+        argv[index] = [argument.as_ptr() as usize, argument.len()];
+    }
+
+    let output = SystemCall::new(SystemCallTable::Exec as usize)
         .arg0(str_address)
         .arg1(str_size)
+        .arg2(argv.as_ptr() as usize)
+        .arg3(arguments.len())
         .call();
+
+    match output {
+        Err(error) => error,
+        Ok(_) => unreachable!(),
+    }
 }
 
-pub extern "C" fn fork() -> usize {
+/// Creates a copy of the current process. Returns the pid of the child to the parent, and 0 to
+/// the child. Only the calling thread is copied, so the child runs without threads.
+pub fn fork() -> Result<usize, Errno> {
     SystemCall::new(SystemCallTable::Fork as usize).call()
 }
 
-pub extern "C" fn wait() {
-    SystemCall::new(SystemCallTable::Wait as usize).call();
+/// Waits for any child to exit. Returns its pid, or NoChildProcess if there are no children.
+pub fn wait(status: &mut i32) -> Result<usize, Errno> {
+    waitpid(-1, status, 0)
+}
+
+/// Waits for the child with the given pid (or any child, if pid is -1) to exit, storing its exit
+/// code in status. With WNOHANG, returns 0 if the child is still running.
+pub fn waitpid(pid: isize, status: &mut i32, flags: usize) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Wait as usize)
+        .arg0(pid as usize)
+        .arg1(status as *mut i32 as usize)
+        .arg2(flags)
+        .call()
+}
+
+/// Creates a thread of the current process, which shares its memory and open files, and calls
+/// entry with argument on the stack that ends at stack. Returns the pid of the thread. See the
+/// thread module for a safe interface.
+pub fn clone(
+    entry: extern "C" fn(usize) -> !,
+    stack: usize,
+    argument: usize,
+) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Clone as usize)
+        .arg0(entry as usize)
+        .arg1(stack)
+        .arg2(argument)
+        .call()
+}
+
+/// Waits for the thread tid of the current process to exit, storing its exit code in status.
+/// Returns the pid of the thread.
+pub fn join(tid: usize, status: &mut i32) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Join as usize)
+        .arg0(tid)
+        .arg1(status as *mut i32 as usize)
+        .call()
+}
+
+pub fn exit(code: i32) -> ! {
+    let _ = SystemCall::new(SystemCallTable::Exit as usize)
+        .arg0(code as usize)
+        .call();
+
+    unreachable!()
 }
 
 pub fn print_message(message: &str) {
-    SystemCall::new(SystemCallTable::Print as usize)
+    let _ = SystemCall::new(SystemCallTable::Print as usize)
         .arg0(message.as_ptr() as usize)
         .arg1(message.len())
         .call();
 }
 
-pub fn sbrk(amount: usize) -> isize {
+/// Grows (or shrinks, if amount is negative) the memory of the process by amount bytes. Returns
+/// the previous end of the memory.
+pub fn sbrk(amount: isize) -> Result<usize, Errno> {
     SystemCall::new(SystemCallTable::Sbrk as usize)
-        .arg0(amount)
-        .call() as isize
+        .arg0(amount as usize)
+        .call()
+}
+
+pub fn open(path: &str, flags: usize) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Open as usize)
+        .arg0(path.as_ptr() as usize)
+        .arg1(path.len())
+        .arg2(flags)
+        .call()
+}
+
+pub fn read(descriptor: usize, buffer: &mut [u8]) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Read as usize)
+        .arg0(descriptor)
+        .arg1(buffer.as_mut_ptr() as usize)
+        .arg2(buffer.len())
+        .call()
+}
+
+pub fn write(descriptor: usize, buffer: &[u8]) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Write as usize)
+        .arg0(descriptor)
+        .arg1(buffer.as_ptr() as usize)
+        .arg2(buffer.len())
+        .call()
+}
+
+pub fn close(descriptor: usize) -> Result<(), Errno> {
+    SystemCall::new(SystemCallTable::Close as usize)
+        .arg0(descriptor)
+        .call()
+        .map(|_| ())
+}
+
+pub fn dup(descriptor: usize) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Dup as usize)
+        .arg0(descriptor)
+        .call()
+}
+
+pub fn mkdir(path: &str) -> Result<(), Errno> {
+    SystemCall::new(SystemCallTable::Mkdir as usize)
+        .arg0(path.as_ptr() as usize)
+        .arg1(path.len())
+        .call()
+        .map(|_| ())
+}
+
+pub fn unlink(path: &str) -> Result<(), Errno> {
+    SystemCall::new(SystemCallTable::Unlink as usize)
+        .arg0(path.as_ptr() as usize)
+        .arg1(path.len())
+        .call()
+        .map(|_| ())
+}
+
+pub fn link(old_path: &str, new_path: &str) -> Result<(), Errno> {
+    SystemCall::new(SystemCallTable::Link as usize)
+        .arg0(old_path.as_ptr() as usize)
+        .arg1(old_path.len())
+        .arg2(new_path.as_ptr() as usize)
+        .arg3(new_path.len())
+        .call()
+        .map(|_| ())
+}
+
+pub fn rename(old_path: &str, new_path: &str) -> Result<(), Errno> {
+    SystemCall::new(SystemCallTable::Rename as usize)
+        .arg0(old_path.as_ptr() as usize)
+        .arg1(old_path.len())
+        .arg2(new_path.as_ptr() as usize)
+        .arg3(new_path.len())
+        .call()
+        .map(|_| ())
+}
+
+pub fn chdir(path: &str) -> Result<(), Errno> {
+    SystemCall::new(SystemCallTable::Chdir as usize)
+        .arg0(path.as_ptr() as usize)
+        .arg1(path.len())
+        .call()
+        .map(|_| ())
+}
+
+/// Creates a pipe. Returns its read and write descriptors, in that order. Bytes written into the
+/// write descriptor are read from the read descriptor, which hits the end of the file once every
+/// write descriptor is closed.
+pub fn pipe() -> Result<(usize, usize), Errno> {
+    let mut descriptors = [0_usize; 2];
+
+    SystemCall::new(SystemCallTable::Pipe as usize)
+        .arg0(descriptors.as_mut_ptr() as usize)
+        .call()?;
+
+    Ok((descriptors[0], descriptors[1]))
+}
+
+/// Reads how many frames of physical memory exist, how many are free, and how many are used by
+/// the Kernel and by user processes.
+pub fn frame_stats() -> Result<FrameStats, Errno> {
+    let mut stats = FrameStats::default();
+
+    SystemCall::new(SystemCallTable::FrameStats as usize)
+        .arg0(&mut stats as *mut FrameStats as usize)
+        .call()?;
+
+    Ok(stats)
+}
+
+/// Maps length bytes into the memory of the process. Returns the address of the mapping. With
+/// MAP_ANONYMOUS, the memory starts zeroed (and is writable with PROT_WRITE) and descriptor is
+/// ignored. Otherwise, the memory holds the content of the file from offset (page aligned) and
+/// is read only. Pages are only loaded once they are accessed.
+pub fn mmap(length: usize, flags: usize, descriptor: usize, offset: usize) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Mmap as usize)
+        .arg0(length)
+        .arg1(flags)
+        .arg2(descriptor)
+        .arg3(offset)
+        .call()
+}
+
+/// Unmaps the pages of the mappings from address (page aligned) to address + length
+pub fn munmap(address: usize, length: usize) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::Munmap as usize)
+        .arg0(address)
+        .arg1(length)
+        .call()
+}
+
+/// Creates a zeroed shared memory segment of size bytes under key, and attaches it. Returns the
+/// address of the segment. Children created by fork have the segment attached as well.
+pub fn shm_create(key: usize, size: usize) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::ShmCreate as usize)
+        .arg0(key)
+        .arg1(size)
+        .call()
+}
+
+/// Attaches the shared memory segment created under key. Returns the address of the segment.
+pub fn shm_attach(key: usize) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::ShmAttach as usize)
+        .arg0(key)
+        .call()
+}
+
+/// Detaches the shared memory segment at address. The segment is destroyed once no process has
+/// it attached.
+pub fn shm_detach(address: usize) -> Result<(), Errno> {
+    SystemCall::new(SystemCallTable::ShmDetach as usize)
+        .arg0(address)
+        .call()
+        .map(|_| ())
+}
+
+/// Reads how each slab cache of the Kernel heap is being used, from the cache of the smallest
+/// objects to the one of the biggest.
+pub fn heap_stats() -> Result<[SlabStats; SLAB_CACHES], Errno> {
+    let mut stats = [SlabStats::default(); SLAB_CACHES];
+
+    SystemCall::new(SystemCallTable::HeapStats as usize)
+        .arg0(stats.as_mut_ptr() as usize)
+        .call()?;
+
+    Ok(stats)
+}
+
+/// Sets the priority of the process pid, which must be the calling process or one of its children.
+/// Returns the previous priority.
+pub fn set_priority(pid: usize, priority: usize) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::SetPriority as usize)
+        .arg0(pid)
+        .arg1(priority)
+        .call()
+}
+
+/// Fills stats with a snapshot of the processes of the system, in the order of the process list.
+/// Returns the number of processes, which is bigger than the length of stats if some did not fit.
+pub fn process_stats(stats: &mut [ProcessStats]) -> Result<usize, Errno> {
+    SystemCall::new(SystemCallTable::ProcessStats as usize)
+        .arg0(stats.as_mut_ptr() as usize)
+        .arg1(stats.len())
+        .call()
+}
+
+/// Returns the number of timer ticks since the Kernel started
+pub fn uptime() -> usize {
+    SystemCall::new(SystemCallTable::Uptime as usize)
+        .call()
+        .unwrap_or(0)
 }
@@ -0,0 +1,77 @@
+/// Threads run a closure in the current process, sharing its memory and open files, on a stack of
+/// their own taken from the heap. A thread ends once its closure returns, with the returned value
+/// as its exit code. Calling exit from a thread ends that thread only. Fork from a thread copies
+/// that thread only, while exec ends every other thread of the process.
+use alloc::{
+    alloc::{alloc, dealloc, Layout},
+    boxed::Box,
+};
+
+use super::{
+    errno::Errno,
+    system_call::{self, exit},
+};
+
+pub const STACK_SIZE: usize = 16 * 4096;
+const STACK_ALIGNMENT: usize = 16;
+
+type ThreadFunction = Box<dyn FnOnce() -> i32 + Send>;
+
+/// Thread that can be joined. The stack of the thread is only freed by join, so it stays allocated
+/// if the handle is dropped while the thread is still running.
+pub struct JoinHandle {
+    tid: usize,
+    stack: *mut u8,
+}
+
+impl JoinHandle {
+    /// Pid of the thread
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    /// Waits for the thread to end, and returns its exit code
+    pub fn join(self) -> Result<i32, Errno> {
+        let mut status = 0;
+        system_call::join(self.tid, &mut status)?;
+
+        unsafe { dealloc(self.stack, stack_layout()) };
+        Ok(status)
+    }
+}
+
+/// Runs function in a new thread of the current process
+pub fn spawn<F>(function: F) -> Result<JoinHandle, Errno>
+where
+    F: FnOnce() -> i32 + Send + 'static,
+{
+    let stack = unsafe { alloc(stack_layout()) };
+    if stack.is_null() {
+        return Err(Errno::OutOfMemory);
+    }
+
+    let function = Box::into_raw(Box::new(Box::new(function) as ThreadFunction));
+    let stack_top = stack as usize + STACK_SIZE;
+
+    match system_call::clone(thread_start, stack_top, function as usize) {
+        Ok(tid) => Ok(JoinHandle { tid, stack }),
+        Err(error) => {
+            unsafe {
+                drop(Box::from_raw(function));
+                dealloc(stack, stack_layout());
+            }
+
+            Err(error)
+        }
+    }
+}
+
+fn stack_layout() -> Layout {
+    Layout::from_size_align(STACK_SIZE, STACK_ALIGNMENT).unwrap()
+}
+
+/// First function of every thread, which receives the closure to run
+extern "C" fn thread_start(function: usize) -> ! {
+    let function = unsafe { Box::from_raw(function as *mut ThreadFunction) };
+    exit(function());
+}